@@ -46,7 +46,7 @@ fn main() {
             2.0,
             &mut UpdateFlagSpec::Prob(0.01),
         );
-        //println!("{}", ensemble[0].deterministic_values.borrow()[0]);
+        //println!("{}", ensemble[0].deterministic_values[0]);
         println!("{}", ensemble[0][0]);
     }
 }
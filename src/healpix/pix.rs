@@ -16,6 +16,7 @@ where
     let npix = 12_usize * nside * nside;
     let fact2 = T::from(4.0).unwrap() / T::from(npix).unwrap();
     if pix < ncap {
+        #[allow(clippy::manual_div_ceil)]
         let iring = (1 + isqrt(1 + 2 * pix)) / 2;
         let iphi = (pix + 1) - 2 * iring * (iring - 1);
 
@@ -39,6 +40,7 @@ where
         (z, phi)
     } else {
         let ip = npix - pix;
+        #[allow(clippy::manual_div_ceil)]
         let iring = (1 + isqrt(2 * ip - 1)) / 2;
         let iphi = 4 * iring + 1 - (ip - 2 * iring * (iring - 1));
 
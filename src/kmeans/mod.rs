@@ -94,15 +94,12 @@ where
 {
     let mut p = classify(points, centroids);
     for _i in 0..niter {
-        p = kmeans_iter(p).unwrap();
+        update_centroids(&p, centroids);
+        p = classify(points, centroids);
     }
+    update_centroids(&p, centroids);
 
-    let c: Vec<_> = calc_centroids(&p)
-        .into_iter()
-        .map(std::option::Option::unwrap)
-        .collect();
-
-    let labels: Vec<_> = points
+    points
         .iter()
         .map(|x| {
             centroids
@@ -121,10 +118,58 @@ where
                 .unwrap()
                 .0
         })
-        .collect();
+        .collect()
+}
 
-    let _ = c.into_iter().zip(centroids.iter_mut()).map(|(c1, c2)| {
-        *c2 = c1;
-    });
-    labels
+///Move every centroid to the mean of its cluster, centroids of empty clusters are kept unchanged
+fn update_centroids<Scalar, T>(clusters: &[Vec<T>], centroids: &mut [T])
+where
+    T: PDInnerProdSpace<Scalar> + Clone + std::fmt::Debug,
+    for<'a> &'a T: Add<Output = T>,
+    for<'a> &'a T: Sub<Output = T>,
+    for<'a> &'a T: Mul<Scalar, Output = T>,
+    Scalar: Float + std::fmt::Debug,
+{
+    for (c1, c2) in calc_centroids(clusters)
+        .into_iter()
+        .zip(centroids.iter_mut())
+    {
+        if let Some(c1) = c1 {
+            *c2 = c1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_space::type_wrapper::LsVec;
+
+    #[test]
+    fn kmeans2_test() {
+        //two well separated clusters of 25 points around (0, 0) and (10, 5)
+        let centers = [(0.0, 0.0), (10.0, 5.0)];
+        let points: Vec<_> = centers
+            .iter()
+            .flat_map(|&(x, y)| {
+                (0..25).map(move |i| {
+                    let (dx, dy) = ((i % 5) as f64 - 2.0, (i / 5) as f64 - 2.0);
+                    LsVec(vec![x + 0.2 * dx, y + 0.2 * dy])
+                })
+            })
+            .collect();
+        //both starting centroids lie at one side of the first cluster
+        let mut centroids = vec![LsVec(vec![-1.0, -1.0]), LsVec(vec![-0.5, -1.0])];
+        let labels = kmeans2(&points, &mut centroids, 10);
+
+        for (c, &(x, y)) in centroids.iter().zip(centers.iter()) {
+            assert!(
+                (c[0] - x).abs() < 1e-10 && (c[1] - y).abs() < 1e-10,
+                "{:?}",
+                c
+            );
+        }
+        assert!(labels[..25].iter().all(|&l| l == 0));
+        assert!(labels[25..].iter().all(|&l| l == 1));
+    }
 }
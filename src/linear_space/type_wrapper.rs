@@ -57,6 +57,7 @@ where
     }
 }
 
+#[allow(clippy::needless_lifetimes)]
 impl<'a, T, V> Mul<T> for &'a LsVec<T, V>
 where
     T: Float,
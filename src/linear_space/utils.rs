@@ -39,3 +39,132 @@ where
         }
     }
 }
+
+///Mean and covariance accumulated one sample at a time (Welford's algorithm)
+#[derive(Clone, Debug)]
+pub struct RunningMoments<T, V>
+where
+    T: Float,
+{
+    n: usize,
+    mean: Option<V>,
+    m2: Vec<Vec<T>>,
+}
+
+impl<T, V> RunningMoments<T, V>
+where
+    T: Float,
+    V: IndexableLinearSpace<T> + Clone,
+    for<'b> &'b V: std::ops::Add<Output = V>,
+    for<'b> &'b V: std::ops::Sub<Output = V>,
+    for<'b> &'b V: std::ops::Mul<T, Output = V>,
+{
+    pub fn new() -> Self {
+        RunningMoments {
+            n: 0,
+            mean: None,
+            m2: Vec::new(),
+        }
+    }
+
+    ///Initialize from a batch of samples with `mean` and `cov`
+    pub fn from_samples(x: &[V]) -> Self {
+        let xm = mean(x);
+        let ndim = xm.dimension();
+        let n = T::from(x.len()).unwrap();
+        let mut m2 = vec![vec![T::zero(); ndim]; ndim];
+        cov(x, &mut |i, j, c| m2[i][j] = c * n);
+        RunningMoments {
+            n: x.len(),
+            mean: Some(xm),
+            m2,
+        }
+    }
+
+    pub fn push(&mut self, x: &V) {
+        self.n += 1;
+        match self.mean {
+            None => {
+                let ndim = x.dimension();
+                self.mean = Some(x.clone());
+                self.m2 = vec![vec![T::zero(); ndim]; ndim];
+            }
+            Some(ref mut xm) => {
+                let d1 = x - xm;
+                *xm = &*xm + &(&d1 * (T::one() / T::from(self.n).unwrap()));
+                let d2 = x - xm;
+                for (i, row) in self.m2.iter_mut().enumerate() {
+                    for (j, m) in row.iter_mut().enumerate() {
+                        *m = *m + d1[i] * d2[j];
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.n
+    }
+
+    pub fn mean(&self) -> Option<&V> {
+        self.mean.as_ref()
+    }
+
+    ///The covariance normalized by the number of samples, as `cov` does
+    pub fn cov(&self) -> Vec<Vec<T>> {
+        let n = T::from(self.n.max(1)).unwrap();
+        self.m2
+            .iter()
+            .map(|row| row.iter().map(|&m| m / n).collect())
+            .collect()
+    }
+}
+
+impl<T, V> Default for RunningMoments<T, V>
+where
+    T: Float,
+    V: IndexableLinearSpace<T> + Clone,
+    for<'b> &'b V: std::ops::Add<Output = V>,
+    for<'b> &'b V: std::ops::Sub<Output = V>,
+    for<'b> &'b V: std::ops::Mul<T, Output = V>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///Lower triangular Cholesky factor of a symmetric matrix, `None` if it is not positive definite
+pub fn cholesky<T>(a: &[Vec<T>]) -> Option<Vec<Vec<T>>>
+where
+    T: Float,
+{
+    let n = a.len();
+    let mut l = vec![vec![T::zero(); n]; n];
+    for i in 0..n {
+        for j in 0..=i {
+            let s = (0..j).fold(a[i][j], |s, k| s - l[i][k] * l[j][k]);
+            if i == j {
+                if s.is_nan() || s <= T::zero() {
+                    return None;
+                }
+                l[i][i] = s.sqrt();
+            } else {
+                l[i][j] = s / l[j][j];
+            }
+        }
+    }
+    Some(l)
+}
+
+///Solve `l*y=x` for lower triangular `l`
+pub fn forward_solve<T>(l: &[Vec<T>], x: &[T]) -> Vec<T>
+where
+    T: Float,
+{
+    let mut y = Vec::with_capacity(x.len());
+    for i in 0..x.len() {
+        let s = (0..i).fold(x[i], |s, k| s - l[i][k] * y[k]);
+        y.push(s / l[i][i]);
+    }
+    y
+}
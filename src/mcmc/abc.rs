@@ -0,0 +1,426 @@
+#![allow(clippy::too_many_arguments)]
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use num::traits::{float::Float, NumCast};
+
+use rand::{
+    distributions::{uniform::SampleUniform, Distribution, Standard, Uniform},
+    rngs::StdRng,
+    Rng, SeedableRng,
+};
+use rand_distr::StandardNormal;
+
+use std::ops::{Add, Mul, Sub};
+
+use crate::linear_space::utils::{cholesky, forward_solve};
+use crate::linear_space::IndexableLinearSpace;
+
+///How the tolerance of an ABC rejection step is chosen
+#[derive(Clone, Copy, Debug)]
+pub enum AbcTolerance<T> {
+    ///Accept the simulations whose distance is not larger than the given value
+    Epsilon(T),
+    ///Accept the given fraction of the simulations with the smallest distances
+    Quantile(T),
+}
+
+pub struct AbcResult<T, V> {
+    pub particles: Vec<V>,
+    pub distances: Vec<T>,
+    ///Normalized importance weights of `particles`
+    pub weights: Vec<T>,
+    ///Tolerance of every generation, only one for ABC rejection
+    pub epsilons: Vec<T>,
+    ///Total number of simulator calls
+    pub nsim: usize,
+}
+
+pub struct AbcSmcParams<T> {
+    pub nparticles: usize,
+    ///The tolerance of a generation is this quantile of the distances of the previous one
+    pub quantile: T,
+    ///Stop when the tolerance is below this value
+    pub min_epsilon: T,
+    ///Stop when the acceptance rate of a generation drops below this value
+    pub min_accept_rate: T,
+    pub max_generations: usize,
+    ///Number of simulations run in parallel at a time
+    pub batch_size: usize,
+}
+
+impl<T> AbcSmcParams<T>
+where
+    T: Float,
+{
+    pub fn new(nparticles: usize) -> AbcSmcParams<T> {
+        AbcSmcParams {
+            nparticles,
+            quantile: T::from(0.5).unwrap(),
+            min_epsilon: T::zero(),
+            min_accept_rate: T::from(0.01).unwrap(),
+            max_generations: 20,
+            batch_size: nparticles,
+        }
+    }
+}
+
+///Simulate every parameter with its own generator seeded from `rng` and return the distances
+fn simulate_distances<T, U, V, X, S, Sim, Sum, Dist>(
+    params: &[V],
+    simulate: &Sim,
+    summary: &Sum,
+    distance: &Dist,
+    observed: &S,
+    rng: &mut U,
+) -> Vec<T>
+where
+    T: Float + Send,
+    U: Rng,
+    V: Sync,
+    S: Sync,
+    Sim: Fn(&V, &mut StdRng) -> X + Sync + ?Sized,
+    Sum: Fn(&X) -> S + Sync + ?Sized,
+    Dist: Fn(&S, &S) -> T + Sync + ?Sized,
+{
+    let seeds: Vec<u64> = params.iter().map(|_| rng.gen()).collect();
+    (0..params.len())
+        .into_par_iter()
+        .map(|i| {
+            let mut r = StdRng::seed_from_u64(seeds[i]);
+            let x = simulate(&params[i], &mut r);
+            let d = distance(&summary(&x), observed);
+            if d.is_nan() {
+                T::infinity()
+            } else {
+                d
+            }
+        })
+        .collect()
+}
+
+fn quantile<T>(x: &[T], q: T) -> T
+where
+    T: Float,
+{
+    let mut x = x.to_vec();
+    x.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let i = (q * T::from(x.len()).unwrap())
+        .ceil()
+        .to_usize()
+        .unwrap()
+        .clamp(1, x.len())
+        - 1;
+    x[i]
+}
+
+///ABC rejection sampling with `nsim` draws from the prior.
+///`simulate` draws a data set for a parameter, which is compared with `observed_data` through
+///`distance` between their `summary` statistics. The simulations run in parallel.
+pub fn rejection<T, U, V, X, S, P, Sim, Sum, Dist>(
+    prior_sample: &P,
+    simulate: &Sim,
+    summary: &Sum,
+    distance: &Dist,
+    observed_data: &X,
+    nsim: usize,
+    tolerance: AbcTolerance<T>,
+    rng: &mut U,
+) -> AbcResult<T, V>
+where
+    T: Float + NumCast + SampleUniform + Send + Sync + std::fmt::Debug,
+    U: Rng,
+    V: Clone + Sync + Send,
+    S: Sync,
+    P: Fn(&mut U) -> V + ?Sized,
+    Sim: Fn(&V, &mut StdRng) -> X + Sync + ?Sized,
+    Sum: Fn(&X) -> S + Sync + ?Sized,
+    Dist: Fn(&S, &S) -> T + Sync + ?Sized,
+{
+    let observed = summary(observed_data);
+    let params: Vec<V> = (0..nsim).map(|_| prior_sample(rng)).collect();
+    let dist = simulate_distances(&params, simulate, summary, distance, &observed, rng);
+    let epsilon = match tolerance {
+        AbcTolerance::Epsilon(e) => e,
+        AbcTolerance::Quantile(q) => quantile(&dist, q),
+    };
+    let (particles, distances): (Vec<V>, Vec<T>) = params
+        .into_iter()
+        .zip(dist)
+        .filter(|&(_, d)| d <= epsilon)
+        .unzip();
+    let n = particles.len();
+    AbcResult {
+        particles,
+        distances,
+        weights: vec![T::one() / T::from(n.max(1)).unwrap(); n],
+        epsilons: vec![epsilon],
+        nsim,
+    }
+}
+
+///Weighted covariance of the particles
+fn weighted_cov<T, V>(particles: &[V], weights: &[T]) -> Vec<Vec<T>>
+where
+    T: Float,
+    V: IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+{
+    let ndim = particles[0].dimension();
+    let m: Vec<T> = (0..ndim)
+        .map(|i| {
+            particles
+                .iter()
+                .zip(weights.iter())
+                .fold(T::zero(), |s, (x, &w)| s + x[i] * w)
+        })
+        .collect();
+    let mut c = vec![vec![T::zero(); ndim]; ndim];
+    for (x, &w) in particles.iter().zip(weights.iter()) {
+        for i in 0..ndim {
+            for j in 0..ndim {
+                c[i][j] = c[i][j] + w * (x[i] - m[i]) * (x[j] - m[j]);
+            }
+        }
+    }
+    c
+}
+
+///Population Monte Carlo ABC (Beaumont et al. 2009).
+///The first generation is drawn from the prior without any tolerance.
+///Every following generation resamples the previous one according to the importance weights,
+///perturbs the particles with a Gaussian kernel of twice their weighted covariance and keeps those
+///within the tolerance, which is the `quantile` of the distances of the previous generation.
+///`flogprior` is the log prior density (up to a constant), see `rejection` for the other arguments.
+pub fn smc<T, U, V, X, S, P, LP, Sim, Sum, Dist>(
+    prior_sample: &P,
+    flogprior: &LP,
+    simulate: &Sim,
+    summary: &Sum,
+    distance: &Dist,
+    observed_data: &X,
+    params: &AbcSmcParams<T>,
+    rng: &mut U,
+) -> AbcResult<T, V>
+where
+    T: Float + NumCast + SampleUniform + Send + Sync + std::fmt::Debug,
+    Standard: Distribution<T>,
+    StandardNormal: Distribution<T>,
+    U: Rng,
+    V: Clone + IndexableLinearSpace<T> + Sync + Send,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    S: Sync,
+    P: Fn(&mut U) -> V + ?Sized,
+    LP: Fn(&V) -> T + ?Sized,
+    Sim: Fn(&V, &mut StdRng) -> X + Sync + ?Sized,
+    Sum: Fn(&X) -> S + Sync + ?Sized,
+    Dist: Fn(&S, &S) -> T + Sync + ?Sized,
+{
+    let n = params.nparticles;
+    assert!(n > 1);
+    let observed = summary(observed_data);
+    let two = T::one() + T::one();
+
+    let mut result = rejection(
+        prior_sample,
+        simulate,
+        summary,
+        distance,
+        observed_data,
+        n,
+        AbcTolerance::Epsilon(T::infinity()),
+        rng,
+    );
+    result.epsilons.clear();
+
+    for _ in 0..params.max_generations {
+        let epsilon = quantile(&result.distances, params.quantile);
+        if epsilon < params.min_epsilon {
+            break;
+        }
+
+        let ndim = result.particles[0].dimension();
+        let mut c = weighted_cov(&result.particles, &result.weights);
+        for (i, row) in c.iter_mut().enumerate() {
+            for x in row.iter_mut() {
+                *x = *x * two;
+            }
+            row[i] = row[i] + T::epsilon().sqrt() * (T::one() + row[i].abs());
+        }
+        let chol = cholesky(&c).expect("covariance of the particles is not positive definite");
+        let cum: Vec<T> = result
+            .weights
+            .iter()
+            .scan(T::zero(), |s, &w| {
+                *s = *s + w;
+                Some(*s)
+            })
+            .collect();
+        let total = *cum.last().unwrap();
+
+        let mut particles = Vec::with_capacity(n);
+        let mut distances = Vec::with_capacity(n);
+        let mut nsim = 0;
+        while particles.len() < n {
+            let proposed: Vec<V> = (0..params.batch_size.max(1))
+                .map(|_| loop {
+                    let r = rng.sample(Uniform::new(T::zero(), total));
+                    let k = cum.iter().position(|&c| r < c).unwrap_or(n - 1);
+                    let u: Vec<T> = (0..ndim).map(|_| rng.sample(StandardNormal)).collect();
+                    let mut x = result.particles[k].clone();
+                    for i in 0..ndim {
+                        x[i] = x[i] + (0..=i).fold(T::zero(), |s, j| s + chol[i][j] * u[j]);
+                    }
+                    if flogprior(&x) > T::neg_infinity() {
+                        break x;
+                    }
+                })
+                .collect();
+            let dist = simulate_distances(&proposed, simulate, summary, distance, &observed, rng);
+            nsim += proposed.len();
+            for (x, d) in proposed.into_iter().zip(dist) {
+                if d <= epsilon && particles.len() < n {
+                    particles.push(x);
+                    distances.push(d);
+                }
+            }
+            if T::from(particles.len()).unwrap() < params.min_accept_rate * T::from(nsim).unwrap()
+                && nsim >= n
+            {
+                break;
+            }
+        }
+        result.nsim += nsim;
+        if particles.len() < n {
+            break;
+        }
+
+        let log_w: Vec<T> = particles
+            .iter()
+            .map(|x: &V| {
+                let denom = result.particles.iter().zip(result.weights.iter()).fold(
+                    T::zero(),
+                    |s, (y, &w)| {
+                        let d: Vec<T> = (0..ndim).map(|i| x[i] - y[i]).collect();
+                        let z = forward_solve(&chol, &d);
+                        let q = z.iter().fold(T::zero(), |s, &z| s + z * z);
+                        s + w * (-q / two).exp()
+                    },
+                );
+                flogprior(x) - denom.ln()
+            })
+            .collect();
+        let m = log_w.iter().fold(T::neg_infinity(), |a, &b| a.max(b));
+        let w: Vec<T> = log_w.iter().map(|&l| (l - m).exp()).collect();
+        let sw = w.iter().fold(T::zero(), |a, &b| a + b);
+
+        result.particles = particles;
+        result.distances = distances;
+        result.weights = w.into_iter().map(|w| w / sw).collect();
+        result.epsilons.push(epsilon);
+
+        if T::from(n).unwrap() < params.min_accept_rate * T::from(nsim).unwrap() {
+            break;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_space::type_wrapper::LsVec;
+
+    type Vf = LsVec<f64, Vec<f64>>;
+
+    //data: 50 draws from N(mu, 1), summary: sample mean, prior: N(0, 10^2)
+    //the posterior is approximately N(mean of data, 1/50)
+    fn setup() -> (Vec<f64>, f64) {
+        let mut rng = StdRng::seed_from_u64(1);
+        let data: Vec<f64> = (0..50)
+            .map(|_| 1.5 + rng.sample::<f64, _>(StandardNormal))
+            .collect();
+        let m = data.iter().sum::<f64>() / 50.0;
+        (data, m)
+    }
+
+    fn simulate(p: &Vf, rng: &mut StdRng) -> Vec<f64> {
+        (0..50)
+            .map(|_| p[0] + rng.sample::<f64, _>(StandardNormal))
+            .collect()
+    }
+
+    fn summary(x: &[f64]) -> f64 {
+        x.iter().sum::<f64>() / x.len() as f64
+    }
+
+    fn prior_sample(rng: &mut StdRng) -> Vf {
+        LsVec(vec![10.0 * rng.sample::<f64, _>(StandardNormal)])
+    }
+
+    fn moments(r: &AbcResult<f64, Vf>) -> (f64, f64) {
+        let m = r
+            .particles
+            .iter()
+            .zip(r.weights.iter())
+            .map(|(x, w)| x[0] * w)
+            .sum::<f64>();
+        let v = r
+            .particles
+            .iter()
+            .zip(r.weights.iter())
+            .map(|(x, w)| (x[0] - m).powi(2) * w)
+            .sum::<f64>();
+        (m, v)
+    }
+
+    #[test]
+    fn rejection_test() {
+        let (data, xm) = setup();
+        let mut rng = StdRng::seed_from_u64(12345);
+        let r = rejection(
+            &prior_sample,
+            &simulate,
+            &|x: &Vec<f64>| summary(x),
+            &|a: &f64, b: &f64| (a - b).abs(),
+            &data,
+            100000,
+            AbcTolerance::Quantile(0.01),
+            &mut rng,
+        );
+        assert_eq!(r.particles.len(), 1000);
+        let (m, v) = moments(&r);
+        let eps = r.epsilons[0];
+        assert!((m - xm).abs() < 0.05, "{} {}", m, xm);
+        let v_expected = 1.0 / 50.0 + eps * eps / 3.0;
+        assert!((v / v_expected - 1.0).abs() < 0.3, "{} {}", v, v_expected);
+    }
+
+    #[test]
+    fn smc_test() {
+        let (data, xm) = setup();
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut params = AbcSmcParams::new(1000);
+        params.min_epsilon = 0.01;
+        let r = smc(
+            &prior_sample,
+            &|p: &Vf| -p[0] * p[0] / 200.0,
+            &simulate,
+            &|x: &Vec<f64>| summary(x),
+            &|a: &f64, b: &f64| (a - b).abs(),
+            &data,
+            &params,
+            &mut rng,
+        );
+        assert!(r.epsilons.windows(2).all(|e| e[1] <= e[0]));
+        let eps = *r.epsilons.last().unwrap();
+        assert!(eps < 0.05, "{:?}", r.epsilons);
+        assert!((r.weights.iter().sum::<f64>() - 1.0).abs() < 1e-10);
+        let (m, v) = moments(&r);
+        assert!((m - xm).abs() < 0.03, "{} {}", m, xm);
+        assert!((v * 50.0 - 1.0).abs() < 0.3, "{}", v);
+    }
+}
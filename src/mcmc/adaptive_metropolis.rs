@@ -0,0 +1,256 @@
+use num::traits::{float::Float, NumCast};
+use rand::{
+    distributions::{uniform::SampleUniform, Distribution, Standard, Uniform},
+    Rng,
+};
+use rand_distr::StandardNormal;
+use std::ops::{Add, Mul, Sub};
+
+use crate::linear_space::utils::{cholesky, RunningMoments};
+use crate::linear_space::IndexableLinearSpace;
+
+///How the proposal covariance is learned
+#[derive(Clone, Copy, Debug)]
+pub enum AdaptScheme<T>
+where
+    T: Float,
+{
+    ///Haario et al. (2001): the proposal covariance is `2.38^2/d*(C+epsilon*I)`,
+    ///where `C` is the covariance of the chain so far, used after `t0` iterations
+    Haario { epsilon: T, t0: usize },
+    ///Vihola (2012) robust adaptive Metropolis: the proposal shape is tuned so that the
+    ///acceptance rate approaches `target_accept`, with step sizes `n^-gamma`
+    Robust { target_accept: T, gamma: T },
+}
+
+pub struct AmState<T, V>
+where
+    T: Float,
+{
+    pub scheme: AdaptScheme<T>,
+    ///Lower triangular Cholesky factor of the current proposal covariance
+    pub chol: Vec<Vec<T>>,
+    pub moments: RunningMoments<T, V>,
+    pub adapt: bool,
+    pub niter: usize,
+    pub naccepted: usize,
+}
+
+impl<T, V> AmState<T, V>
+where
+    T: Float + NumCast + std::fmt::Debug,
+    V: Clone + IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+{
+    ///`init_cov` is the proposal covariance used before (or as the start of) the adaptation
+    pub fn new(scheme: AdaptScheme<T>, init_cov: &[Vec<T>]) -> AmState<T, V> {
+        AmState {
+            scheme,
+            chol: cholesky(init_cov).expect("initial covariance is not positive definite"),
+            moments: RunningMoments::new(),
+            adapt: true,
+            niter: 0,
+            naccepted: 0,
+        }
+    }
+
+    pub fn haario(init_cov: &[Vec<T>]) -> AmState<T, V> {
+        let t0 = 10 * init_cov.len().max(1);
+        Self::new(
+            AdaptScheme::Haario {
+                epsilon: T::from(1e-10).unwrap(),
+                t0,
+            },
+            init_cov,
+        )
+    }
+
+    pub fn robust(init_cov: &[Vec<T>], target_accept: T) -> AmState<T, V> {
+        Self::new(
+            AdaptScheme::Robust {
+                target_accept,
+                gamma: T::from(2.0 / 3.0).unwrap(),
+            },
+            init_cov,
+        )
+    }
+
+    ///Stop adapting, so that the following iterations form a valid Markov chain
+    pub fn freeze(&mut self) {
+        self.adapt = false;
+    }
+
+    pub fn proposal_cov(&self) -> Vec<Vec<T>> {
+        let n = self.chol.len();
+        (0..n)
+            .map(|i| {
+                (0..n)
+                    .map(|j| {
+                        (0..=i.min(j)).fold(T::zero(), |s, k| s + self.chol[i][k] * self.chol[j][k])
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn accept_ratio(&self) -> Option<T> {
+        if self.niter == 0 {
+            None
+        } else {
+            Some(T::from(self.naccepted).unwrap() / T::from(self.niter).unwrap())
+        }
+    }
+
+    fn adapt_haario(&mut self, x: &V, epsilon: T, t0: usize) {
+        self.moments.push(x);
+        let ndim = x.dimension();
+        if self.moments.count() <= t0.max(1) {
+            return;
+        }
+        let sd = T::from(2.38 * 2.38).unwrap() / T::from(ndim).unwrap();
+        let mut c = self.moments.cov();
+        for (i, row) in c.iter_mut().enumerate() {
+            for (j, c1) in row.iter_mut().enumerate() {
+                *c1 = sd * (*c1 + if i == j { epsilon } else { T::zero() });
+            }
+        }
+        if let Some(l) = cholesky(&c) {
+            self.chol = l;
+        }
+    }
+
+    fn adapt_robust(&mut self, u: &[T], alpha: T, target_accept: T, gamma: T) {
+        let ndim = u.len();
+        let eta = T::from(self.niter).unwrap().powf(-gamma).min(T::one());
+        let u2 = u.iter().fold(T::zero(), |s, &u1| s + u1 * u1);
+        if u2 <= T::zero() {
+            return;
+        }
+        let c = eta * (alpha - target_accept) / u2;
+        //S*u
+        let su: Vec<T> = (0..ndim)
+            .map(|i| (0..=i).fold(T::zero(), |s, k| s + self.chol[i][k] * u[k]))
+            .collect();
+        let mut m = self.proposal_cov();
+        for i in 0..ndim {
+            for j in 0..ndim {
+                m[i][j] = m[i][j] + c * su[i] * su[j];
+            }
+        }
+        if let Some(l) = cholesky(&m) {
+            self.chol = l;
+        }
+    }
+}
+
+///One adaptive random-walk Metropolis step.
+///`lp` is the log probability of `x` and is updated together with it.
+///Returns whether the proposal has been accepted.
+pub fn sample<T, U, V, F>(
+    flogprob: &F,
+    x: &mut V,
+    lp: &mut T,
+    state: &mut AmState<T, V>,
+    rng: &mut U,
+) -> bool
+where
+    T: Float + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
+    Standard: Distribution<T>,
+    StandardNormal: Distribution<T>,
+    U: Rng,
+    V: Clone + IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    F: Fn(&V) -> T + ?Sized,
+{
+    let ndim = x.dimension();
+    assert_eq!(state.chol.len(), ndim);
+    let u: Vec<T> = (0..ndim).map(|_| rng.sample(StandardNormal)).collect();
+    let mut y = x.clone();
+    for i in 0..ndim {
+        y[i] = x[i] + (0..=i).fold(T::zero(), |s, k| s + state.chol[i][k] * u[k]);
+    }
+    let lp_y = flogprob(&y);
+    let alpha = if lp_y.is_nan() {
+        T::zero()
+    } else {
+        (lp_y - *lp).exp().min(T::one())
+    };
+
+    let accepted = rng.sample(Uniform::new(T::zero(), T::one())) < alpha;
+    if accepted {
+        *x = y;
+        *lp = lp_y;
+        state.naccepted += 1;
+    }
+    state.niter += 1;
+
+    if state.adapt {
+        match state.scheme {
+            AdaptScheme::Haario { epsilon, t0 } => state.adapt_haario(x, epsilon, t0),
+            AdaptScheme::Robust {
+                target_accept,
+                gamma,
+            } => state.adapt_robust(&u, alpha, target_accept, gamma),
+        }
+    }
+    accepted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_space::type_wrapper::LsVec;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type Vf = LsVec<f64, Vec<f64>>;
+
+    fn run(state: &mut AmState<f64, Vf>) -> (f64, f64, f64) {
+        let rho = 0.9;
+        let s = [1.0, 10.0];
+        let flogprob = |x: &Vf| {
+            let (a, b) = (x[0] / s[0], x[1] / s[1]);
+            -(a * a - 2.0 * rho * a * b + b * b) / (2.0 * (1.0 - rho * rho))
+        };
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut x = LsVec(vec![0.0, 0.0]);
+        let mut lp = flogprob(&x);
+        for _ in 0..20000 {
+            sample(&flogprob, &mut x, &mut lp, state, &mut rng);
+        }
+        state.freeze();
+        let mut m = RunningMoments::new();
+        for _ in 0..200000 {
+            sample(&flogprob, &mut x, &mut lp, state, &mut rng);
+            m.push(&x);
+        }
+        let c = m.cov();
+        (c[0][0], c[1][1], c[0][1] / (c[0][0] * c[1][1]).sqrt())
+    }
+
+    #[test]
+    fn haario_test() {
+        let mut state = AmState::haario(&[vec![0.01, 0.0], vec![0.0, 0.01]]);
+        let (v0, v1, r) = run(&mut state);
+        assert!((v0 - 1.0).abs() < 0.1, "{}", v0);
+        assert!((v1 - 100.0).abs() < 10.0, "{}", v1);
+        assert!((r - 0.9).abs() < 0.03, "{}", r);
+        let c = state.proposal_cov();
+        assert!((c[1][1] / c[0][0] - 100.0).abs() < 20.0, "{:?}", c);
+    }
+
+    #[test]
+    fn robust_test() {
+        let mut state = AmState::robust(&[vec![0.01, 0.0], vec![0.0, 0.01]], 0.234);
+        let (v0, v1, r) = run(&mut state);
+        assert!((v0 - 1.0).abs() < 0.1, "{}", v0);
+        assert!((v1 - 100.0).abs() < 10.0, "{}", v1);
+        assert!((r - 0.9).abs() < 0.03, "{}", r);
+        let n0 = (state.niter, state.naccepted);
+        let ratio = (n0.1 as f64) / (n0.0 as f64);
+        assert!((ratio - 0.234).abs() < 0.05, "{}", ratio);
+    }
+}
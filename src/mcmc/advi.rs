@@ -0,0 +1,354 @@
+use num::traits::{float::Float, NumCast};
+use rand::{distributions::Distribution, Rng};
+use rand_distr::StandardNormal;
+
+use super::psis::pareto_k;
+use crate::autodiff::{eval, grad, F};
+use crate::linear_space::utils::forward_solve;
+
+///The family of the Gaussian approximation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Family {
+    ///Diagonal covariance
+    MeanField,
+    ///Dense covariance, parameterised by its Cholesky factor
+    FullRank,
+}
+
+///Step-size control of the stochastic gradient ascent
+#[derive(Clone, Copy, Debug)]
+pub enum StepControl<T> {
+    Adam { lr: T, beta1: T, beta2: T, eps: T },
+    Adagrad { lr: T, eps: T },
+}
+
+impl<T> StepControl<T>
+where
+    T: Float,
+{
+    pub fn adam(lr: T) -> StepControl<T> {
+        StepControl::Adam {
+            lr,
+            beta1: T::from(0.9).unwrap(),
+            beta2: T::from(0.999).unwrap(),
+            eps: T::from(1e-8).unwrap(),
+        }
+    }
+
+    pub fn adagrad(lr: T) -> StepControl<T> {
+        StepControl::Adagrad {
+            lr,
+            eps: T::from(1e-8).unwrap(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AdviParams<T> {
+    pub family: Family,
+    pub step: StepControl<T>,
+    ///Number of Monte Carlo draws per gradient estimate
+    pub nmc: usize,
+    pub niter: usize,
+}
+
+impl<T> AdviParams<T>
+where
+    T: Float,
+{
+    pub fn new(family: Family) -> AdviParams<T> {
+        AdviParams {
+            family,
+            step: StepControl::adam(T::from(0.05).unwrap()),
+            nmc: 5,
+            niter: 5000,
+        }
+    }
+}
+
+///A Gaussian `N(mean, chol chol^T)` in the unconstrained space.
+///`chol` is lower triangular, and diagonal for the mean-field family.
+#[derive(Clone, Debug)]
+pub struct GaussianApprox<T> {
+    pub mean: Vec<T>,
+    pub chol: Vec<Vec<T>>,
+}
+
+impl<T> GaussianApprox<T>
+where
+    T: Float + NumCast,
+    StandardNormal: Distribution<T>,
+{
+    pub fn ndim(&self) -> usize {
+        self.mean.len()
+    }
+
+    fn transform(&self, eta: &[T]) -> Vec<T> {
+        self.mean
+            .iter()
+            .zip(self.chol.iter())
+            .map(|(&m, row)| {
+                m + row
+                    .iter()
+                    .zip(eta.iter())
+                    .fold(T::zero(), |s, (&l, &e)| s + l * e)
+            })
+            .collect()
+    }
+
+    pub fn sample<U>(&self, rng: &mut U) -> Vec<T>
+    where
+        U: Rng,
+    {
+        let eta: Vec<T> = (0..self.ndim())
+            .map(|_| rng.sample(StandardNormal))
+            .collect();
+        self.transform(&eta)
+    }
+
+    pub fn log_density(&self, x: &[T]) -> T {
+        let d: Vec<T> = x
+            .iter()
+            .zip(self.mean.iter())
+            .map(|(&x, &m)| x - m)
+            .collect();
+        let z = forward_solve(&self.chol, &d);
+        let two = T::one() + T::one();
+        let log_det = (0..self.ndim()).fold(T::zero(), |s, i| s + self.chol[i][i].ln());
+        -z.iter().fold(T::zero(), |s, &z| s + z * z) / two
+            - log_det
+            - T::from(self.ndim()).unwrap()
+                * (T::from(2).unwrap() * T::from(std::f64::consts::PI).unwrap()).ln()
+                / two
+    }
+
+    pub fn cov(&self) -> Vec<Vec<T>> {
+        let n = self.ndim();
+        (0..n)
+            .map(|i| {
+                (0..n)
+                    .map(|j| {
+                        (0..=i.min(j)).fold(T::zero(), |s, k| s + self.chol[i][k] * self.chol[j][k])
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn sd(&self) -> Vec<T> {
+        self.chol
+            .iter()
+            .map(|row| row.iter().fold(T::zero(), |s, &l| s + l * l).sqrt())
+            .collect()
+    }
+
+    ///Differential entropy
+    pub fn entropy(&self) -> T {
+        let two = T::one() + T::one();
+        let n = T::from(self.ndim()).unwrap();
+        (0..self.ndim()).fold(T::zero(), |s, i| s + self.chol[i][i].ln())
+            + n * (T::one() + (two * T::from(std::f64::consts::PI).unwrap()).ln()) / two
+    }
+}
+
+pub struct AdviResult<T> {
+    pub approx: GaussianApprox<T>,
+    ///Monte Carlo estimate of the ELBO at each iteration
+    pub elbo_trace: Vec<T>,
+}
+
+///Indices `(i, j)` of the free entries of the Cholesky factor
+fn chol_entries(n: usize, family: Family) -> Vec<(usize, usize)> {
+    match family {
+        Family::MeanField => (0..n).map(|i| (i, i)).collect(),
+        Family::FullRank => (0..n).flat_map(|i| (0..=i).map(move |j| (i, j))).collect(),
+    }
+}
+
+///Automatic differentiation variational inference (Kucukelbir et al. 2017).
+///Fits a Gaussian approximation to the density `exp(flogprob)` defined on the unconstrained
+///space by stochastic maximization of the ELBO, with reparameterisation gradients computed by
+///`autodiff::grad`. The diagonal of the Cholesky factor is optimized on a log scale.
+///`init` is the initial mean; the initial covariance is the identity.
+///The returned approximation is the average of the iterates over the second half of the run.
+pub fn fit<T, G, U>(flogprob: &G, init: &[T], params: &AdviParams<T>, rng: &mut U) -> AdviResult<T>
+where
+    T: Float + NumCast + std::fmt::Debug,
+    G: Fn(&[F<T>]) -> F<T>,
+    U: Rng,
+    StandardNormal: Distribution<T>,
+{
+    let n = init.len();
+    let entries = chol_entries(n, params.family);
+    let nparams = n + entries.len();
+    //the free parameters: the mean, followed by the entries of the Cholesky factor,
+    //with the diagonal entries stored as their logarithm
+    let mut lambda: Vec<T> = init.to_vec();
+    lambda.extend(entries.iter().map(|_| T::zero()));
+
+    let unpack = |lambda: &[T]| -> GaussianApprox<T> {
+        let mut chol = vec![vec![T::zero(); n]; n];
+        for (&(i, j), &l) in entries.iter().zip(lambda[n..].iter()) {
+            chol[i][j] = if i == j { l.exp() } else { l };
+        }
+        GaussianApprox {
+            mean: lambda[..n].to_vec(),
+            chol,
+        }
+    };
+
+    let nmc = T::from(params.nmc).unwrap();
+    let mut m1 = vec![T::zero(); nparams];
+    let mut m2 = vec![T::zero(); nparams];
+    let mut elbo_trace = Vec::with_capacity(params.niter);
+    let nburn = params.niter / 2;
+    let mut lambda_sum = vec![T::zero(); nparams];
+
+    for t in 1..=params.niter {
+        let approx = unpack(&lambda);
+        let mut g_lambda = vec![T::zero(); nparams];
+        let mut elbo = T::zero();
+        for _ in 0..params.nmc {
+            let eta: Vec<T> = (0..n).map(|_| rng.sample(StandardNormal)).collect();
+            let z = approx.transform(&eta);
+            let g = grad(flogprob, &z);
+            elbo = elbo + eval(flogprob, &z);
+            for i in 0..n {
+                g_lambda[i] = g_lambda[i] + g[i];
+            }
+            for (k, &(i, j)) in entries.iter().enumerate() {
+                let d = if i == j {
+                    g[i] * eta[i] * approx.chol[i][i]
+                } else {
+                    g[i] * eta[j]
+                };
+                g_lambda[n + k] = g_lambda[n + k] + d;
+            }
+        }
+        for x in g_lambda.iter_mut() {
+            *x = *x / nmc;
+        }
+        //the gradient of the entropy
+        for (k, &(i, j)) in entries.iter().enumerate() {
+            if i == j {
+                g_lambda[n + k] = g_lambda[n + k] + T::one();
+            }
+        }
+        elbo_trace.push(elbo / nmc + approx.entropy());
+
+        match params.step {
+            StepControl::Adam {
+                lr,
+                beta1,
+                beta2,
+                eps,
+            } => {
+                let c1 = T::one() - beta1.powi(t as i32);
+                let c2 = T::one() - beta2.powi(t as i32);
+                for k in 0..nparams {
+                    m1[k] = beta1 * m1[k] + (T::one() - beta1) * g_lambda[k];
+                    m2[k] = beta2 * m2[k] + (T::one() - beta2) * g_lambda[k] * g_lambda[k];
+                    lambda[k] = lambda[k] + lr * (m1[k] / c1) / ((m2[k] / c2).sqrt() + eps);
+                }
+            }
+            StepControl::Adagrad { lr, eps } => {
+                for k in 0..nparams {
+                    m2[k] = m2[k] + g_lambda[k] * g_lambda[k];
+                    lambda[k] = lambda[k] + lr * g_lambda[k] / (m2[k].sqrt() + eps);
+                }
+            }
+        }
+        if t > nburn {
+            for (s, &l) in lambda_sum.iter_mut().zip(lambda.iter()) {
+                *s = *s + l;
+            }
+        }
+    }
+
+    let navg = T::from(params.niter - nburn).unwrap();
+    let lambda_avg: Vec<T> = lambda_sum.iter().map(|&x| x / navg).collect();
+    AdviResult {
+        approx: unpack(&lambda_avg),
+        elbo_trace,
+    }
+}
+
+///Pareto `k` diagnostic of the approximation (Yao et al. 2018), computed from the importance
+///ratios `p(x)/q(x)` of `nsamples` draws from the approximation.
+///Values below 0.5 indicate that the approximation is close to the target,
+///values above 0.7 that it is unreliable.
+pub fn pareto_k_diagnostic<T, G, U>(
+    flogprob: &G,
+    approx: &GaussianApprox<T>,
+    nsamples: usize,
+    rng: &mut U,
+) -> T
+where
+    T: Float + NumCast + std::fmt::Debug,
+    G: Fn(&[F<T>]) -> F<T>,
+    U: Rng,
+    StandardNormal: Distribution<T>,
+{
+    let log_ratios: Vec<T> = (0..nsamples)
+        .map(|_| {
+            let x = approx.sample(rng);
+            eval(flogprob, &x) - approx.log_density(&x)
+        })
+        .collect();
+    pareto_k(&log_ratios)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    //N(mu, S) with S = [[1, rho s], [rho s, s^2]]
+    const MU: [f64; 2] = [1.0, -2.0];
+    const S: f64 = 2.0;
+    const RHO: f64 = 0.8;
+
+    fn logprob(x: &[F<f64>]) -> F<f64> {
+        let d0 = x[0] - MU[0];
+        let d1 = (x[1] - MU[1]) / S;
+        -(d0 * d0 - d0 * d1 * (2.0 * RHO) + d1 * d1) / (2.0 * (1.0 - RHO * RHO))
+    }
+
+    #[test]
+    fn full_rank_test() {
+        let mut rng = StdRng::seed_from_u64(12345);
+        let params = AdviParams::new(Family::FullRank);
+        let result = fit(&logprob, &[0.0, 0.0], &params, &mut rng);
+        let q = &result.approx;
+        let cov = q.cov();
+        assert!((q.mean[0] - MU[0]).abs() < 0.1, "{:?}", q.mean);
+        assert!((q.mean[1] - MU[1]).abs() < 0.2, "{:?}", q.mean);
+        assert!((cov[0][0] - 1.0).abs() < 0.15, "{:?}", cov);
+        assert!((cov[1][1] - S * S).abs() < 0.6, "{:?}", cov);
+        assert!((cov[0][1] - RHO * S).abs() < 0.3, "{:?}", cov);
+        let n = result.elbo_trace.len();
+        let late = result.elbo_trace[n - 100..].iter().sum::<f64>() / 100.0;
+        let early = result.elbo_trace[..10].iter().sum::<f64>() / 10.0;
+        assert!(late > early);
+
+        let k = pareto_k_diagnostic(&logprob, q, 4000, &mut rng);
+        assert!(k < 0.5, "{}", k);
+    }
+
+    #[test]
+    fn mean_field_test() {
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut params = AdviParams::new(Family::MeanField);
+        params.step = StepControl::adagrad(0.5);
+        let result = fit(&logprob, &[0.0, 0.0], &params, &mut rng);
+        let q = &result.approx;
+        assert!((q.mean[0] - MU[0]).abs() < 0.1, "{:?}", q.mean);
+        assert!((q.mean[1] - MU[1]).abs() < 0.2, "{:?}", q.mean);
+        //the mean-field solution matches the conditional standard deviations
+        let sd = q.sd();
+        let c = (1.0 - RHO * RHO).sqrt();
+        assert!((sd[0] - c).abs() < 0.1, "{:?}", sd);
+        assert!((sd[1] - S * c).abs() < 0.2, "{:?}", sd);
+        assert!(q.chol[1][0] == 0.0);
+    }
+}
@@ -1,3 +1,4 @@
+#![allow(clippy::type_complexity)]
 #![allow(clippy::needless_range_loop)]
 use std;
 use std::ops::IndexMut;
@@ -9,6 +10,7 @@ use num::traits::{
 };
 use std::collections::VecDeque;
 
+use crate::opt::linmin::{brent, mnbrak};
 use crate::utils::HasLen;
 use rand::{
     distributions::{uniform::SampleUniform, Distribution, Standard, Uniform},
@@ -72,6 +74,7 @@ where
     IllConditionedDistribution(String, VecDeque<Section<T>>),
     TooFewInitPoints(String),
     DataNotInOrder(String),
+    RangeExpansionFailed(String, T),
 }
 
 fn fmin<T>(x: T, y: T) -> T
@@ -540,7 +543,8 @@ where
                 if ((y3 - y1) * (x2 - x1) == (y2 - y1) * (x3 - x1)
                     && (y4 - y1) * (x2 - x1) == (y2 - y1) * (x4 - x1))
                     || (y2 - y1) * (x4 - x3) == (x2 - x1) * (y4 - y3)
-                    || y_i.is_infinite() =>
+                    || !x_i.is_finite()
+                    || !y_i.is_finite() =>
             {
                 ((s.x_l() + s.x_u()) / two, (s.y_l() + s.y_u()) / two)
             }
@@ -802,6 +806,264 @@ where
     Ok(new_list)
 }
 
+/// The exterior abscissae are placed until the log probability drops by this amount
+/// below the maximum of the evaluated points
+const TAIL_LOG_DROP: f64 = 50.0;
+
+/// Maximum number of steps used to search an exterior abscissa
+const MAX_EXPANSION_STEPS: usize = 1024;
+
+/// Generate `n` initial abscissae inside `xrange`, which may be infinite at either end.
+/// For finite ranges the points are evenly spaced. Otherwise they are centred on the mode of
+/// `pd` (located starting from `xcur`, on a log scale for half-infinite ranges) and spaced
+/// according to its curvature, so that they do not depend on the current state.
+pub fn init_points<T, F>(pd: &F, xrange: (T, T), xcur: T, n: usize) -> Vec<T>
+where
+    T: Float
+        + NumCast
+        + std::cmp::PartialOrd
+        + SampleUniform
+        + std::marker::Sync
+        + std::marker::Send
+        + std::fmt::Display
+        + std::fmt::Debug,
+    Standard: Distribution<T>,
+    F: Fn(T) -> T + std::marker::Sync + std::marker::Send,
+{
+    let (x1, x2) = xrange;
+    let two = one::<T>() + one::<T>();
+    if x1.is_finite() && x2.is_finite() {
+        let nn = T::from(n + 1).unwrap();
+        return (1..=n)
+            .map(|k| x1 + (x2 - x1) / nn * T::from(k).unwrap())
+            .collect();
+    }
+
+    let to_x = |u: T| {
+        if x1.is_finite() {
+            x1 + u.exp()
+        } else if x2.is_finite() {
+            x2 - u.exp()
+        } else {
+            u
+        }
+    };
+    let neg_logp = |u: T| {
+        let y = -pd(to_x(u));
+        let y = if x1.is_finite() || x2.is_finite() {
+            y - u
+        } else {
+            y
+        };
+        if y.is_nan() {
+            T::infinity()
+        } else {
+            y
+        }
+    };
+
+    let u0 = if x1.is_finite() && xcur > x1 {
+        (xcur - x1).ln()
+    } else if x2.is_finite() && xcur < x2 {
+        (x2 - xcur).ln()
+    } else if x1.is_infinite() && x2.is_infinite() {
+        xcur
+    } else {
+        zero()
+    };
+    let step = if u0.is_zero() {
+        one()
+    } else {
+        u0.abs() / T::from(10).unwrap()
+    };
+
+    let (mut ax, mut bx, mut cx) = (u0, u0 + step, zero());
+    let (mut fa, mut fb, mut fc) = (zero(), zero(), zero());
+    mnbrak(
+        &mut ax, &mut bx, &mut cx, &mut fa, &mut fb, &mut fc, &neg_logp,
+    );
+    let (umode, fmode) = brent(ax, bx, cx, &neg_logp, T::epsilon().sqrt());
+
+    let h = T::from(1e-3).unwrap() * (one::<T>() + umode.abs());
+    let curv = (neg_logp(umode + h) + neg_logp(umode - h) - two * fmode) / (h * h);
+    let su = if curv > zero() && curv.is_finite() {
+        one::<T>() / curv.sqrt()
+    } else {
+        one()
+    };
+
+    let half = T::from(n - 1).unwrap() / two;
+    (0..n)
+        .map(|k| to_x(umode + su * (T::from(k).unwrap() - half)))
+        .collect()
+}
+
+/// Move the finite ends of `xrange` at which `pd` is not finite, e.g., below the largest
+/// observation of a uniform distribution whose upper bound is sampled, inwards to the edges of
+/// the support, which are located by bisection towards `xcur`.
+pub fn trim_range<T, F>(pd: &F, xrange: (T, T), xcur: T) -> (T, T)
+where
+    T: Float,
+    F: Fn(T) -> T,
+{
+    let two = one::<T>() + one::<T>();
+    let edge = |end: T| {
+        if end.is_infinite() || pd(end).is_finite() || !pd(xcur).is_finite() {
+            return end;
+        }
+        let (mut outside, mut inside) = (end, xcur);
+        for _ in 0..MAX_EXPANSION_STEPS {
+            let mid = (outside + inside) / two;
+            if mid == outside || mid == inside {
+                break;
+            }
+            if pd(mid).is_finite() {
+                inside = mid;
+            } else {
+                outside = mid;
+            }
+        }
+        inside
+    };
+    (edge(xrange.0), edge(xrange.1))
+}
+
+fn search_exterior<T, F>(
+    pd: &F,
+    xstart: T,
+    step: T,
+    ymax: &mut T,
+    bound: T,
+) -> Result<(T, Vec<T>), ArmsErr<T>>
+where
+    T: Float
+        + NumCast
+        + std::cmp::PartialOrd
+        + SampleUniform
+        + std::marker::Sync
+        + std::marker::Send
+        + std::fmt::Display
+        + std::fmt::Debug,
+    Standard: Distribution<T>,
+    F: Fn(T) -> T + std::marker::Sync + std::marker::Send,
+{
+    let two = one::<T>() + one::<T>();
+    let drop = T::from(TAIL_LOG_DROP).unwrap();
+    let mut points = Vec::new();
+    let mut x_last = xstart;
+    let mut step = step;
+    for _ in 0..MAX_EXPANSION_STEPS {
+        let x = x_last + step;
+        if x.is_infinite() || (bound.is_finite() && (x - bound) * step >= zero()) {
+            break;
+        }
+        let y = pd(x);
+        if y.is_nan() {
+            return Err(ArmsErr::LogProbIsNan(format!("Error@{}", line!()), x));
+        }
+        if y == T::neg_infinity() {
+            //the support ends between x_last and x, locate the edge by bisection
+            let mut lo = x_last;
+            let mut hi = x;
+            for _ in 0..MAX_EXPANSION_STEPS {
+                let mid = (lo + hi) / two;
+                if mid == lo || mid == hi {
+                    break;
+                }
+                if pd(mid).is_finite() {
+                    lo = mid;
+                } else {
+                    hi = mid;
+                }
+            }
+            if lo == xstart {
+                return Err(ArmsErr::RangeExpansionFailed(
+                    format!("Error@{}", line!()),
+                    x,
+                ));
+            }
+            return Ok((lo, points));
+        }
+        if y.is_infinite() {
+            return Err(ArmsErr::LogProbIsInf(format!("Error@{}", line!()), x));
+        }
+        if y > *ymax {
+            *ymax = y;
+        }
+        if y < *ymax - drop {
+            return Ok((x, points));
+        }
+        points.push(x);
+        x_last = x;
+        step = step * two;
+    }
+    Err(ArmsErr::RangeExpansionFailed(
+        format!("Error@{}", line!()),
+        x_last,
+    ))
+}
+
+/// Replace the infinite ends of `xrange` with finite ones, beyond which the probability is
+/// negligible. The exterior abscissae visited are returned together with the initial ones.
+pub fn expand_range<T, F, V>(
+    pd: &F,
+    xrange: (T, T),
+    init_x: &V,
+) -> Result<((T, T), Vec<T>), ArmsErr<T>>
+where
+    T: Float
+        + NumCast
+        + std::cmp::PartialOrd
+        + SampleUniform
+        + std::marker::Sync
+        + std::marker::Send
+        + std::fmt::Display
+        + std::fmt::Debug,
+    Standard: Distribution<T>,
+    F: Fn(T) -> T + std::marker::Sync + std::marker::Send,
+    V: Clone + IndexMut<usize, Output = T> + HasLen + std::marker::Sync + std::marker::Send + Debug,
+{
+    if init_x.len() < 3 {
+        return Err(ArmsErr::TooFewInitPoints(format!("Error@{}", line!())));
+    }
+    let mut points: Vec<T> = (0..init_x.len()).map(|i| init_x[i]).collect();
+    let mut ymax = T::neg_infinity();
+    for &x in &points {
+        let y = pd(x);
+        if y.is_nan() {
+            return Err(ArmsErr::LogProbIsNan(format!("Error@{}", line!()), x));
+        }
+        if y > ymax {
+            ymax = y;
+        }
+    }
+    if !ymax.is_finite() {
+        return Err(ArmsErr::LogProbIsInf(format!("Error@{}", line!()), ymax));
+    }
+    let x_first = points[0];
+    let x_last = points[points.len() - 1];
+    let step = if x_last > x_first {
+        x_last - x_first
+    } else {
+        one()
+    };
+
+    let (mut x1, mut x2) = xrange;
+    if x2.is_infinite() {
+        let (b, mut ext) = search_exterior(pd, x_last, step, &mut ymax, x2)?;
+        x2 = b;
+        points.append(&mut ext);
+    }
+    if x1.is_infinite() {
+        let (b, mut ext) = search_exterior(pd, x_first, -step, &mut ymax, x1)?;
+        x1 = b;
+        ext.reverse();
+        ext.append(&mut points);
+        points = ext;
+    }
+    Ok(((x1, x2), points))
+}
+
 pub fn init<T, F, V>(
     pd: &F,
     xrange: (T, T),
@@ -1136,7 +1398,13 @@ where
     let mut scale = zero();
     //assert!(xrange.0<=xcur);
     //assert!(xrange.1>=xcur);
-    let mut section_list = init(pd, xrange, init_x, &mut scale)?;
+    let mut section_list = if xrange.0.is_finite() && xrange.1.is_finite() {
+        init(pd, xrange, init_x, &mut scale)?
+    } else {
+        let ((x1, x2), init_x) = expand_range(pd, xrange, init_x)?;
+        //a current point far in the tail must stay inside the truncated range
+        init(pd, (x1.min(xcur), x2.max(xcur)), &init_x, &mut scale)?
+    };
     let mut x: T;
     let mut xm = xcur;
     let mut i = 0;
@@ -1181,3 +1449,75 @@ where
     }
     Ok(xm)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn draw<F>(pd: &F, xrange: (f64, f64), x0: f64, n: usize) -> Vec<f64>
+    where
+        F: Fn(f64) -> f64 + Sync + Send,
+    {
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut nchanged = 0;
+        let mut x = x0;
+        let mut result: Vec<_> = (0..n)
+            .map(|_| {
+                let initx = init_points(pd, xrange, x, 5);
+                x = sample(pd, xrange, &initx, x, 2, &mut rng, &mut nchanged).unwrap();
+                x
+            })
+            .collect();
+        result.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        result
+    }
+
+    fn quantile(x: &[f64], q: f64) -> f64 {
+        x[((x.len() as f64) * q) as usize]
+    }
+
+    #[test]
+    fn cauchy_tail_test() {
+        let pd = |x: f64| -(1.0 + x * x).ln();
+        let x = draw(&pd, (f64::NEG_INFINITY, f64::INFINITY), 0.0, 20000);
+        for &q in &[0.01, 0.1, 0.5, 0.9, 0.99] {
+            let expected = (std::f64::consts::PI * (q - 0.5)).tan();
+            let tol = 0.05 + 0.2 * expected.abs();
+            assert!(
+                (quantile(&x, q) - expected).abs() < tol,
+                "q={} {} {}",
+                q,
+                quantile(&x, q),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn pareto_tail_test() {
+        let a = 1.5;
+        let pd = |x: f64| -(a + 1.0) * x.ln();
+        let x = draw(&pd, (1.0, f64::INFINITY), 2.0, 20000);
+        for &q in &[0.1, 0.5, 0.9, 0.99] {
+            let expected = (1.0 - q).powf(-1.0 / a);
+            let tol = 0.2 * (expected - 1.0);
+            assert!(
+                (quantile(&x, q) - expected).abs() < tol,
+                "q={} {} {}",
+                q,
+                quantile(&x, q),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn far_start_test() {
+        //the initial abscissae and the truncated range sit around the mode, far from x0
+        let pd = |x: f64| -x * x / 2.0;
+        let x = draw(&pd, (f64::NEG_INFINITY, f64::INFINITY), 50.0, 5000);
+        assert!(quantile(&x, 0.0) > -5.0 && quantile(&x, 0.9999) < 5.0);
+        assert!(quantile(&x, 0.5).abs() < 0.1, "{}", quantile(&x, 0.5));
+    }
+}
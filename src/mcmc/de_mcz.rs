@@ -0,0 +1,262 @@
+#![allow(clippy::too_many_arguments)]
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use num::traits::{float::Float, NumCast};
+
+use rand::{
+    distributions::{uniform::SampleUniform, Distribution, Standard, Uniform},
+    Rng,
+};
+use rand_distr::StandardNormal;
+
+use std::ops::{Add, Mul, Sub};
+
+use super::ensemble_sample::UpdateFlagSpec;
+use crate::linear_space::IndexableLinearSpace;
+
+pub struct DeMczParams<T> {
+    ///Probability of a snooker update
+    pub p_snooker: T,
+    ///Every `gamma_one_every` iterations the parallel direction update uses `gamma=1`,
+    ///which allows jumps between modes
+    pub gamma_one_every: usize,
+    ///Standard deviation of the Gaussian noise added to the parallel direction update
+    pub b: T,
+    ///The current states are appended to the archive every `archive_thin` iterations
+    pub archive_thin: usize,
+}
+
+impl<T> Default for DeMczParams<T>
+where
+    T: Float,
+{
+    fn default() -> Self {
+        DeMczParams {
+            p_snooker: T::from(0.1).unwrap(),
+            gamma_one_every: 10,
+            b: T::from(1e-6).unwrap(),
+            archive_thin: 10,
+        }
+    }
+}
+
+///The archive of past states `Z` and the iteration counters
+pub struct DeMczState<V> {
+    pub archive: Vec<V>,
+    pub niter: usize,
+    pub nproposed: usize,
+    pub naccepted: usize,
+}
+
+impl<V> DeMczState<V> {
+    ///`init_archive` is usually drawn from the prior and should contain at least 3 points
+    pub fn new(init_archive: Vec<V>) -> DeMczState<V> {
+        assert!(init_archive.len() >= 3);
+        DeMczState {
+            archive: init_archive,
+            niter: 0,
+            nproposed: 0,
+            naccepted: 0,
+        }
+    }
+}
+
+fn dot<T, V>(x: &V, y: &V) -> T
+where
+    T: Float,
+    V: IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+{
+    (0..x.dimension()).fold(T::zero(), |s, i| s + x[i] * y[i])
+}
+
+///Draw `n` distinct indices of the archive
+fn draw_distinct<U>(len: usize, n: usize, rng: &mut U) -> Vec<usize>
+where
+    U: Rng,
+{
+    let mut result: Vec<usize> = Vec::with_capacity(n);
+    while result.len() < n {
+        let i = rng.gen_range(0..len);
+        if !result.contains(&i) {
+            result.push(i);
+        }
+    }
+    result
+}
+
+///One iteration of DE-MCz (ter Braak & Vrugt 2008) for all `chains`.
+///The proposals are built from the archive in `state`, so that the chains are independent
+///given the archive and their log probabilities are evaluated in parallel.
+///The parallel direction update only changes the coordinates selected by `ufs`,
+///while the snooker update moves all coordinates.
+#[allow(clippy::manual_is_multiple_of)]
+pub fn sample<'a, T, U, V, F>(
+    flogprob: &F,
+    chains: &mut [V],
+    cached_logprob: &mut [T],
+    state: &mut DeMczState<V>,
+    params: &DeMczParams<T>,
+    rng: &mut U,
+    ufs: &mut UpdateFlagSpec<'a, T>,
+) where
+    T: Float
+        + NumCast
+        + std::cmp::PartialOrd
+        + SampleUniform
+        + Sync
+        + Send
+        + std::fmt::Display
+        + std::fmt::Debug,
+    Standard: Distribution<T>,
+    StandardNormal: Distribution<T>,
+    U: Rng,
+    V: Clone + IndexableLinearSpace<T> + Sync + Send,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    F: Fn(&V) -> T + Send + Sync + ?Sized,
+{
+    assert_eq!(chains.len(), cached_logprob.len());
+    assert!(state.archive.len() >= 3);
+    let unit = Uniform::new(T::zero(), T::one());
+    let two = T::one() + T::one();
+    let nz = state.archive.len();
+
+    //proposed point and the log of the extra factor of the acceptance ratio
+    let proposed: Vec<(V, T)> = chains
+        .iter()
+        .map(|x| {
+            let ndim = x.dimension();
+            if rng.sample(&unit) < params.p_snooker {
+                let idx = draw_distinct(nz, 3, rng);
+                let z = &state.archive[idx[0]];
+                let d = x - z;
+                let d2 = dot(&d, &d);
+                if d2 <= T::zero() {
+                    return (x.clone(), T::zero());
+                }
+                let p1 = dot(&state.archive[idx[1]], &d);
+                let p2 = dot(&state.archive[idx[2]], &d);
+                let gamma = rng.sample(Uniform::new(T::from(1.2).unwrap(), T::from(2.2).unwrap()));
+                let y = x + &(&d * (gamma * (p1 - p2) / d2));
+                let dy = &y - z;
+                let ratio = T::from(ndim - 1).unwrap() * (dot(&dy, &dy).ln() - d2.ln()) / two;
+                (y, ratio)
+            } else {
+                let flags = ufs.generate_update_flags(ndim, rng);
+                let d_eff = flags.iter().filter(|&&f| f).count().max(1);
+                let gamma = if params.gamma_one_every > 0
+                    && (state.niter + 1) % params.gamma_one_every == 0
+                {
+                    T::one()
+                } else {
+                    T::from(2.38).unwrap() / (two * T::from(d_eff).unwrap()).sqrt()
+                };
+                let idx = draw_distinct(nz, 2, rng);
+                let diff = &state.archive[idx[0]] - &state.archive[idx[1]];
+                let mut y = x.clone();
+                for i in 0..ndim {
+                    if flags[i] {
+                        y[i] =
+                            x[i] + gamma * diff[i] + params.b * rng.sample::<T, _>(StandardNormal);
+                    }
+                }
+                (y, T::zero())
+            }
+        })
+        .collect();
+
+    let new_logprob: Vec<T> = proposed.par_iter().map(|(y, _)| flogprob(y)).collect();
+
+    for ((x, lp), ((y, ratio), new_lp)) in chains
+        .iter_mut()
+        .zip(cached_logprob.iter_mut())
+        .zip(proposed.into_iter().zip(new_logprob))
+    {
+        state.nproposed += 1;
+        let log_alpha = new_lp - *lp + ratio;
+        if !log_alpha.is_nan() && rng.sample(&unit).ln() < log_alpha {
+            *x = y;
+            *lp = new_lp;
+            state.naccepted += 1;
+        }
+    }
+
+    state.niter += 1;
+    if params.archive_thin > 0 && state.niter % params.archive_thin == 0 {
+        state.archive.extend(chains.iter().cloned());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_space::type_wrapper::LsVec;
+    use crate::mcmc::ensemble_sample::init_logprob;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type Vf = LsVec<f64, Vec<f64>>;
+
+    fn run(ufs: &mut UpdateFlagSpec<f64>) {
+        let rho = 0.8;
+        let flogprob = |x: &Vf| {
+            -(x[0] * x[0] - 2.0 * rho * x[0] * x[1] + x[1] * x[1]) / (2.0 * (1.0 - rho * rho))
+                - x[2] * x[2] / 2.0
+        };
+        let mut rng = StdRng::seed_from_u64(12345);
+        let draw = |rng: &mut StdRng| {
+            LsVec(
+                (0..3)
+                    .map(|_| 3.0 * rng.sample::<f64, _>(StandardNormal))
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let archive: Vec<Vf> = (0..30).map(|_| draw(&mut rng)).collect();
+        let mut chains: Vec<Vf> = (0..3).map(|_| draw(&mut rng)).collect();
+        let mut lp = vec![0.0; 3];
+        init_logprob(&flogprob, &chains, &mut lp);
+        let mut state = DeMczState::new(archive);
+        let params = DeMczParams::default();
+
+        let (mut s0, mut s1, mut s01, mut s2) = (0.0, 0.0, 0.0, 0.0);
+        let mut n = 0.0;
+        for k in 0..60000 {
+            sample(
+                &flogprob,
+                &mut chains,
+                &mut lp,
+                &mut state,
+                &params,
+                &mut rng,
+                ufs,
+            );
+            if k >= 5000 {
+                for x in &chains {
+                    s0 += x[0] * x[0];
+                    s1 += x[1] * x[1];
+                    s01 += x[0] * x[1];
+                    s2 += x[2] * x[2];
+                    n += 1.0;
+                }
+            }
+        }
+        assert!((s0 / n - 1.0).abs() < 0.1, "{}", s0 / n);
+        assert!((s1 / n - 1.0).abs() < 0.1, "{}", s1 / n);
+        assert!((s01 / n - rho).abs() < 0.1, "{}", s01 / n);
+        assert!((s2 / n - 1.0).abs() < 0.1, "{}", s2 / n);
+        assert!(state.naccepted > 0 && state.naccepted < state.nproposed);
+    }
+
+    #[test]
+    fn gaussian_test() {
+        run(&mut UpdateFlagSpec::All);
+    }
+
+    #[test]
+    fn subspace_test() {
+        run(&mut UpdateFlagSpec::Prob(0.5));
+    }
+}
@@ -104,6 +104,7 @@ pub fn sample<'a, T, U, V, F>(
     sample_pt(flogprob, ensemble, cached_logprob, rng, a, ufs, &[T::one()])
 }
 
+#[allow(clippy::manual_is_multiple_of)]
 pub fn sample_pt<'a, T, U, V, F>(
     flogprob: &F,
     ensemble: &mut [V],
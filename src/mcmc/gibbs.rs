@@ -0,0 +1,303 @@
+#![allow(clippy::needless_range_loop)]
+use num::traits::{float::Float, NumCast};
+use rand::{
+    distributions::{uniform::SampleUniform, Distribution, Standard, Uniform},
+    Rng,
+};
+use rand_distr::StandardNormal;
+use std::ops::{Add, Mul, Sub};
+
+use super::arms::{init_points, sample as arms, ArmsErr};
+use crate::linear_space::IndexableLinearSpace;
+
+///The order in which the coordinates are visited in one sweep
+#[derive(Clone, Debug)]
+pub enum ScanOrder {
+    ///Visit all coordinates in the order 0, 1, ..., n-1
+    Systematic,
+    ///Visit n coordinates drawn uniformly with replacement
+    Random,
+    ///Visit the blocks in the given order. Each block takes one hit-and-run step,
+    ///i.e. it is updated along a random direction within the subspace spanned by its coordinates,
+    ///rather than being drawn from its joint conditional
+    RandomDirectionBlocks(Vec<Vec<usize>>),
+}
+
+pub fn sample_coordinate<T, U, V, F>(
+    flogprob: &F,
+    x: &mut V,
+    i: usize,
+    xrange: (T, T),
+    ninit: usize,
+    niter: usize,
+    rng: &mut U,
+) -> Result<bool, ArmsErr<T>>
+where
+    T: Float
+        + NumCast
+        + std::cmp::PartialOrd
+        + SampleUniform
+        + Sync
+        + Send
+        + std::fmt::Display
+        + std::fmt::Debug,
+    Standard: Distribution<T>,
+    U: Rng,
+    V: Clone + IndexableLinearSpace<T> + Sync + Send,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    F: Fn(&V) -> T + Sync + Send + ?Sized,
+{
+    assert!(xrange.1 > xrange.0);
+    let x0 = x[i];
+    let mut nchanged = 0;
+    let x1 = {
+        let x = &*x;
+        let pd = |xi| {
+            let mut y = x.clone();
+            y[i] = xi;
+            flogprob(&y)
+        };
+        let initx = init_points(&pd, xrange, x0, ninit);
+        arms(&pd, xrange, &initx, x0, niter, rng, &mut nchanged)?
+    };
+    x[i] = x1;
+    Ok(x1 != x0)
+}
+
+///Hit-and-run update of the coordinates in `block`: draw a uniformly distributed direction
+///in their subspace and sample the position along it with ARMS
+pub fn sample_random_direction<T, U, V, F>(
+    flogprob: &F,
+    x: &mut V,
+    block: &[usize],
+    bounds: &[(T, T)],
+    ninit: usize,
+    niter: usize,
+    rng: &mut U,
+) -> Result<Vec<bool>, ArmsErr<T>>
+where
+    T: Float
+        + NumCast
+        + std::cmp::PartialOrd
+        + SampleUniform
+        + Sync
+        + Send
+        + std::fmt::Display
+        + std::fmt::Debug,
+    Standard: Distribution<T>,
+    StandardNormal: Distribution<T>,
+    U: Rng,
+    V: Clone + IndexableLinearSpace<T> + Sync + Send,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    F: Fn(&V) -> T + Sync + Send + ?Sized,
+{
+    if block.len() == 1 {
+        let i = block[0];
+        return Ok(vec![sample_coordinate(
+            flogprob, x, i, bounds[i], ninit, niter, rng,
+        )?]);
+    }
+
+    let direction: Vec<T> = loop {
+        let d: Vec<T> = block.iter().map(|_| rng.sample(StandardNormal)).collect();
+        let norm = d.iter().fold(T::zero(), |a, &b| a + b * b).sqrt();
+        if norm > T::zero() {
+            break d.into_iter().map(|d1| d1 / norm).collect();
+        }
+    };
+
+    let mut t_range = (T::neg_infinity(), T::infinity());
+    for (&i, &d) in block.iter().zip(direction.iter()) {
+        if d != T::zero() {
+            let t1 = (bounds[i].0 - x[i]) / d;
+            let t2 = (bounds[i].1 - x[i]) / d;
+            t_range.0 = t_range.0.max(t1.min(t2));
+            t_range.1 = t_range.1.min(t1.max(t2));
+        }
+    }
+    assert!(t_range.1 > t_range.0);
+
+    let x0 = x.clone();
+    let pd = |t| {
+        let mut y = x0.clone();
+        for (&i, &d) in block.iter().zip(direction.iter()) {
+            y[i] = x0[i] + d * t;
+        }
+        flogprob(&y)
+    };
+    let initx = init_points(&pd, t_range, T::zero(), ninit);
+    let mut nchanged = 0;
+    let t = arms(&pd, t_range, &initx, T::zero(), niter, rng, &mut nchanged)?;
+
+    Ok(block
+        .iter()
+        .zip(direction.iter())
+        .map(|(&i, &d)| {
+            x[i] = (x0[i] + d * t).max(bounds[i].0).min(bounds[i].1);
+            x[i] != x0[i]
+        })
+        .collect())
+}
+
+///One Gibbs sweep over the coordinates of `x`, each conditional is sampled with ARMS.
+///`bounds` gives the support of every coordinate (either end may be infinite),
+///`ninit` the number of initial abscissae and `niter` the number of ARMS iterations per update.
+///The returned vector counts how many times each coordinate has been changed.
+pub fn sample<T, U, V, F>(
+    flogprob: &F,
+    x: &mut V,
+    bounds: &[(T, T)],
+    scan: &ScanOrder,
+    ninit: usize,
+    niter: usize,
+    rng: &mut U,
+) -> Result<Vec<usize>, ArmsErr<T>>
+where
+    T: Float
+        + NumCast
+        + std::cmp::PartialOrd
+        + SampleUniform
+        + Sync
+        + Send
+        + std::fmt::Display
+        + std::fmt::Debug,
+    Standard: Distribution<T>,
+    StandardNormal: Distribution<T>,
+    U: Rng,
+    V: Clone + IndexableLinearSpace<T> + Sync + Send,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    F: Fn(&V) -> T + Sync + Send + ?Sized,
+{
+    let ndim = x.dimension();
+    assert_eq!(bounds.len(), ndim);
+    let mut change_count = vec![0; ndim];
+
+    match scan {
+        ScanOrder::Systematic => {
+            for i in 0..ndim {
+                if sample_coordinate(flogprob, x, i, bounds[i], ninit, niter, rng)? {
+                    change_count[i] += 1;
+                }
+            }
+        }
+        ScanOrder::Random => {
+            let idx = Uniform::new(0, ndim);
+            for _ in 0..ndim {
+                let i = rng.sample(idx);
+                if sample_coordinate(flogprob, x, i, bounds[i], ninit, niter, rng)? {
+                    change_count[i] += 1;
+                }
+            }
+        }
+        ScanOrder::RandomDirectionBlocks(blocks) => {
+            for block in blocks {
+                let changed =
+                    sample_random_direction(flogprob, x, block, bounds, ninit, niter, rng)?;
+                for (&i, c) in block.iter().zip(changed) {
+                    if c {
+                        change_count[i] += 1;
+                    }
+                }
+            }
+        }
+    }
+    Ok(change_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_space::type_wrapper::LsVec;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type Vf = LsVec<f64, Vec<f64>>;
+
+    //N(0, S) with S = [[1, 0.8, 0], [0.8, 1, 0], [0, 0, 4]]
+    const RHO: f64 = 0.8;
+
+    fn logprob(x: &Vf) -> f64 {
+        -(x[0] * x[0] - 2.0 * RHO * x[0] * x[1] + x[1] * x[1]) / (2.0 * (1.0 - RHO * RHO))
+            - x[2] * x[2] / 8.0
+    }
+
+    fn run(scan: &ScanOrder, nsweeps: usize) -> (Vec<Vf>, Vec<usize>) {
+        let mut rng = StdRng::seed_from_u64(12345);
+        let bounds = vec![(-20.0, 20.0); 3];
+        let mut x = LsVec(vec![3.0, -3.0, 0.0]);
+        let mut change_count = vec![0; 3];
+        let mut samples = Vec::with_capacity(nsweeps);
+        for _ in 0..nsweeps {
+            let c = sample(&logprob, &mut x, &bounds, scan, 5, 10, &mut rng).unwrap();
+            for (a, b) in change_count.iter_mut().zip(c) {
+                *a += b;
+            }
+            samples.push(x.clone());
+        }
+        (samples, change_count)
+    }
+
+    fn check_moments(samples: &[Vf]) {
+        let n = samples.len() as f64;
+        let mean: Vec<f64> = (0..3)
+            .map(|i| samples.iter().map(|x| x[i]).sum::<f64>() / n)
+            .collect();
+        let cov = |i: usize, j: usize| {
+            samples
+                .iter()
+                .map(|x| (x[i] - mean[i]) * (x[j] - mean[j]))
+                .sum::<f64>()
+                / n
+        };
+        for (i, &m) in mean.iter().enumerate() {
+            assert!(m.abs() < 0.1, "{:?}", mean);
+            let expected = if i == 2 { 4.0 } else { 1.0 };
+            assert!(
+                (cov(i, i) / expected - 1.0).abs() < 0.1,
+                "{} {}",
+                i,
+                cov(i, i)
+            );
+        }
+        assert!((cov(0, 1) - RHO).abs() < 0.1, "{}", cov(0, 1));
+        assert!(cov(0, 2).abs() < 0.1, "{}", cov(0, 2));
+    }
+
+    #[test]
+    fn systematic_test() {
+        let nsweeps = 10000;
+        let (samples, change_count) = run(&ScanOrder::Systematic, nsweeps);
+        check_moments(&samples[100..]);
+        //every conditional is log-concave, so ARMS always moves
+        assert_eq!(change_count, vec![nsweeps; 3]);
+    }
+
+    #[test]
+    fn random_test() {
+        let nsweeps = 10000;
+        let (samples, change_count) = run(&ScanOrder::Random, nsweeps);
+        check_moments(&samples[100..]);
+        assert_eq!(change_count.iter().sum::<usize>(), 3 * nsweeps);
+        for &c in &change_count {
+            assert!(
+                (c as f64 / nsweeps as f64 - 1.0).abs() < 0.05,
+                "{:?}",
+                change_count
+            );
+        }
+    }
+
+    #[test]
+    fn random_direction_blocks_test() {
+        let nsweeps = 10000;
+        let scan = ScanOrder::RandomDirectionBlocks(vec![vec![0, 1], vec![2]]);
+        let (samples, change_count) = run(&scan, nsweeps);
+        check_moments(&samples[100..]);
+        assert_eq!(change_count, vec![nsweeps; 3]);
+    }
+}
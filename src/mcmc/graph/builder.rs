@@ -0,0 +1,324 @@
+#![allow(clippy::type_complexity)]
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use num::traits::float::Float;
+use rand::distributions::uniform::SampleUniform;
+use rand::distributions::{Distribution, Standard};
+
+use super::graph::{Graph, NodeAdder, NodeHandle};
+use super::nodes::{
+    abs_node, add_node, const_node, cos_node, div_node, exp_func_node, lg_node, ln_node,
+    logistic_func_node, mul_node, neg_node, pow_node, scalar_func_node, sin_node, sqrt_node,
+    sub_node,
+};
+
+///Builds expressions of node values on a graph with operators, e.g.,
+///```ignore
+///let b = GraphBuilder::new(&mut g);
+///let mu = b.node(normal_node(b.constant(0.0).handle(), b.constant(1.0).handle()), &"mu".to_string());
+///let y = (mu * 2.0 + 1.0).exp() - mu.sin();
+///```
+///Each operation adds a deterministic node, with a key from `key_gen` that is not yet used in
+///the graph, and equal constants share one node.
+pub struct GraphBuilder<'g, K, T>
+where
+    K: std::hash::Hash + Eq + Clone + Debug,
+    T: Float + Sync + Send + Display,
+{
+    graph: RefCell<&'g mut Graph<K, T>>,
+    key_gen: Box<dyn Fn(usize) -> K>,
+    nauto: Cell<usize>,
+    constants: RefCell<HashMap<u64, NodeHandle>>,
+}
+
+///An output of a node in the graph of a `GraphBuilder`
+pub struct NodeRef<'b, 'g, K, T>
+where
+    K: std::hash::Hash + Eq + Clone + Debug,
+    T: Float + Sync + Send + Display,
+{
+    builder: &'b GraphBuilder<'g, K, T>,
+    handle: NodeHandle,
+    idx: usize,
+}
+
+impl<'b, 'g, K, T> Clone for NodeRef<'b, 'g, K, T>
+where
+    K: std::hash::Hash + Eq + Clone + Debug,
+    T: Float + Sync + Send + Display,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'b, 'g, K, T> Copy for NodeRef<'b, 'g, K, T>
+where
+    K: std::hash::Hash + Eq + Clone + Debug,
+    T: Float + Sync + Send + Display,
+{
+}
+
+impl<'g, K, T> GraphBuilder<'g, K, T>
+where
+    K: std::hash::Hash + Eq + Clone + Debug + From<String>,
+    T: 'static + Float + Sync + SampleUniform + Send + Display + Debug,
+    Standard: Distribution<T>,
+{
+    ///A builder generating the keys `#expr1`, `#expr2`, ...
+    pub fn new(g: &'g mut Graph<K, T>) -> Self {
+        Self::with_keys(g, |i| K::from(format!("#expr{}", i)))
+    }
+}
+
+impl<'g, K, T> GraphBuilder<'g, K, T>
+where
+    K: std::hash::Hash + Eq + Clone + Debug,
+    T: 'static + Float + Sync + SampleUniform + Send + Display + Debug,
+    Standard: Distribution<T>,
+{
+    pub fn with_keys<F>(g: &'g mut Graph<K, T>, key_gen: F) -> Self
+    where
+        F: 'static + Fn(usize) -> K,
+    {
+        GraphBuilder {
+            graph: RefCell::new(g),
+            key_gen: Box::new(key_gen),
+            nauto: Cell::new(0),
+            constants: RefCell::new(HashMap::new()),
+        }
+    }
+
+    ///Add the node with the key `k`
+    pub fn node(&self, n: NodeAdder<T>, k: &K) -> NodeRef<'_, 'g, K, T> {
+        let handle = n.add_to(&mut self.graph.borrow_mut(), k);
+        self.wrap((handle, 0))
+    }
+
+    ///Refer to an output of a node that is already in the graph
+    pub fn wrap(&self, (handle, idx): (NodeHandle, usize)) -> NodeRef<'_, 'g, K, T> {
+        NodeRef {
+            builder: self,
+            handle,
+            idx,
+        }
+    }
+
+    pub fn constant(&self, x: T) -> NodeRef<'_, 'g, K, T> {
+        let bits = x.to_f64().unwrap().to_bits();
+        if let Some(&h) = self.constants.borrow().get(&bits) {
+            return self.wrap((h, 0));
+        }
+        let r = self.intermediate(const_node(x));
+        self.constants.borrow_mut().insert(bits, r.handle);
+        r
+    }
+
+    ///A deterministic node computing `func` from the values of `x`
+    pub fn func(
+        &self,
+        x: &[NodeRef<K, T>],
+        func: Box<dyn Fn(&[T]) -> T + Send + Sync>,
+    ) -> NodeRef<'_, 'g, K, T> {
+        let parents: Vec<_> = x.iter().map(|r| r.handle()).collect();
+        self.intermediate(scalar_func_node(&parents, func))
+    }
+
+    fn intermediate(&self, n: NodeAdder<T>) -> NodeRef<'_, 'g, K, T> {
+        let mut g = self.graph.borrow_mut();
+        let key = loop {
+            self.nauto.set(self.nauto.get() + 1);
+            let key = (self.key_gen)(self.nauto.get());
+            if !g.contains_key(&key) {
+                break key;
+            }
+        };
+        let handle = n.add_to(&mut g, &key);
+        NodeRef {
+            builder: self,
+            handle,
+            idx: 0,
+        }
+    }
+}
+
+impl<'b, 'g, K, T> NodeRef<'b, 'g, K, T>
+where
+    K: std::hash::Hash + Eq + Clone + Debug,
+    T: 'static + Float + Sync + SampleUniform + Send + Display + Debug,
+    Standard: Distribution<T>,
+{
+    ///The parent reference to pass to the node constructors
+    pub fn handle(&self) -> (NodeHandle, usize) {
+        (self.handle, self.idx)
+    }
+
+    fn unary(self, f: fn((NodeHandle, usize)) -> NodeAdder<T>) -> Self {
+        self.builder.intermediate(f(self.handle()))
+    }
+
+    fn binary(
+        self,
+        rhs: Self,
+        f: fn((NodeHandle, usize), (NodeHandle, usize)) -> NodeAdder<T>,
+    ) -> Self {
+        self.builder.intermediate(f(self.handle(), rhs.handle()))
+    }
+
+    pub fn exp(self) -> Self {
+        self.unary(exp_func_node)
+    }
+
+    pub fn ln(self) -> Self {
+        self.unary(ln_node)
+    }
+
+    pub fn log10(self) -> Self {
+        self.unary(lg_node)
+    }
+
+    pub fn sin(self) -> Self {
+        self.unary(sin_node)
+    }
+
+    pub fn cos(self) -> Self {
+        self.unary(cos_node)
+    }
+
+    pub fn sqrt(self) -> Self {
+        self.unary(sqrt_node)
+    }
+
+    pub fn abs(self) -> Self {
+        self.unary(abs_node)
+    }
+
+    pub fn logistic(self) -> Self {
+        self.unary(logistic_func_node)
+    }
+
+    pub fn pow(self, e: Self) -> Self {
+        self.binary(e, pow_node)
+    }
+
+    pub fn powf(self, e: T) -> Self {
+        self.pow(self.builder.constant(e))
+    }
+}
+
+macro_rules! impl_binary_op {
+    ($tr:ident, $method:ident, $node:ident) => {
+        impl<'b, 'g, K, T> $tr for NodeRef<'b, 'g, K, T>
+        where
+            K: std::hash::Hash + Eq + Clone + Debug,
+            T: 'static + Float + Sync + SampleUniform + Send + Display + Debug,
+            Standard: Distribution<T>,
+        {
+            type Output = Self;
+            fn $method(self, rhs: Self) -> Self {
+                self.binary(rhs, $node)
+            }
+        }
+
+        impl<'b, 'g, K, T> $tr<T> for NodeRef<'b, 'g, K, T>
+        where
+            K: std::hash::Hash + Eq + Clone + Debug,
+            T: 'static + Float + Sync + SampleUniform + Send + Display + Debug,
+            Standard: Distribution<T>,
+        {
+            type Output = Self;
+            fn $method(self, rhs: T) -> Self {
+                self.binary(self.builder.constant(rhs), $node)
+            }
+        }
+
+        impl_binary_op!($tr, $method, $node, f64);
+        impl_binary_op!($tr, $method, $node, f32);
+    };
+    ($tr:ident, $method:ident, $node:ident, $t:ty) => {
+        impl<'b, 'g, K> $tr<NodeRef<'b, 'g, K, $t>> for $t
+        where
+            K: std::hash::Hash + Eq + Clone + Debug,
+        {
+            type Output = NodeRef<'b, 'g, K, $t>;
+            fn $method(self, rhs: NodeRef<'b, 'g, K, $t>) -> NodeRef<'b, 'g, K, $t> {
+                rhs.builder.constant(self).binary(rhs, $node)
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add, add_node);
+impl_binary_op!(Sub, sub, sub_node);
+impl_binary_op!(Mul, mul, mul_node);
+impl_binary_op!(Div, div, div_node);
+
+impl<'b, 'g, K, T> Neg for NodeRef<'b, 'g, K, T>
+where
+    K: std::hash::Hash + Eq + Clone + Debug,
+    T: 'static + Float + Sync + SampleUniform + Send + Display + Debug,
+    Standard: Distribution<T>,
+{
+    type Output = Self;
+    fn neg(self) -> Self {
+        self.unary(neg_node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcmc::graph::graph::ParamObservability::UnObserved;
+    use crate::mcmc::graph::nodes::normal_node;
+
+    #[test]
+    fn expr_test() {
+        let mut g = Graph::<String, f64>::new();
+        let b = GraphBuilder::new(&mut g);
+        let a = b.constant(1.5);
+        let c = b.node(const_node(-0.5), &"c".to_string());
+        let x = b.node(
+            normal_node(a.handle(), (a * 2.0).handle()).with_all_values(&[UnObserved(0.25)]),
+            &"x".to_string(),
+        );
+        let exprs = [
+            (a + x * c - 1.0) / (2.0 - c),
+            (-x).exp() + x.sin() * x.cos() - 3.0 / a,
+            (x + 1.0).sqrt().ln() + c.abs().log10(),
+            x.logistic().pow(a) - 2.0 * x.powf(2.0),
+        ];
+        let keys: Vec<String> = exprs
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                let k = format!("e{}", i);
+                b.node(
+                    scalar_func_node(&[e.handle()], Box::new(|x: &[f64]| x[0])),
+                    &k,
+                );
+                k
+            })
+            .collect();
+        drop(b);
+
+        let gv = g.init_gv();
+        let (a, c, x) = (1.5f64, -0.5f64, 0.25f64);
+        let expected = [
+            (a + x * c - 1.0) / (2.0 - c),
+            (-x).exp() + x.sin() * x.cos() - 3.0 / a,
+            (x + 1.0).sqrt().ln() + c.abs().log10(),
+            (1.0 / (1.0 + (-x).exp())).powf(a) - 2.0 * x.powf(2.0),
+        ];
+        for (k, e) in keys.iter().zip(expected.iter()) {
+            let v = g.values_of(k, &gv)[0];
+            assert!((v - e).abs() < 1e-12, "{} {} {}", k, v, e);
+        }
+        //the keys of the intermediate nodes are generated in order
+        assert!(g.contains_key(&"#expr1".to_string()) && g.contains_key(&"c".to_string()));
+        assert_eq!(g.values_of(&"#expr2".to_string(), &gv), vec![2.0]);
+        assert_eq!(g.values_of(&"#expr3".to_string(), &gv), vec![3.0]);
+    }
+}
@@ -0,0 +1,490 @@
+#![allow(clippy::type_complexity)]
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::ops::Index;
+
+use num::traits::float::Float;
+use rand::distributions::uniform::SampleUniform;
+use rand::distributions::{Distribution, Standard};
+
+use super::graph::Graph;
+use super::graph_var::GraphVar;
+use super::node::BlockUpdate;
+use super::node::NodeContent;
+use crate::linear_space::type_wrapper::LsVec;
+
+///One or more coordinates of the flat vector
+enum Coord {
+    ///The `j`-th value of the scalar node `node`, transformed according to which of the bounds of
+    ///its range are finite
+    Scalar {
+        node: usize,
+        j: usize,
+        lower: bool,
+        upper: bool,
+    },
+    ///All the values of the block node `node`, with `n` coordinates
+    Block {
+        node: usize,
+        n: usize,
+        transformed: bool,
+    },
+}
+
+///The log posterior of a sealed graph as a function of a flat vector of the unobserved continuous
+///values, transformed to an unconstrained space, so that the samplers and optimizers working on
+///`LsVec` can be used, e.g.,
+///```ignore
+///let fp = FlatPosterior::new(&g, &g.init_gv());
+///let f = |x: &LsVec<f64, Vec<f64>>| -fp.logpost_without_jacobian(x);
+///let (x, _) = fmin(&f, &fp.to_unconstrained(&g.init_gv()), Tolerance::Abs(1e-10), 1000, &mut |_, _| {});
+///let map = fp.values(&x);
+///```
+///A scalar value bounded by the `range` of its node from below (above) is mapped by
+///`ln(x-lo)` (`ln(hi-x)`), one bounded from both sides by `logit((x-lo)/(hi-lo))`, with the
+///bounds evaluated at the current parent values. Block nodes with `BlockUpdate::Transformed`
+///use their own transforms. Discrete values are held at their values in the `GraphVar` passed
+///to `new`.
+pub struct FlatPosterior<'a, K, T>
+where
+    K: std::hash::Hash + Eq + Clone + Debug,
+    T: Float + Sync + Send + Display,
+{
+    graph: &'a Graph<K, T>,
+    fixed_values: Vec<T>,
+    deterministic_values: Vec<T>,
+    sampleable_values: Vec<T>,
+    coords: Vec<Coord>,
+    keys: Vec<(K, usize)>,
+}
+
+fn logistic<T>(y: T) -> T
+where
+    T: Float,
+{
+    T::one() / (T::one() + (-y).exp())
+}
+
+///`ln(1+exp(y))`
+fn softplus<T>(y: T) -> T
+where
+    T: Float,
+{
+    y.max(T::zero()) + (-y.abs()).exp().ln_1p()
+}
+
+impl<'a, K, T> FlatPosterior<'a, K, T>
+where
+    K: std::hash::Hash + Eq + Clone + Debug,
+    T: Float + Sync + SampleUniform + Send + Display + Debug,
+    Standard: Distribution<T>,
+{
+    pub fn new(graph: &'a Graph<K, T>, gv: &GraphVar<T>) -> Self {
+        let mut coords = Vec::new();
+        let mut keys = Vec::new();
+        for (i, n) in graph.nodes().iter().enumerate() {
+            if let NodeContent::StochasticNode {
+                ref is_observed,
+                ref block,
+                discrete,
+                ..
+            } = n.content
+            {
+                if is_observed.iter().all(|&x| x) {
+                    continue;
+                }
+                match block {
+                    Some(BlockUpdate::CountTransfer) => continue,
+                    Some(b) => {
+                        if is_observed.iter().any(|&x| x) {
+                            panic!(
+                                "block node {:?} must be either observed or unobserved as a whole",
+                                graph.key_of(i)
+                            );
+                        }
+                        let (n, transformed) = match b {
+                            BlockUpdate::Transformed {
+                                to_unconstrained, ..
+                            } => (to_unconstrained(&graph.cached_values_of(i, gv)).len(), true),
+                            _ => (is_observed.len(), false),
+                        };
+                        keys.extend((0..n).map(|m| (graph.key_of(i).clone(), m)));
+                        coords.push(Coord::Block {
+                            node: i,
+                            n,
+                            transformed,
+                        });
+                    }
+                    None if discrete => continue,
+                    None => {
+                        let range = graph.range(i, gv).unwrap();
+                        for (j, &(lo, hi)) in range.iter().enumerate() {
+                            if is_observed[j] {
+                                continue;
+                            }
+                            keys.push((graph.key_of(i).clone(), j));
+                            coords.push(Coord::Scalar {
+                                node: i,
+                                j,
+                                lower: lo.is_finite(),
+                                upper: hi.is_finite(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        let mut gv = gv.clone();
+        graph.update_all_deterministic_nodes(&mut gv);
+        FlatPosterior {
+            graph,
+            fixed_values: gv.fixed_values.clone(),
+            deterministic_values: gv.deterministic_values.clone(),
+            sampleable_values: gv.sampleable_values.clone(),
+            coords,
+            keys,
+        }
+    }
+
+    ///The number of coordinates of the flat vector
+    pub fn ndim(&self) -> usize {
+        self.keys.len()
+    }
+
+    ///The key of the node and the index in its (transformed) values of each coordinate
+    pub fn keys(&self) -> &[(K, usize)] {
+        &self.keys
+    }
+
+    fn template(&self) -> GraphVar<T> {
+        GraphVar {
+            fixed_values: self.fixed_values.clone(),
+            deterministic_values: self.deterministic_values.clone(),
+            sampleable_values: self.sampleable_values.clone(),
+            old_fixed_values: vec![None; self.fixed_values.len()],
+            old_deterministic_values: vec![None; self.deterministic_values.len()],
+            old_sampleable_values: vec![None; self.sampleable_values.len()],
+        }
+    }
+
+    ///The flat vector corresponding to the values in `gv`
+    pub fn to_unconstrained(&self, gv: &GraphVar<T>) -> LsVec<T, Vec<T>> {
+        let mut gv = gv.clone();
+        self.graph.update_all_deterministic_nodes(&mut gv);
+        let mut result = Vec::with_capacity(self.ndim());
+        for c in &self.coords {
+            match *c {
+                Coord::Scalar {
+                    node,
+                    j,
+                    lower,
+                    upper,
+                } => {
+                    let x = self.graph.cached_value_of(node, j, &gv);
+                    let (lo, hi) = self.graph.range(node, &gv).unwrap()[j];
+                    result.push(match (lower, upper) {
+                        (false, false) => x,
+                        (true, false) => (x - lo).ln(),
+                        (false, true) => (hi - x).ln(),
+                        (true, true) => ((x - lo) / (hi - x)).ln(),
+                    });
+                }
+                Coord::Block {
+                    node, transformed, ..
+                } => {
+                    let x = self.graph.cached_values_of(node, &gv);
+                    if transformed {
+                        if let NodeContent::StochasticNode {
+                            block:
+                                Some(BlockUpdate::Transformed {
+                                    ref to_unconstrained,
+                                    ..
+                                }),
+                            ..
+                        } = self.graph.nodes()[node].content
+                        {
+                            result.extend(to_unconstrained(&x));
+                        }
+                    } else {
+                        result.extend(x);
+                    }
+                }
+            }
+        }
+        LsVec(result)
+    }
+
+    ///The `GraphVar` corresponding to the flat vector `y`, and `ln|J|` of the transform
+    pub fn from_unconstrained<V>(&self, y: &V) -> (GraphVar<T>, T)
+    where
+        V: Index<usize, Output = T> + ?Sized,
+    {
+        let mut gv = self.template();
+        let mut log_jacobian = T::zero();
+        let mut k = 0;
+        //the nodes are in topological order, so that the bounds are evaluated at the new values
+        //of the parents
+        for c in &self.coords {
+            match *c {
+                Coord::Scalar {
+                    node,
+                    j,
+                    lower,
+                    upper,
+                } => {
+                    let u = y[k];
+                    k += 1;
+                    let (lo, hi) = self.graph.range(node, &gv).unwrap()[j];
+                    let (x, lj) = match (lower, upper) {
+                        (false, false) => (u, T::zero()),
+                        (true, false) => (lo + u.exp(), u),
+                        (false, true) => (hi - u.exp(), u),
+                        (true, true) => (
+                            lo + (hi - lo) * logistic(u),
+                            (hi - lo).ln() - softplus(u) - softplus(-u),
+                        ),
+                    };
+                    log_jacobian = log_jacobian + lj;
+                    self.graph.set_value_then_update(node, j, x, &mut gv);
+                }
+                Coord::Block {
+                    node,
+                    n,
+                    transformed,
+                } => {
+                    let u: Vec<T> = (k..k + n).map(|k| y[k]).collect();
+                    k += n;
+                    if transformed {
+                        if let NodeContent::StochasticNode {
+                            block:
+                                Some(BlockUpdate::Transformed {
+                                    ref from_unconstrained,
+                                    ..
+                                }),
+                            ..
+                        } = self.graph.nodes()[node].content
+                        {
+                            let (x, lj) = from_unconstrained(&u);
+                            log_jacobian = log_jacobian + lj;
+                            self.graph.set_values_then_update(node, &x, &mut gv);
+                        }
+                    } else {
+                        self.graph.set_values_then_update(node, &u, &mut gv);
+                    }
+                }
+            }
+        }
+        (gv, log_jacobian)
+    }
+
+    ///The log density of the flat vector `y`, i.e., `logpost_all` plus `ln|J|` of the transform,
+    ///to be sampled from
+    pub fn logpost<V>(&self, y: &V) -> T
+    where
+        V: Index<usize, Output = T> + ?Sized,
+    {
+        let (gv, log_jacobian) = self.from_unconstrained(y);
+        let lp = self.graph.logpost_all_no_update(&gv) + log_jacobian;
+        if lp.is_nan() {
+            T::neg_infinity()
+        } else {
+            lp
+        }
+    }
+
+    ///`logpost_all` at the values corresponding to `y`, to be maximized for the posterior mode
+    pub fn logpost_without_jacobian<V>(&self, y: &V) -> T
+    where
+        V: Index<usize, Output = T> + ?Sized,
+    {
+        let lp = self
+            .graph
+            .logpost_all_no_update(&self.from_unconstrained(y).0);
+        if lp.is_nan() {
+            T::neg_infinity()
+        } else {
+            lp
+        }
+    }
+
+    ///The values of the nodes with unobserved continuous values corresponding to `y`
+    pub fn values<V>(&self, y: &V) -> HashMap<K, Vec<T>>
+    where
+        V: Index<usize, Output = T> + ?Sized,
+    {
+        let gv = self.from_unconstrained(y).0;
+        self.coords
+            .iter()
+            .map(|c| match *c {
+                Coord::Scalar { node, .. } | Coord::Block { node, .. } => node,
+            })
+            .map(|i| {
+                (
+                    self.graph.key_of(i).clone(),
+                    self.graph.cached_values_of(i, &gv),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcmc::ensemble_sample::{sample, UpdateFlagSpec};
+    use crate::mcmc::graph::graph::NodeHandle;
+    use crate::mcmc::graph::graph::ParamObservability::{Observed, UnObserved};
+    use crate::mcmc::graph::mv_nodes::dirichlet_node;
+    use crate::mcmc::graph::nodes::{beta_node, const_node, gamma_node, normal_node};
+    use crate::opt::powell::fmin;
+    use crate::opt::tolerance::Tolerance;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn c(g: &mut Graph<String, f64>, k: &str, v: f64) -> (NodeHandle, usize) {
+        (const_node(v).add_to(g, &k.to_string()), 0)
+    }
+
+    fn test_graph() -> Graph<String, f64> {
+        let mut g = Graph::new();
+        let (a, b, z, one) = (
+            c(&mut g, "a", 3.0),
+            c(&mut g, "b", 2.0),
+            c(&mut g, "z", 0.0),
+            c(&mut g, "one", 1.0),
+        );
+        let five = c(&mut g, "five", 5.0);
+        let three = c(&mut g, "three", 3.0);
+        gamma_node(a, b)
+            .with_all_values(&[UnObserved(1.0)])
+            .add_to(&mut g, &"x".to_string());
+        let y = beta_node(b, five)
+            .with_all_values(&[UnObserved(0.5)])
+            .add_to(&mut g, &"y".to_string());
+        normal_node((y, 0), one)
+            .with_all_values(&[UnObserved(0.0)])
+            .add_to(&mut g, &"n".to_string());
+        normal_node(z, one)
+            .with_all_values(&[Observed(0.3)])
+            .add_to(&mut g, &"obs".to_string());
+        dirichlet_node(&[one, b, three]).add_to(&mut g, &"p".to_string());
+        g.seal();
+        g
+    }
+
+    #[test]
+    fn flat_test() {
+        let g = test_graph();
+        let gv = g.init_gv();
+        let fp = FlatPosterior::new(&g, &gv);
+        //x, y, n, and the two coordinates of the additive log-ratio of p
+        assert_eq!(fp.ndim(), 5);
+        assert_eq!(fp.keys()[4], ("p".to_string(), 1));
+
+        let u = fp.to_unconstrained(&gv);
+        let (gv1, log_jacobian) = fp.from_unconstrained(&u);
+        for k in ["x", "y", "n", "p"] {
+            let (v, v1) = (
+                g.values_of(&k.to_string(), &gv),
+                g.values_of(&k.to_string(), &gv1),
+            );
+            for (a, b) in v.iter().zip(v1.iter()) {
+                assert!((a - b).abs() < 1e-12, "{} {} {}", k, a, b);
+            }
+        }
+        assert!((fp.logpost(&u) - g.logpost_all(&gv) - log_jacobian).abs() < 1e-12);
+        assert!((fp.logpost_without_jacobian(&u) - g.logpost_all(&gv)).abs() < 1e-12);
+
+        let mut rng = StdRng::seed_from_u64(12345);
+        let nwalkers = 16;
+        let mut ensemble: Vec<_> = (0..nwalkers)
+            .map(|_| {
+                LsVec(
+                    u.iter()
+                        .map(|&u| u + rng.gen_range(-0.1..0.1))
+                        .collect::<Vec<f64>>(),
+                )
+            })
+            .collect();
+        let flogprob = |y: &LsVec<f64, Vec<f64>>| fp.logpost(y);
+        let mut lp: Vec<_> = ensemble.iter().map(flogprob).collect();
+        let mut xs = Vec::new();
+        for i in 0..6000 {
+            sample(
+                &flogprob,
+                &mut ensemble,
+                &mut lp,
+                &mut rng,
+                2.0,
+                &mut UpdateFlagSpec::All,
+            );
+            if i >= 1000 {
+                xs.extend(ensemble.iter().map(|y| fp.values(y)));
+            }
+        }
+        let n = xs.len() as f64;
+        let mean = |k: &str, j: usize| xs.iter().map(|v| v[k][j]).sum::<f64>() / n;
+        let var = |k: &str, j: usize| {
+            let m = mean(k, j);
+            xs.iter().map(|v| (v[k][j] - m).powi(2)).sum::<f64>() / n
+        };
+        for &(k, j, m, v) in &[
+            ("x", 0, 1.5, 0.75),
+            ("y", 0, 2.0 / 7.0, 10.0 / 49.0 / 8.0),
+            ("n", 0, 2.0 / 7.0, 1.0 + 10.0 / 49.0 / 8.0),
+            ("p", 2, 0.5, 0.25 / 7.0),
+        ] {
+            assert!(
+                (mean(k, j) - m).abs() < 0.05 * v.sqrt().max(m),
+                "{} {} {}",
+                k,
+                mean(k, j),
+                m
+            );
+            assert!((var(k, j) - v).abs() < 0.1 * v, "{} {} {}", k, var(k, j), v);
+        }
+    }
+
+    #[test]
+    fn map_test() {
+        let mut g = Graph::new();
+        let (z, ten, one) = (
+            c(&mut g, "z", 0.0),
+            c(&mut g, "ten", 10.0),
+            c(&mut g, "one", 1.0),
+        );
+        let (a, b) = (c(&mut g, "a", 3.0), c(&mut g, "b", 2.0));
+        let mu = normal_node(z, ten)
+            .with_all_values(&[UnObserved(0.0)])
+            .add_to(&mut g, &"mu".to_string());
+        gamma_node(a, b)
+            .with_all_values(&[UnObserved(1.0)])
+            .add_to(&mut g, &"x".to_string());
+        let data = [1.2, 0.7, 2.1, 1.5];
+        for (i, &d) in data.iter().enumerate() {
+            normal_node((mu, 0), one)
+                .with_all_values(&[Observed(d)])
+                .add_to(&mut g, &format!("d{}", i));
+        }
+        g.seal();
+        let gv = g.init_gv();
+        let fp = FlatPosterior::new(&g, &gv);
+        let (y, _) = fmin(
+            &|y: &LsVec<f64, Vec<f64>>| -fp.logpost_without_jacobian(y),
+            &fp.to_unconstrained(&gv),
+            Tolerance::Abs(1e-12),
+            1000,
+            &mut |_, _| {},
+        );
+        let v = fp.values(&y);
+        //the posterior mode of the normal mean with a normal prior
+        let expected = data.iter().sum::<f64>() / (data.len() as f64 + 0.01);
+        assert!(
+            (v["mu"][0] - expected).abs() < 1e-4,
+            "{} {}",
+            v["mu"][0],
+            expected
+        );
+        //the mode of gamma(3, 2)
+        assert!((v["x"][0] - 1.0).abs() < 1e-4, "{}", v["x"][0]);
+    }
+}
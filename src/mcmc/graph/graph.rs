@@ -2,7 +2,6 @@
 #![allow(clippy::new_without_default)]
 #![allow(clippy::type_complexity)]
 use std;
-use std::cell::RefCell;
 use std::collections::{BTreeSet, HashMap};
 use std::convert::From;
 use std::fmt::Debug;
@@ -13,15 +12,27 @@ use std::option::Option;
 use num::traits::{float::Float, identities::zero};
 
 use rand::distributions::uniform::SampleUniform;
-use rand::distributions::{Distribution, Standard};
-use rand::Rng;
+use rand::distributions::{Distribution, Open01, Standard, Uniform};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
 
-use super::super::arms::sample as arms;
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+use rayon::slice::ParallelSlice;
+
+use rand_distr::{Exp1, Gamma as GammaDist, StandardNormal};
+
+use super::super::arms::{init_points, sample as arms, trim_range};
+use super::super::slice::{
+    elliptical_slice, sample_univariate as slice_univariate, SliceMethod, SliceWidth,
+};
 use super::graph_var::GraphVar;
 use super::node::BasicNode;
+use super::node::BlockUpdate;
+use super::node::Family;
 use super::node::Node;
 use super::node::NodeContent;
 use super::node::ValueType;
+use crate::linear_space::type_wrapper::LsVec;
 #[derive(Debug)]
 pub struct NodeHandle(usize);
 
@@ -45,6 +56,9 @@ where
     num_of_fixed_vars: usize,
     num_of_deterministic_vars: usize,
     num_of_sampleable_vars: usize,
+    samplers: Vec<Option<Sampler>>,
+    ///Sets of nodes whose updates do not access the values written by each other
+    colours: Vec<Vec<usize>>,
 }
 
 impl<K, T> Display for Graph<K, T>
@@ -75,6 +89,24 @@ where
     }
 }
 
+///How a stochastic node is updated by `Graph::sample_all`, as chosen by `Graph::seal`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sampler {
+    Arms,
+    ///Exact draw from the enumerated support of a discrete node
+    Enumeration,
+    ///Joint update according to the node's `BlockUpdate`
+    Block,
+    ///Normal prior on the mean of normal children
+    NormalNormal,
+    ///Inverse gamma prior on the variance of `normal_var_node` children
+    NormalInvGamma,
+    ///Beta prior on the success probability of binomial or Bernoulli children
+    BetaBinomial,
+    ///Gamma prior on the mean of Poisson children
+    GammaPoisson,
+}
+
 pub enum ParamObservability<T> {
     Observed(T),
     UnObserved(T),
@@ -144,6 +176,76 @@ where
         self
     }
 
+    ///Declare the values of a stochastic node to be integers, whose finite support given by
+    ///`range` is enumerated by `Graph::sample` instead of calling ARMS
+    pub fn discrete(mut self) -> Self {
+        if let NodeContent::StochasticNode {
+            ref mut discrete, ..
+        } = self.n.content
+        {
+            *discrete = true;
+        } else {
+            panic!("It is not a stochastic node");
+        }
+        self
+    }
+
+    ///Update all the values of a stochastic node jointly with `b`
+    pub fn with_block(mut self, b: BlockUpdate<T>) -> Self {
+        if let NodeContent::StochasticNode { ref mut block, .. } = self.n.content {
+            *block = Some(b);
+        } else {
+            panic!("It is not a stochastic node");
+        }
+        self
+    }
+
+    ///Replicate a scalar stochastic node into a plate of `n` values that are conditionally
+    ///independent given the parents, which share one node, and its closures
+    pub fn plate(mut self, n: usize) -> Self {
+        match self.n.content {
+            NodeContent::StochasticNode {
+                ref mut is_observed,
+                ref mut values,
+                ref block,
+                ref mut plate,
+                ..
+            } if self.n.info.ndim_output == 1 && block.is_none() && !*plate => {
+                *is_observed = vec![is_observed[0]; n];
+                *values = vec![values[0]; n];
+                *plate = true;
+            }
+            _ => panic!("only a scalar stochastic node can be replicated"),
+        }
+        self.n.info.ndim_output = n;
+        self
+    }
+
+    ///Declare the distribution family of a scalar stochastic node, so that `Graph::seal` can
+    ///detect conjugacy. The parents must be in the order documented in `Family`.
+    pub fn with_family(mut self, f: Family) -> Self {
+        if let NodeContent::StochasticNode { ref mut family, .. } = self.n.content {
+            *family = f;
+        } else {
+            panic!("It is not a stochastic node");
+        }
+        self
+    }
+
+    ///Set the function drawing the values of a stochastic node given the parent values, used by
+    ///`Graph::simulate`
+    pub fn with_draw<F>(mut self, f: F) -> Self
+    where
+        F: 'static + Fn(&[T], &mut dyn RngCore) -> Vec<T> + Send + Sync,
+    {
+        if let NodeContent::StochasticNode { ref mut draw, .. } = self.n.content {
+            *draw = Some(Box::new(f));
+        } else {
+            panic!("It is not a stochastic node");
+        }
+        self
+    }
+
     pub fn add_to<K>(mut self, g: &mut Graph<K, T>, k: &K) -> NodeHandle
     where
         K: std::hash::Hash + Eq + Clone + Debug,
@@ -243,20 +345,53 @@ where
             num_of_fixed_vars: 0,
             num_of_deterministic_vars: 0,
             num_of_sampleable_vars: 0,
+            samplers: Vec::new(),
+            colours: Vec::new(),
         }
     }
 
     pub fn enumerate_children_by_kind(&self, nid: usize) -> (BTreeSet<usize>, BTreeSet<usize>) {
+        self.enumerate_descendants_by_kind(nid, self.nodes[nid].get_children())
+    }
+
+    ///The stochastic and the deterministic children that read the `j`-th value of the node
+    ///`nid`, directly or through deterministic nodes
+    pub fn enumerate_element_children_by_kind(
+        &self,
+        nid: usize,
+        j: usize,
+    ) -> (BTreeSet<usize>, BTreeSet<usize>) {
+        let children: Vec<usize> = self.nodes[nid]
+            .get_children()
+            .iter()
+            .filter(|&&c| self.nodes[c].info.parents.contains(&(nid, j)))
+            .cloned()
+            .collect();
+        self.enumerate_descendants_by_kind(nid, &children)
+    }
+
+    ///The stochastic nodes and the deterministic nodes among `children` of the node `nid`, and
+    ///among the children of those deterministic nodes, recursively
+    fn enumerate_descendants_by_kind(
+        &self,
+        nid: usize,
+        children: &[usize],
+    ) -> (BTreeSet<usize>, BTreeSet<usize>) {
         type Stack = Vec<usize>;
         let mut result0 = BTreeSet::<usize>::new();
         let mut result1 = BTreeSet::<usize>::new();
         let mut stack = Stack::new();
         stack.push(nid);
         #[allow(clippy::manual_while_let_some)]
-        while !stack.is_empty() {            
+        while !stack.is_empty() {
             let top = stack.pop().unwrap();
+            let top_children = if top == nid {
+                children
+            } else {
+                self.nodes[top].get_children()
+            };
 
-            for i in self.nodes[top].get_children() {
+            for i in top_children {
                 match self.nodes[*i].content {
                     NodeContent::StochasticNode { .. } => {
                         result0.insert(*i);
@@ -274,22 +409,243 @@ where
     pub fn seal(&mut self) {
         for i in 0..self.nodes.len() {
             let (s, d) = self.enumerate_children_by_kind(i);
+            let e: Vec<(Vec<usize>, Vec<usize>)> = if self.is_plate(i) {
+                (0..self.nodes[i].info.ndim_output)
+                    .map(|j| {
+                        let (s, d) = self.enumerate_element_children_by_kind(i, j);
+                        (Vec::from_iter(s), Vec::from_iter(d))
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
             if let NodeContent::StochasticNode {
                 ref mut all_stochastic_children,
                 ref mut all_deterministic_children,
+                ref mut element_children,
                 ..
             } = self.nodes[i].content
             {
                 *all_stochastic_children = Vec::from_iter(s);
                 *all_deterministic_children = Vec::from_iter(d);
+                *element_children = e;
             }
         }
+        self.samplers = (0..self.nodes.len())
+            .map(|i| self.choose_sampler(i))
+            .collect();
+        self.colours = self.colour_nodes();
+    }
+
+    ///The nodes whose values are written when the stochastic node `i` is updated, i.e., `i` and
+    ///its deterministic children
+    fn written_by(&self, i: usize) -> BTreeSet<usize> {
+        match self.nodes[i].content {
+            NodeContent::StochasticNode {
+                ref all_deterministic_children,
+                ..
+            } => std::iter::once(i)
+                .chain(all_deterministic_children.iter().cloned())
+                .collect(),
+            _ => panic!("not a stochastic node"),
+        }
+    }
+
+    ///The nodes whose values are written when the stochastic node `i` is updated, and those
+    ///that are read or written
+    fn footprint(&self, i: usize) -> (BTreeSet<usize>, BTreeSet<usize>) {
+        let stochastic = match self.nodes[i].content {
+            NodeContent::StochasticNode {
+                ref all_stochastic_children,
+                ..
+            } => all_stochastic_children,
+            _ => panic!("not a stochastic node"),
+        };
+        let written = self.written_by(i);
+        let accessed = written
+            .iter()
+            .chain(stochastic.iter())
+            .flat_map(|&k| {
+                std::iter::once(k).chain(self.nodes[k].info.parents.iter().map(|&(p, _)| p))
+            })
+            .collect();
+        (written, accessed)
+    }
+
+    ///Greedy colouring of the nodes with unobserved values in topological order, such that no
+    ///node of a colour accesses the values written by another node of the same colour
+    fn colour_nodes(&self) -> Vec<Vec<usize>> {
+        let updated: Vec<usize> = (0..self.nodes.len())
+            .filter(|&i| {
+                matches!(self.nodes[i].content,
+                NodeContent::StochasticNode { ref is_observed, .. } if is_observed.iter().any(|&x| !x))
+            })
+            .collect();
+        let footprints: Vec<_> = updated.iter().map(|&i| self.footprint(i)).collect();
+        let mut writers = vec![Vec::new(); self.nodes.len()];
+        let mut accessors = vec![Vec::new(); self.nodes.len()];
+        for (u, (written, accessed)) in footprints.iter().enumerate() {
+            for &k in written {
+                writers[k].push(u);
+            }
+            for &k in accessed {
+                accessors[k].push(u);
+            }
+        }
+        let mut colour_of = Vec::with_capacity(updated.len());
+        let mut colours: Vec<Vec<usize>> = Vec::new();
+        for (u, (written, accessed)) in footprints.iter().enumerate() {
+            let used: BTreeSet<usize> = accessed
+                .iter()
+                .flat_map(|&k| writers[k].iter())
+                .chain(written.iter().flat_map(|&k| accessors[k].iter()))
+                .filter(|&&v| v < u)
+                .map(|&v| colour_of[v])
+                .collect();
+            let c = (0..).find(|c| !used.contains(c)).unwrap();
+            if c == colours.len() {
+                colours.push(Vec::new());
+            }
+            colours[c].push(updated[u]);
+            colour_of.push(c);
+        }
+        colours
+    }
+
+    ///The sets of nodes updated in parallel by `sample_all`, in the order of the updates
+    pub fn colours(&self) -> Vec<Vec<K>> {
+        self.colours
+            .iter()
+            .map(|c| c.iter().map(|i| self.node_key_map[i].clone()).collect())
+            .collect()
+    }
+
+    fn family_of(&self, i: usize) -> Family {
+        match self.nodes[i].content {
+            NodeContent::StochasticNode { family, .. } => family,
+            _ => Family::Other,
+        }
+    }
+
+    ///Whether node `c` depends on node `i` only directly through its `k`-th parent, i.e. none of
+    ///its other parents is `i` or one of the deterministic nodes computed from it
+    fn is_parent_only_at(&self, c: usize, i: usize, k: usize) -> bool {
+        let deterministic_children = match self.nodes[i].content {
+            NodeContent::StochasticNode {
+                ref all_deterministic_children,
+                ..
+            } => all_deterministic_children,
+            _ => return false,
+        };
+        let parents = &self.nodes[c].info.parents;
+        parents.len() > k
+            && parents[k] == (i, 0)
+            && parents
+                .iter()
+                .enumerate()
+                .all(|(q, &(p, _))| q == k || (p != i && !deterministic_children.contains(&p)))
+    }
+
+    fn choose_sampler(&self, i: usize) -> Option<Sampler> {
+        let (children, is_observed, family) = match self.nodes[i].content {
+            NodeContent::StochasticNode {
+                ref all_stochastic_children,
+                ref is_observed,
+                ref block,
+                discrete,
+                family,
+                ..
+            } => {
+                if is_observed.iter().all(|&x| x) {
+                    return None;
+                } else if block.is_some() {
+                    return Some(Sampler::Block);
+                } else if discrete {
+                    return Some(Sampler::Enumeration);
+                }
+                (all_stochastic_children, is_observed, family)
+            }
+            _ => return None,
+        };
+        //without children, the prior itself is sampled with ARMS
+        if is_observed.len() != 1 || children.is_empty() {
+            return Some(Sampler::Arms);
+        }
+        let all_children = |f: &dyn Fn(Family) -> Option<usize>| {
+            children.iter().all(|&c| {
+                (self.nodes[c].info.ndim_output == 1 || self.is_plate(c))
+                    && f(self.family_of(c)).is_some_and(|k| self.is_parent_only_at(c, i, k))
+            })
+        };
+        let conjugate = match family {
+            Family::Normal | Family::NormalVar => Some(Sampler::NormalNormal).filter(|_| {
+                all_children(&|f| match f {
+                    Family::Normal | Family::NormalVar => Some(0),
+                    _ => None,
+                })
+            }),
+            Family::InvGamma => Some(Sampler::NormalInvGamma).filter(|_| {
+                all_children(&|f| match f {
+                    Family::NormalVar => Some(1),
+                    _ => None,
+                })
+            }),
+            Family::Beta => Some(Sampler::BetaBinomial).filter(|_| {
+                all_children(&|f| match f {
+                    Family::Binomial | Family::Bernoulli => Some(0),
+                    _ => None,
+                })
+            }),
+            Family::Gamma => Some(Sampler::GammaPoisson).filter(|_| {
+                all_children(&|f| match f {
+                    Family::Poisson => Some(0),
+                    _ => None,
+                })
+            }),
+            _ => None,
+        };
+        Some(conjugate.unwrap_or(Sampler::Arms))
+    }
+
+    ///The sampler chosen by `seal` for the node `k`, `None` if it is deterministic or fully
+    ///observed, or if the graph has not been sealed
+    pub fn sampler_of(&self, k: &K) -> Option<Sampler> {
+        self.samplers
+            .get(self.key_node_map[k])
+            .cloned()
+            .unwrap_or(None)
+    }
+
+    pub fn samplers(&self) -> HashMap<K, Sampler> {
+        self.samplers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, s)| s.map(|s| (self.node_key_map[&i].clone(), s)))
+            .collect()
     }
 
     pub fn get_node(&self, k: &K) -> &Node<T> {
         &self.nodes[self.key_node_map[k]]
     }
 
+    ///All the nodes, in the order of their indices
+    pub fn nodes(&self) -> &[Node<T>] {
+        &self.nodes
+    }
+
+    pub fn key_of(&self, i: usize) -> &K {
+        &self.node_key_map[&i]
+    }
+
+    pub fn contains_key(&self, k: &K) -> bool {
+        self.key_node_map.contains_key(k)
+    }
+
+    ///The current values of the node `k`
+    pub fn values_of(&self, k: &K, gv: &GraphVar<T>) -> Vec<T> {
+        self.cached_values_of(self.key_node_map[k], gv)
+    }
+
     pub fn topology(&self) -> HashMap<K, Vec<(K, usize)>> {
         let mut result = HashMap::new();
         for (tag, &node_idx) in self.key_node_map.iter() {
@@ -305,45 +661,49 @@ where
 
     pub fn init_gv(&self) -> GraphVar<T> {
         let mut gv = GraphVar {
-            fixed_values: RefCell::new(Vec::new()),
-            deterministic_values: RefCell::new(Vec::new()),
+            fixed_values: Vec::new(),
+            deterministic_values: Vec::new(),
             sampleable_values: Vec::new(),
-            old_fixed_values: RefCell::new(Vec::new()),
-            old_deterministic_values: RefCell::new(Vec::new()),
-            old_sampleable_values: RefCell::new(Vec::new()),
+            old_fixed_values: Vec::new(),
+            old_deterministic_values: Vec::new(),
+            old_sampleable_values: Vec::new(),
         };
 
-        gv.fixed_values
-            .borrow_mut()
-            .resize(self.num_of_fixed_vars, zero());
-        gv.old_fixed_values
-            .borrow_mut()
-            .resize(self.num_of_fixed_vars, None);
+        gv.fixed_values.resize(self.num_of_fixed_vars, zero());
+        gv.old_fixed_values.resize(self.num_of_fixed_vars, None);
         gv.deterministic_values
-            .borrow_mut()
             .resize(self.num_of_deterministic_vars, zero());
         gv.old_deterministic_values
-            .borrow_mut()
             .resize(self.num_of_deterministic_vars, None);
         gv.sampleable_values
             .resize(self.num_of_sampleable_vars, zero());
         gv.old_sampleable_values
-            .borrow_mut()
             .resize(self.num_of_sampleable_vars, None);
 
         for (i, n) in self.nodes.iter().enumerate() {
             match n.content {
                 NodeContent::DeterministicNode { .. } => {
-                    self.update_deterministic_value_of(i, &gv);
+                    self.update_deterministic_value_of(i, &mut gv);
                 }
                 NodeContent::StochasticNode {
                     ref is_observed,
                     ref values,
+                    ref block,
                     ..
                 } => {
+                    let mut values = values.clone();
+                    if let Some(BlockUpdate::CountTransfer) = *block {
+                        let total = *self.parent_values_of(i, &gv).last().unwrap();
+                        if is_observed.iter().all(|&x| !x)
+                            && values.iter().fold(zero::<T>(), |s, &x| s + x) != total
+                        {
+                            values = vec![zero(); values.len()];
+                            values[0] = total;
+                        }
+                    }
                     for (j, p) in n.info.idx_in_var.iter().enumerate() {
                         if is_observed[j] {
-                            gv.fixed_values.borrow_mut()[*p] = values[j];
+                            gv.fixed_values[*p] = values[j];
                         } else {
                             gv[*p] = values[j];
                         }
@@ -356,25 +716,22 @@ where
 
     pub fn cached_value_of(&self, i: usize, j: usize, gv: &GraphVar<T>) -> T {
         match self.nodes[i].info.value_type[j] {
-            ValueType::DETERMINISTIC => {
-                gv.deterministic_values.borrow()[self.nodes[i].info.idx_in_var[j]]
-            }
-            ValueType::FIXED => gv.fixed_values.borrow()[self.nodes[i].info.idx_in_var[j]],
+            ValueType::DETERMINISTIC => gv.deterministic_values[self.nodes[i].info.idx_in_var[j]],
+            ValueType::FIXED => gv.fixed_values[self.nodes[i].info.idx_in_var[j]],
             ValueType::SAMPLEABLE => gv.sampleable_values[self.nodes[i].info.idx_in_var[j]],
         }
     }
 
-    pub fn store_cache_value_of(&self, i: usize, j: usize, v: T, gv: &GraphVar<T>) {
+    pub fn store_cache_value_of(&self, i: usize, j: usize, v: T, gv: &mut GraphVar<T>) {
         match self.nodes[i].info.value_type[j] {
             ValueType::DETERMINISTIC => {
-                gv.old_deterministic_values.borrow_mut()[self.nodes[i].info.idx_in_var[j]] =
-                    Some(v);
+                gv.old_deterministic_values[self.nodes[i].info.idx_in_var[j]] = Some(v);
             }
             ValueType::FIXED => {
-                gv.old_fixed_values.borrow_mut()[self.nodes[i].info.idx_in_var[j]] = Some(v);
+                gv.old_fixed_values[self.nodes[i].info.idx_in_var[j]] = Some(v);
             }
             ValueType::SAMPLEABLE => {
-                gv.old_sampleable_values.borrow_mut()[self.nodes[i].info.idx_in_var[j]] = Some(v);
+                gv.old_sampleable_values[self.nodes[i].info.idx_in_var[j]] = Some(v);
             }
         };
     }
@@ -390,12 +747,10 @@ where
     pub fn old_cached_value_of(&self, i: usize, j: usize, gv: &GraphVar<T>) -> Option<T> {
         match self.nodes[i].info.value_type[j] {
             ValueType::DETERMINISTIC => {
-                gv.old_deterministic_values.borrow()[self.nodes[i].info.idx_in_var[j]]
-            }
-            ValueType::FIXED => gv.old_fixed_values.borrow()[self.nodes[i].info.idx_in_var[j]],
-            ValueType::SAMPLEABLE => {
-                gv.old_sampleable_values.borrow()[self.nodes[i].info.idx_in_var[j]]
+                gv.old_deterministic_values[self.nodes[i].info.idx_in_var[j]]
             }
+            ValueType::FIXED => gv.old_fixed_values[self.nodes[i].info.idx_in_var[j]],
+            ValueType::SAMPLEABLE => gv.old_sampleable_values[self.nodes[i].info.idx_in_var[j]],
         }
     }
 
@@ -421,7 +776,7 @@ where
         result
     }
 
-    pub fn store_parent_values_of(&self, i: usize, v: &[T], gv: &GraphVar<T>) {
+    pub fn store_parent_values_of(&self, i: usize, v: &[T], gv: &mut GraphVar<T>) {
         let node = &self.nodes[i];
         for j in 0..node.info.ndim_input {
             let (p, k) = node.info.parents[j];
@@ -430,7 +785,7 @@ where
         }
     }
 
-    pub fn update_deterministic_value_of(&self, i: usize, gv: &GraphVar<T>) {
+    pub fn update_deterministic_value_of(&self, i: usize, gv: &mut GraphVar<T>) {
         let pv = self.parent_values_of(i, gv);
 
         let node = &self.nodes[i];
@@ -440,13 +795,13 @@ where
                 for (m, k) in node.info.idx_in_var.iter().enumerate() {
                     match node.info.value_type[m] {
                         ValueType::FIXED => {
-                            gv.fixed_values.borrow_mut()[*k] = v[m];
-                            gv.old_fixed_values.borrow_mut()[*k] = Some(v[m]);
+                            gv.fixed_values[*k] = v[m];
+                            gv.old_fixed_values[*k] = Some(v[m]);
                             //panic!("Impossible")
                         }
                         ValueType::DETERMINISTIC => {
-                            gv.deterministic_values.borrow_mut()[*k] = v[m];
-                            gv.old_deterministic_values.borrow_mut()[*k] = Some(v[m]);
+                            gv.deterministic_values[*k] = v[m];
+                            gv.old_deterministic_values[*k] = Some(v[m]);
                         }
                         ValueType::SAMPLEABLE => panic!("Impossible"),
                     }
@@ -458,7 +813,7 @@ where
                     match node.info.value_type[m] {
                         ValueType::FIXED => {
                             //panic!("Impossible")
-                            gv.fixed_values.borrow_mut()[*k] = values[m];
+                            gv.fixed_values[*k] = values[m];
                         }
                         ValueType::DETERMINISTIC => {
                             panic!("Impossible");
@@ -471,24 +826,96 @@ where
     }
 
     pub fn logprob(&self, i: usize, gv: &GraphVar<T>) -> T {
-        if let NodeContent::StochasticNode { ref logprob, .. } = self.nodes[i].content {
+        if let NodeContent::StochasticNode {
+            ref logprob, plate, ..
+        } = self.nodes[i].content
+        {
             let x = self.cached_values_of(i, gv);
             let p = self.parent_values_of(i, gv);
-            logprob(x.as_slice(), p.as_slice())
+            if plate {
+                x.iter()
+                    .fold(zero(), |a: T, x| a + logprob(std::slice::from_ref(x), &p))
+            } else {
+                logprob(x.as_slice(), p.as_slice())
+            }
         } else {
             panic!("not a stochastic node");
         }
     }
 
     pub fn range(&self, i: usize, gv: &GraphVar<T>) -> Option<Vec<(T, T)>> {
-        if let NodeContent::StochasticNode { ref range, .. } = self.nodes[i].content {
+        if let NodeContent::StochasticNode {
+            ref range, plate, ..
+        } = self.nodes[i].content
+        {
             let p = self.parent_values_of(i, gv);
-            Some(range(p.as_slice()))
+            if plate {
+                Some(vec![range(p.as_slice())[0]; self.nodes[i].info.ndim_output])
+            } else {
+                Some(range(p.as_slice()))
+            }
         } else {
             None
         }
     }
 
+    pub fn is_plate(&self, i: usize) -> bool {
+        matches!(
+            self.nodes[i].content,
+            NodeContent::StochasticNode { plate: true, .. }
+        )
+    }
+
+    pub fn is_discrete(&self, i: usize) -> bool {
+        matches!(
+            self.nodes[i].content,
+            NodeContent::StochasticNode { discrete: true, .. }
+        )
+    }
+
+    ///Draw the `j`-th value of the discrete node `i` from its exact full conditional by
+    ///enumerating the support
+    pub fn sample_discrete<R>(&self, i: usize, j: usize, gv: &mut GraphVar<T>, rng: &mut R) -> T
+    where
+        R: Rng,
+    {
+        let (x1, x2) = self.range(i, gv).unwrap()[j];
+        let (lo, hi) = (x1.ceil(), x2.floor());
+        if !(lo.is_finite() && hi.is_finite()) || hi < lo {
+            panic!(
+                "the support of {:?} must be finite and nonempty",
+                self.node_key_map[&i]
+            );
+        }
+        let nvalues = (hi - lo).to_usize().unwrap() + 1;
+        let lp: Vec<T> = (0..nvalues)
+            .map(|k| {
+                self.set_value_then_update(i, j, lo + T::from(k).unwrap(), gv);
+                self.logpost_at(i, j, gv)
+            })
+            .collect();
+        let lpmax = lp
+            .iter()
+            .fold(T::neg_infinity(), |a, &b| if b > a { b } else { a });
+        if lpmax == T::neg_infinity() {
+            panic!("error when sampling {:?}", self.node_key_map[&i]);
+        }
+        let w: Vec<T> = lp.iter().map(|&l| (l - lpmax).exp()).collect();
+        let total = w.iter().fold(zero(), |a: T, &b| a + b);
+        let u = rng.sample(Uniform::new(T::zero(), total));
+        let mut cum = zero();
+        let k = w
+            .iter()
+            .position(|&w| {
+                cum = cum + w;
+                u < cum
+            })
+            .unwrap_or(nvalues - 1);
+        let x = lo + T::from(k).unwrap();
+        self.set_value_then_update(i, j, x, gv);
+        x
+    }
+
     pub fn sample<R>(
         &self,
         i: usize,
@@ -498,57 +925,410 @@ where
         n: usize,
         nchanged: &mut usize,
     ) where
+        K: Sync,
         R: Rng,
+        Exp1: Distribution<T>,
+        StandardNormal: Distribution<T>,
+        Open01: Distribution<T>,
     {
+        if let Some(Some(sampler)) = self.samplers.get(i) {
+            if !matches!(
+                sampler,
+                Sampler::Arms | Sampler::Enumeration | Sampler::Block
+            ) {
+                let x = self.sample_conjugate(i, *sampler, gv, rng);
+                self.set_value_then_update(i, j, x, gv);
+                *nchanged += 1;
+                return;
+            }
+        }
+        if self.is_discrete(i) {
+            let x0 = self.cached_value_of(i, j, gv);
+            if self.sample_discrete(i, j, gv, rng) != x0 {
+                *nchanged += 1;
+            }
+            return;
+        }
         let range = self.range(i, gv).unwrap();
         let x0 = self.cached_value_of(i, j, gv);
         let (x1, x2) = range[j];
         assert!(x2 > x1);
         //let initx=vec![x1+(x2-x1)*(T::from(0.3).unwrap()), (x1+x2)*(T::from(0.5).unwrap()), x1+(x2-x1)*T::from(0.6).unwrap()];
-        let mut initx = Vec::new();
-        for k in 0..n {
-            initx.push(x1 + (x2 - x1) / (T::from(n + 2).unwrap()) * T::from(k).unwrap())
-        }
-
-        let x = arms(
-            &|x| {
-                let mut gv = gv.clone();
-                self.set_value_then_update(i, j, x, &mut gv);
-                self.logpost(i, &gv)
-            },
-            range[j],
-            &initx,
-            x0,
-            10,
-            rng,
-            nchanged,
-        )
-        .unwrap_or_else(|_| panic!("error when sampling {:?}", self.node_key_map[&i]));
+        let pd = |x| {
+            let mut gv = gv.clone();
+            self.set_value_then_update(i, j, x, &mut gv);
+            self.logpost_at(i, j, &gv)
+        };
+        //the children may restrict the support, e.g., uniform children of an upper bound
+        let range = trim_range(&pd, range[j], x0);
+        let initx = init_points(&pd, range, x0, n);
+
+        let x = arms(&pd, range, &initx, x0, 10, rng, nchanged)
+            .unwrap_or_else(|e| panic!("error when sampling {:?}: {:?}", self.node_key_map[&i], e));
         self.set_value_then_update(i, j, x, gv);
     }
 
+    ///Draw the scalar node `i` from its closed-form full conditional
+    fn sample_conjugate<R>(&self, i: usize, sampler: Sampler, gv: &GraphVar<T>, rng: &mut R) -> T
+    where
+        R: Rng,
+        Exp1: Distribution<T>,
+        StandardNormal: Distribution<T>,
+        Open01: Distribution<T>,
+    {
+        let p = self.parent_values_of(i, gv);
+        let (one, two) = (T::one(), T::one() + T::one());
+        let gamma = |a: T, rng: &mut R| GammaDist::new(a, one).unwrap().sample(rng);
+        let children = match self.nodes[i].content {
+            NodeContent::StochasticNode {
+                ref all_stochastic_children,
+                ..
+            } => all_stochastic_children,
+            _ => unreachable!(),
+        };
+        //the value and the parent values of each child
+        let parent_values: Vec<Vec<T>> = children
+            .iter()
+            .map(|&c| self.parent_values_of(c, gv))
+            .collect();
+        let data = children
+            .iter()
+            .zip(parent_values.iter())
+            .flat_map(|(&c, pc)| {
+                let f = self.family_of(c);
+                (0..self.nodes[c].info.ndim_output)
+                    .map(move |j| (f, self.cached_value_of(c, j, gv), pc))
+            });
+        let variance = |f: Family, s: T| if f == Family::Normal { s * s } else { s };
+        match sampler {
+            Sampler::NormalNormal => {
+                let v0 = variance(self.family_of(i), p[1]);
+                let (prec, wm) = data.fold((one / v0, p[0] / v0), |(prec, wm), (f, y, pc)| {
+                    let v = variance(f, pc[1]);
+                    (prec + one / v, wm + y / v)
+                });
+                let z: T = rng.sample(StandardNormal);
+                wm / prec + z / prec.sqrt()
+            }
+            Sampler::NormalInvGamma => {
+                let (a, b) = data.fold((p[0], p[1]), |(a, b), (_, y, pc)| {
+                    (a + one / two, b + (y - pc[0]) * (y - pc[0]) / two)
+                });
+                (b / gamma(a, rng)).max(T::min_positive_value())
+            }
+            Sampler::BetaBinomial => {
+                let (a, b) = data.fold((p[0], p[1]), |(a, b), (f, k, pc)| {
+                    let n = if f == Family::Binomial { pc[1] } else { one };
+                    (a + k, b + n - k)
+                });
+                let (x, y) = (gamma(a, rng), gamma(b, rng));
+                (x / (x + y))
+                    .max(T::min_positive_value())
+                    .min(one - T::epsilon())
+            }
+            Sampler::GammaPoisson => {
+                let (a, b) = data.fold((p[0], p[1]), |(a, b), (_, k, _)| (a + k, b + one));
+                (gamma(a, rng) / b).max(T::min_positive_value())
+            }
+            _ => panic!("{:?} is not a conjugate sampler", sampler),
+        }
+    }
+
+    ///Update all the values of the block node `i` jointly according to its `BlockUpdate`
+    pub fn sample_block<R>(&self, i: usize, gv: &mut GraphVar<T>, rng: &mut R) -> bool
+    where
+        R: Rng,
+        Exp1: Distribution<T>,
+        StandardNormal: Distribution<T>,
+    {
+        let block = match self.nodes[i].content {
+            NodeContent::StochasticNode {
+                block: Some(ref block),
+                ..
+            } => block,
+            _ => panic!("not a block node"),
+        };
+        let x0 = self.cached_values_of(i, gv);
+        let p = self.parent_values_of(i, gv);
+        let ndim = x0.len();
+        match block {
+            BlockUpdate::Elliptical(f) => {
+                let (m, l) = f(&p);
+                let floglik = |x: &LsVec<T, Vec<T>>| {
+                    let mut gv = gv.clone();
+                    self.set_values_then_update(i, x, &mut gv);
+                    self.likelihood(i, &gv)
+                };
+                let prior_sample = |rng: &mut R| {
+                    let eta: Vec<T> = (0..ndim).map(|_| rng.sample(StandardNormal)).collect();
+                    LsVec(
+                        (0..ndim)
+                            .map(|k| (0..=k).fold(m[k], |s, q| s + l[k][q] * eta[q]))
+                            .collect::<Vec<T>>(),
+                    )
+                };
+                let mut x = LsVec(x0);
+                let mut ll = floglik(&x);
+                elliptical_slice(
+                    &floglik,
+                    &mut x,
+                    &mut ll,
+                    &prior_sample,
+                    Some(&LsVec(m.clone())),
+                    rng,
+                );
+                self.set_values_then_update(i, &x, gv);
+                true
+            }
+            BlockUpdate::Transformed {
+                to_unconstrained,
+                from_unconstrained,
+            } => {
+                let u0 = to_unconstrained(&x0);
+                let d: Vec<T> = (0..u0.len()).map(|_| rng.sample(StandardNormal)).collect();
+                let norm = d.iter().fold(T::zero(), |s, &x| s + x * x).sqrt();
+                let along = |t: T| -> Vec<T> {
+                    u0.iter()
+                        .zip(d.iter())
+                        .map(|(&u, &d)| u + t * d / norm)
+                        .collect()
+                };
+                let pd = |t: T| {
+                    let (x, log_jacobian) = from_unconstrained(&along(t));
+                    let mut gv = gv.clone();
+                    self.set_values_then_update(i, &x, &mut gv);
+                    self.logpost(i, &gv) + log_jacobian
+                };
+                let lp0 = pd(T::zero());
+                let (t, _) = slice_univariate(
+                    &pd,
+                    T::zero(),
+                    lp0,
+                    (T::neg_infinity(), T::infinity()),
+                    &mut SliceWidth::new(T::one()),
+                    SliceMethod::default(),
+                    rng,
+                );
+                let (x, _) = from_unconstrained(&along(t));
+                self.set_values_then_update(i, &x, gv);
+                t != T::zero()
+            }
+            BlockUpdate::CountTransfer => {
+                let mut changed = false;
+                let mut lp = self.logpost(i, gv);
+                for _ in 0..ndim {
+                    let a = rng.gen_range(0..ndim);
+                    let b = (a + rng.gen_range(1..ndim)) % ndim;
+                    let mut x = self.cached_values_of(i, gv);
+                    if x[a] < T::one() {
+                        continue;
+                    }
+                    x[a] = x[a] - T::one();
+                    x[b] = x[b] + T::one();
+                    let mut gv1 = gv.clone();
+                    self.set_values_then_update(i, &x, &mut gv1);
+                    let lp1 = self.logpost(i, &gv1);
+                    if rng.sample(Uniform::new(T::zero(), T::one())).ln() < lp1 - lp {
+                        *gv = gv1;
+                        lp = lp1;
+                        changed = true;
+                    }
+                }
+                changed
+            }
+        }
+    }
+
+    ///Update the unobserved values of the stochastic node `i`, returning the number of changed
+    ///values, or one for a changed block node
+    fn update_node<R>(&self, i: usize, gv: &mut GraphVar<T>, rng: &mut R, n: usize) -> usize
+    where
+        K: Sync,
+        R: Rng,
+        Exp1: Distribution<T>,
+        StandardNormal: Distribution<T>,
+        Open01: Distribution<T>,
+    {
+        let mut nchanged = 0;
+        if let Node {
+            content:
+                NodeContent::StochasticNode {
+                    ref is_observed,
+                    ref block,
+                    ..
+                },
+            info: BasicNode { ndim_output, .. },
+        } = self.nodes[i]
+        {
+            if block.is_some() {
+                if is_observed.iter().all(|&x| x) {
+                    return 0;
+                }
+                if is_observed.iter().any(|&x| x) {
+                    panic!(
+                        "block node {:?} must be either observed or unobserved as a whole",
+                        self.node_key_map[&i]
+                    );
+                }
+                if self.sample_block(i, gv, rng) {
+                    nchanged += 1;
+                }
+                return nchanged;
+            }
+            for j in 0..ndim_output {
+                if !is_observed[j] {
+                    let mut change_count = 0;
+                    self.sample(i, j, gv, rng, n, &mut change_count);
+                    if change_count > 0 {
+                        nchanged += 1;
+                    }
+                }
+            }
+        }
+        nchanged
+    }
+
+    ///Update all the unobserved values, one set of `colours` after another. The nodes of a set
+    ///are split among the threads of rayon, each updating its own copy of `gv` with one
+    ///generator per node seeded from `rng`, so that the draws do not depend on the number of
+    ///threads. Only the new values of the nodes are copied back to `gv`, whose deterministic
+    ///values are then computed once per set. The graph must be sealed.
     pub fn sample_all<R>(&self, gv: &mut GraphVar<T>, rng: &mut R, n: usize, nchanged: &mut usize)
+    where
+        K: Sync,
+        R: Rng,
+        Exp1: Distribution<T>,
+        StandardNormal: Distribution<T>,
+        Open01: Distribution<T>,
+    {
+        assert!(
+            self.samplers.len() == self.nodes.len(),
+            "the graph must be sealed before sampling, and again after adding nodes"
+        );
+        let nthreads = rayon::current_num_threads();
+        //the copies of gv are kept across the sets, and only the values written since they were
+        //last used are copied to them
+        let mut locals: Vec<GraphVar<T>> = Vec::new();
+        let mut stale = BTreeSet::new();
+        for colour in &self.colours {
+            let seeds: Vec<u64> = colour.iter().map(|_| rng.gen::<u64>()).collect();
+            if colour.len() == 1 {
+                let mut rng1 = StdRng::seed_from_u64(seeds[0]);
+                *nchanged += self.update_node(colour[0], gv, &mut rng1, n);
+                stale.extend(self.written_by(colour[0]));
+                continue;
+            }
+            #[allow(clippy::manual_div_ceil)]
+            let chunk_size = (colour.len() + nthreads - 1) / nthreads;
+            #[allow(clippy::manual_div_ceil)]
+            let nchunks = (colour.len() + chunk_size - 1) / chunk_size;
+            let shared: &GraphVar<T> = gv;
+            locals.par_iter_mut().for_each(|local| {
+                for &i in &stale {
+                    self.copy_values_of(i, shared, local);
+                }
+            });
+            stale.clear();
+            while locals.len() < nchunks {
+                locals.push(shared.clone());
+            }
+            let results: Vec<(Vec<Vec<T>>, usize)> = locals[..nchunks]
+                .par_iter_mut()
+                .zip(colour.par_chunks(chunk_size))
+                .zip(seeds.par_chunks(chunk_size))
+                .map(|((local, nodes), seeds)| {
+                    let changed = nodes
+                        .iter()
+                        .zip(seeds.iter())
+                        .map(|(&i, &s)| {
+                            self.update_node(i, local, &mut StdRng::seed_from_u64(s), n)
+                        })
+                        .sum();
+                    let values = nodes.iter().map(|&i| self.cached_values_of(i, local));
+                    (values.collect(), changed)
+                })
+                .collect();
+            for ((values, changed), nodes) in results.iter().zip(colour.chunks(chunk_size)) {
+                for (&i, x) in nodes.iter().zip(values.iter()) {
+                    for (j, &x) in x.iter().enumerate() {
+                        self.set_value_no_update(i, j, x, gv);
+                    }
+                }
+                *nchanged += changed;
+            }
+            let written: BTreeSet<usize> =
+                colour.iter().flat_map(|&i| self.written_by(i)).collect();
+            for &i in &written {
+                if let NodeContent::DeterministicNode { .. } = self.nodes[i].content {
+                    self.update_deterministic_value_of(i, gv);
+                }
+            }
+            stale.extend(written);
+        }
+    }
+
+    ///Copy the values of the node `i` from `src` to `dst`
+    fn copy_values_of(&self, i: usize, src: &GraphVar<T>, dst: &mut GraphVar<T>) {
+        let info = &self.nodes[i].info;
+        for (m, &k) in info.idx_in_var.iter().enumerate() {
+            match info.value_type[m] {
+                ValueType::FIXED => {
+                    dst.fixed_values[k] = src.fixed_values[k];
+                    dst.old_fixed_values[k] = src.old_fixed_values[k];
+                }
+                ValueType::DETERMINISTIC => {
+                    dst.deterministic_values[k] = src.deterministic_values[k];
+                    dst.old_deterministic_values[k] = src.old_deterministic_values[k];
+                }
+                ValueType::SAMPLEABLE => {
+                    dst.sampleable_values[k] = src.sampleable_values[k];
+                    dst.old_sampleable_values[k] = src.old_sampleable_values[k];
+                }
+            }
+        }
+    }
+
+    ///Draw the values of the stochastic nodes in topological order, each given the current values
+    ///of its parents, with the draw function of the node. If `which` is `None`, all the
+    ///unobserved values are drawn, e.g., from the prior; otherwise all the values of the nodes in
+    ///`which`, including the observed ones, e.g., replicated data given a posterior draw in `gv`.
+    pub fn simulate<R>(&self, gv: &mut GraphVar<T>, rng: &mut R, which: Option<&[K]>)
     where
         R: Rng,
     {
+        let selected: Option<BTreeSet<usize>> =
+            which.map(|w| w.iter().map(|k| self.key_node_map[k]).collect());
+        self.update_all_deterministic_nodes(gv);
         for i in 0..self.nodes.len() {
-            if let Node {
-                content:
-                    NodeContent::StochasticNode {
-                        ref is_observed, ..
-                    },
-                info: BasicNode { ndim_output, .. },
-            } = self.nodes[i]
+            if let NodeContent::StochasticNode {
+                ref is_observed,
+                ref draw,
+                plate,
+                ..
+            } = self.nodes[i].content
             {
-                for j in 0..ndim_output {
-                    if !is_observed[j] {
-                        let mut change_count = 0;
-                        self.sample(i, j, gv, rng, n, &mut change_count);
-                        if change_count > 0 {
-                            *nchanged += 1;
-                        }
+                let to_draw: Vec<bool> = match selected {
+                    Some(ref s) => vec![s.contains(&i); is_observed.len()],
+                    None => is_observed.iter().map(|&x| !x).collect(),
+                };
+                if !to_draw.iter().any(|&x| x) {
+                    continue;
+                }
+                let draw = draw
+                    .as_ref()
+                    .unwrap_or_else(|| panic!("{:?} has no draw function", self.node_key_map[&i]));
+                let p = self.parent_values_of(i, gv);
+                let x: Vec<T> = if plate {
+                    (0..to_draw.len()).map(|_| draw(&p, rng)[0]).collect()
+                } else {
+                    draw(&p, rng)
+                };
+                for (j, &d) in to_draw.iter().enumerate() {
+                    if d {
+                        self.set_value_no_update(i, j, x[j], gv);
                     }
                 }
+                self.update_deterministic_children(i, gv);
             }
         }
     }
@@ -568,24 +1348,32 @@ where
     }
 
     pub fn logpost(&self, i: usize, gv: &GraphVar<T>) -> T {
-        let mut result = zero();
-        if let NodeContent::StochasticNode {
-            ref all_stochastic_children,
-            ref logprob,
-            ..
-        } = self.nodes[i].content
-        {
-            let x = self.cached_values_of(i, gv);
-            let p = self.parent_values_of(i, gv);
-            for n in all_stochastic_children {
-                result = result + self.logprob(*n, gv);
+        self.likelihood(i, gv) + self.logprob(i, gv)
+    }
+
+    ///The terms of the log posterior that depend on the `j`-th value of the node `i`, which
+    ///exclude the other values of a plate and the children that do not read the `j`-th value
+    pub fn logpost_at(&self, i: usize, j: usize, gv: &GraphVar<T>) -> T {
+        match self.nodes[i].content {
+            NodeContent::StochasticNode {
+                ref logprob,
+                ref element_children,
+                plate: true,
+                ..
+            } => {
+                let x = self.cached_value_of(i, j, gv);
+                let p = self.parent_values_of(i, gv);
+                element_children
+                    .get(j)
+                    .map_or(&[][..], |e| &e.0[..])
+                    .iter()
+                    .fold(logprob(&[x], &p), |s, &c| s + self.logprob(c, gv))
             }
-            result = result + logprob(x.as_slice(), p.as_slice());
+            _ => self.logpost(i, gv),
         }
-        result
     }
 
-    pub fn update_deterministic_children(&self, i: usize, gv: &GraphVar<T>) {
+    pub fn update_deterministic_children(&self, i: usize, gv: &mut GraphVar<T>) {
         if let Node {
             content:
                 NodeContent::StochasticNode {
@@ -607,15 +1395,22 @@ where
         }
     }
 
-    pub fn update_all_deterministic_nodes(&self, gv: &GraphVar<T>) {
+    pub fn update_all_deterministic_nodes(&self, gv: &mut GraphVar<T>) {
         for i in 0..self.nodes.len() {
             self.update_deterministic_value_of(i, gv);
         }
     }
 
+    ///The joint log density of `gv`, whose deterministic values are computed afresh
     pub fn logpost_all(&self, gv: &GraphVar<T>) -> T {
+        let mut gv = gv.clone();
+        self.update_all_deterministic_nodes(&mut gv);
+        self.logpost_all_no_update(&gv)
+    }
+
+    ///The joint log density of `gv`, whose deterministic values must be up to date
+    pub fn logpost_all_no_update(&self, gv: &GraphVar<T>) -> T {
         let mut result = zero();
-        self.update_all_deterministic_nodes(gv);
         for n in 0..self.nodes.len() {
             if let NodeContent::StochasticNode { .. } = self.nodes[n].content {
                 result = result + self.logprob(n, gv);
@@ -632,17 +1427,36 @@ where
                     ref value_type,
                     ..
                 },
-            content: NodeContent::StochasticNode { .. },
+            content:
+                NodeContent::StochasticNode {
+                    ref element_children,
+                    plate,
+                    ..
+                },
             ..
         } = self.nodes[i]
         {
             match value_type[j] {
-                ValueType::DETERMINISTIC => gv.deterministic_values.borrow_mut()[idx_in_var[j]] = x,
-                ValueType::FIXED => gv.fixed_values.borrow_mut()[idx_in_var[j]] = x,
+                ValueType::DETERMINISTIC => gv.deterministic_values[idx_in_var[j]] = x,
+                ValueType::FIXED => gv.fixed_values[idx_in_var[j]] = x,
                 ValueType::SAMPLEABLE => gv.sampleable_values[idx_in_var[j]] = x,
             }
-            self.update_deterministic_children(i, gv);
+            if plate && !element_children.is_empty() {
+                //only the deterministic children reading the j-th value of a plate change
+                for &k in &element_children[j].1 {
+                    self.update_deterministic_value_of(k, gv);
+                }
+            } else {
+                self.update_deterministic_children(i, gv);
+            }
+        }
+    }
+
+    pub fn set_values_then_update(&self, i: usize, x: &[T], gv: &mut GraphVar<T>) {
+        for (j, &x) in x.iter().enumerate() {
+            self.set_value_no_update(i, j, x, gv);
         }
+        self.update_deterministic_children(i, gv);
     }
 
     pub fn set_value_no_update(&self, i: usize, j: usize, x: T, gv: &mut GraphVar<T>) {
@@ -658,8 +1472,8 @@ where
         } = self.nodes[i]
         {
             match value_type[j] {
-                ValueType::DETERMINISTIC => gv.deterministic_values.borrow_mut()[idx_in_var[j]] = x,
-                ValueType::FIXED => gv.fixed_values.borrow_mut()[idx_in_var[j]] = x,
+                ValueType::DETERMINISTIC => gv.deterministic_values[idx_in_var[j]] = x,
+                ValueType::FIXED => gv.fixed_values[idx_in_var[j]] = x,
                 ValueType::SAMPLEABLE => gv.sampleable_values[idx_in_var[j]] = x,
             }
         }
@@ -669,17 +1483,3 @@ where
     //    Box::new(|x:&GraphVar<T>|->T{self.logpost_all(x)})
     //}
 }
-
-unsafe impl<K, T> std::marker::Sync for Graph<K, T>
-where
-    K: std::hash::Hash + Eq + Clone + Debug,
-    T: Float + Sync + Send + std::fmt::Display,
-{
-}
-
-unsafe impl<K, T> std::marker::Send for Graph<K, T>
-where
-    K: std::hash::Hash + Eq + Clone + Debug,
-    T: Float + Sync + Send + std::fmt::Display,
-{
-}
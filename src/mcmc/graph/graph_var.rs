@@ -3,7 +3,6 @@ use crate::linear_space::{IndexableLinearSpace, LinearSpace};
 use crate::utils::HasLen;
 use num::traits::float::Float;
 use std;
-use std::cell::RefCell;
 use std::fmt::{Display, Error, Formatter};
 use std::ops::{Add, Mul, Sub};
 
@@ -12,27 +11,25 @@ pub struct GraphVar<T>
 where
     T: Float + Sync + Send + std::fmt::Display,
 {
-    pub fixed_values: RefCell<Vec<T>>,
-    pub deterministic_values: RefCell<Vec<T>>,
+    pub fixed_values: Vec<T>,
+    pub deterministic_values: Vec<T>,
     pub sampleable_values: Vec<T>,
-    pub old_fixed_values: RefCell<Vec<Option<T>>>,
-    pub old_deterministic_values: RefCell<Vec<Option<T>>>,
-    pub old_sampleable_values: RefCell<Vec<Option<T>>>,
+    pub old_fixed_values: Vec<Option<T>>,
+    pub old_deterministic_values: Vec<Option<T>>,
+    pub old_sampleable_values: Vec<Option<T>>,
 }
 
-unsafe impl<T> std::marker::Sync for GraphVar<T> where T: Float + Sync + Send + std::fmt::Display {}
-
 impl<T> Display for GraphVar<T>
 where
     T: Float + Send + Sync + Display,
 {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         writeln!(f, "Fixed values:")?;
-        for v in &*self.fixed_values.borrow() {
+        for v in &self.fixed_values {
             write!(f, "{} ", *v)?;
         }
         writeln!(f, "\nDeterministic values:")?;
-        for v in &*(self.deterministic_values.borrow()) {
+        for v in &self.deterministic_values {
             write!(f, "{} ", v)?;
         }
         write!(f, "\n Sampleable values:\n")?;
@@ -102,6 +99,7 @@ where
     }
 }
 
+#[allow(clippy::needless_lifetimes)]
 impl<'a, T> Mul<T> for &'a GraphVar<T>
 where
     T: Float + Sync + Send + std::fmt::Display,
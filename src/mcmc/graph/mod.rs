@@ -1,6 +1,10 @@
 #![allow(clippy::module_inception)]
+pub mod builder;
+pub mod flat;
 pub mod graph;
 pub mod graph_var;
+pub mod model_lang;
+pub mod mv_nodes;
 pub mod node;
 pub mod node_tag;
 pub mod nodes;
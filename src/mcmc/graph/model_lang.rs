@@ -0,0 +1,1271 @@
+#![cfg(not(target_family = "wasm"))]
+#![allow(clippy::type_complexity)]
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Display, Error as FmtError, Formatter};
+
+use num::traits::float::{Float, FloatConst};
+use rand::distributions::uniform::SampleUniform;
+use rand::distributions::{Distribution, Standard};
+use special::{Error, Gamma};
+
+use super::super::functions::phi;
+use super::graph::ParamObservability::{Observed, UnObserved};
+use super::graph::{Graph, NodeAdder, NodeHandle};
+use super::node_tag::NodeTag;
+use super::nodes::{
+    bernoulli_node, beta_node, binomial_node, categorical_node, cauchy_node, const_node,
+    discrete_uniform_node, exp_node, gamma_node, half_cauchy_node, half_normal_node,
+    inv_gamma_node, laplace_node, logistic_node, lognormal_node, normal_node, normal_var_node,
+    pareto_node, poisson_obs_node, scalar_func_node, t_node, trunc_poisson_node, uniform_node,
+    weibull_node,
+};
+
+///An error in a model description, with the 1-based line and column where it was detected
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModelError {
+    Parse {
+        line: usize,
+        column: usize,
+        msg: String,
+    },
+    Semantic {
+        line: usize,
+        column: usize,
+        msg: String,
+    },
+}
+
+impl Display for ModelError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            ModelError::Parse { line, column, msg } => {
+                write!(f, "parse error at {}:{}: {}", line, column, msg)
+            }
+            ModelError::Semantic { line, column, msg } => {
+                write!(f, "error at {}:{}: {}", line, column, msg)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Pos {
+    line: usize,
+    column: usize,
+}
+
+fn parse_error<R>(pos: Pos, msg: String) -> Result<R, ModelError> {
+    Err(ModelError::Parse {
+        line: pos.line,
+        column: pos.column,
+        msg,
+    })
+}
+
+fn semantic_error<R>(pos: Pos, msg: String) -> Result<R, ModelError> {
+    Err(ModelError::Semantic {
+        line: pos.line,
+        column: pos.column,
+        msg,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Num(f64),
+    Ident(String),
+    Tilde,
+    Assign,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    Comma,
+    Colon,
+    Semicolon,
+    Op(char),
+    Eof,
+}
+
+impl Display for Tok {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            Tok::Num(x) => write!(f, "number {}", x),
+            Tok::Ident(s) => write!(f, "'{}'", s),
+            Tok::Tilde => write!(f, "'~'"),
+            Tok::Assign => write!(f, "'<-'"),
+            Tok::LParen => write!(f, "'('"),
+            Tok::RParen => write!(f, "')'"),
+            Tok::LBracket => write!(f, "'['"),
+            Tok::RBracket => write!(f, "']'"),
+            Tok::LBrace => write!(f, "'{{'"),
+            Tok::RBrace => write!(f, "'}}'"),
+            Tok::Comma => write!(f, "','"),
+            Tok::Colon => write!(f, "':'"),
+            Tok::Semicolon => write!(f, "';'"),
+            Tok::Op(c) => write!(f, "'{}'", c),
+            Tok::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+fn tokenize(src: &str) -> Result<Vec<(Tok, Pos)>, ModelError> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut result = Vec::new();
+    let (mut i, mut line, mut column) = (0, 1, 1);
+    while i < chars.len() {
+        let c = chars[i];
+        let pos = Pos { line, column };
+        let start = i;
+        if c == '\n' {
+            i += 1;
+            line += 1;
+            column = 1;
+            continue;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c.is_ascii_digit()
+            || (c == '.' && i + 1 < chars.len() && chars[i + 1].is_ascii_digit())
+        {
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let s: String = chars[start..i].iter().collect();
+            match s.parse::<f64>() {
+                Ok(x) => result.push((Tok::Num(x), pos)),
+                Err(_) => return parse_error(pos, format!("invalid number '{}'", s)),
+            }
+        } else if c.is_alphabetic() {
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            result.push((Tok::Ident(chars[start..i].iter().collect()), pos));
+        } else {
+            let tok = match c {
+                '~' => Tok::Tilde,
+                '<' if i + 1 < chars.len() && chars[i + 1] == '-' => {
+                    i += 1;
+                    Tok::Assign
+                }
+                '(' => Tok::LParen,
+                ')' => Tok::RParen,
+                '[' => Tok::LBracket,
+                ']' => Tok::RBracket,
+                '{' => Tok::LBrace,
+                '}' => Tok::RBrace,
+                ',' => Tok::Comma,
+                ':' => Tok::Colon,
+                ';' => Tok::Semicolon,
+                '+' | '-' | '*' | '/' | '^' => Tok::Op(c),
+                _ => return parse_error(pos, format!("unexpected character '{}'", c)),
+            };
+            i += 1;
+            result.push((tok, pos));
+        }
+        column += i - start;
+    }
+    result.push((Tok::Eof, Pos { line, column }));
+    Ok(result)
+}
+
+#[derive(Debug)]
+enum ExprKind {
+    Num(f64),
+    Var(String, Option<Box<Expr>>),
+    Neg(Box<Expr>),
+    Bin(char, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Debug)]
+struct Expr {
+    kind: ExprKind,
+    pos: Pos,
+}
+
+#[derive(Debug)]
+struct LValue {
+    name: String,
+    index: Option<Expr>,
+    pos: Pos,
+}
+
+#[derive(Debug)]
+enum Stmt {
+    For {
+        var: String,
+        from: Expr,
+        to: Expr,
+        body: Vec<Stmt>,
+    },
+    Stochastic {
+        lhs: LValue,
+        dist: String,
+        args: Vec<Expr>,
+        pos: Pos,
+    },
+    Deterministic {
+        lhs: LValue,
+        expr: Expr,
+    },
+}
+
+struct Parser {
+    tokens: Vec<(Tok, Pos)>,
+    cur: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.cur].0
+    }
+
+    fn next(&mut self) -> (Tok, Pos) {
+        let t = self.tokens[self.cur].clone();
+        if t.0 != Tok::Eof {
+            self.cur += 1;
+        }
+        t
+    }
+
+    fn expect(&mut self, tok: Tok) -> Result<Pos, ModelError> {
+        let (t, pos) = self.next();
+        if t == tok {
+            Ok(pos)
+        } else {
+            parse_error(pos, format!("expected {}, found {}", tok, t))
+        }
+    }
+
+    fn ident(&mut self) -> Result<(String, Pos), ModelError> {
+        match self.next() {
+            (Tok::Ident(s), pos) => Ok((s, pos)),
+            (t, pos) => parse_error(pos, format!("expected a name, found {}", t)),
+        }
+    }
+
+    fn model(&mut self) -> Result<Vec<Stmt>, ModelError> {
+        let wrapped = *self.peek() == Tok::Ident("model".to_string());
+        if wrapped {
+            self.next();
+            self.expect(Tok::LBrace)?;
+        }
+        let stmts = self.stmts()?;
+        if wrapped {
+            self.expect(Tok::RBrace)?;
+        }
+        self.expect(Tok::Eof)?;
+        Ok(stmts)
+    }
+
+    ///Statements up to a closing brace or the end of input
+    fn stmts(&mut self) -> Result<Vec<Stmt>, ModelError> {
+        let mut result = Vec::new();
+        loop {
+            while *self.peek() == Tok::Semicolon {
+                self.next();
+            }
+            match self.peek() {
+                Tok::RBrace | Tok::Eof => return Ok(result),
+                _ => result.push(self.stmt()?),
+            }
+        }
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, ModelError> {
+        let (name, pos) = self.ident()?;
+        if name == "for" && *self.peek() == Tok::LParen {
+            self.next();
+            let (var, _) = self.ident()?;
+            match self.next() {
+                (Tok::Ident(ref s), _) if s == "in" => {}
+                (t, pos) => return parse_error(pos, format!("expected 'in', found {}", t)),
+            }
+            let from = self.expr()?;
+            self.expect(Tok::Colon)?;
+            let to = self.expr()?;
+            self.expect(Tok::RParen)?;
+            self.expect(Tok::LBrace)?;
+            let body = self.stmts()?;
+            self.expect(Tok::RBrace)?;
+            return Ok(Stmt::For {
+                var,
+                from,
+                to,
+                body,
+            });
+        }
+        let index = if *self.peek() == Tok::LBracket {
+            self.next();
+            let e = self.expr()?;
+            self.expect(Tok::RBracket)?;
+            Some(e)
+        } else {
+            None
+        };
+        let lhs = LValue { name, index, pos };
+        match self.next() {
+            (Tok::Tilde, _) => {
+                let (dist, pos) = self.ident()?;
+                self.expect(Tok::LParen)?;
+                let args = self.args()?;
+                Ok(Stmt::Stochastic {
+                    lhs,
+                    dist,
+                    args,
+                    pos,
+                })
+            }
+            (Tok::Assign, _) => Ok(Stmt::Deterministic {
+                lhs,
+                expr: self.expr()?,
+            }),
+            (t, pos) => parse_error(pos, format!("expected '~' or '<-', found {}", t)),
+        }
+    }
+
+    ///Comma separated expressions after an opening parenthesis, up to the closing one
+    fn args(&mut self) -> Result<Vec<Expr>, ModelError> {
+        let mut result = Vec::new();
+        if *self.peek() == Tok::RParen {
+            self.next();
+            return Ok(result);
+        }
+        loop {
+            result.push(self.expr()?);
+            match self.next() {
+                (Tok::Comma, _) => {}
+                (Tok::RParen, _) => return Ok(result),
+                (t, pos) => return parse_error(pos, format!("expected ',' or ')', found {}", t)),
+            }
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ModelError> {
+        let mut lhs = self.term()?;
+        while let Tok::Op(c @ ('+' | '-')) = *self.peek() {
+            let pos = self.next().1;
+            let rhs = self.term()?;
+            lhs = Expr {
+                kind: ExprKind::Bin(c, Box::new(lhs), Box::new(rhs)),
+                pos,
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<Expr, ModelError> {
+        let mut lhs = self.unary()?;
+        while let Tok::Op(c @ ('*' | '/')) = *self.peek() {
+            let pos = self.next().1;
+            let rhs = self.unary()?;
+            lhs = Expr {
+                kind: ExprKind::Bin(c, Box::new(lhs), Box::new(rhs)),
+                pos,
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ModelError> {
+        if *self.peek() == Tok::Op('-') {
+            let pos = self.next().1;
+            let e = self.unary()?;
+            return Ok(Expr {
+                kind: ExprKind::Neg(Box::new(e)),
+                pos,
+            });
+        }
+        let base = self.primary()?;
+        if *self.peek() == Tok::Op('^') {
+            let pos = self.next().1;
+            let exponent = self.unary()?;
+            return Ok(Expr {
+                kind: ExprKind::Bin('^', Box::new(base), Box::new(exponent)),
+                pos,
+            });
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Expr, ModelError> {
+        match self.next() {
+            (Tok::Num(x), pos) => Ok(Expr {
+                kind: ExprKind::Num(x),
+                pos,
+            }),
+            (Tok::LParen, _) => {
+                let e = self.expr()?;
+                self.expect(Tok::RParen)?;
+                Ok(e)
+            }
+            (Tok::Ident(name), pos) => match self.peek() {
+                Tok::LParen => {
+                    self.next();
+                    let args = self.args()?;
+                    Ok(Expr {
+                        kind: ExprKind::Call(name, args),
+                        pos,
+                    })
+                }
+                Tok::LBracket => {
+                    self.next();
+                    let e = self.expr()?;
+                    self.expect(Tok::RBracket)?;
+                    Ok(Expr {
+                        kind: ExprKind::Var(name, Some(Box::new(e))),
+                        pos,
+                    })
+                }
+                _ => Ok(Expr {
+                    kind: ExprKind::Var(name, None),
+                    pos,
+                }),
+            },
+            (t, pos) => parse_error(pos, format!("expected an expression, found {}", t)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Func {
+    Exp,
+    Log,
+    Log10,
+    Sqrt,
+    Sin,
+    Cos,
+    Tan,
+    Abs,
+    Pow,
+    Logistic,
+    Logit,
+    Phi,
+}
+
+const FUNCTIONS: &[(&str, Func, usize)] = &[
+    ("exp", Func::Exp, 1),
+    ("log", Func::Log, 1),
+    ("log10", Func::Log10, 1),
+    ("sqrt", Func::Sqrt, 1),
+    ("sin", Func::Sin, 1),
+    ("cos", Func::Cos, 1),
+    ("tan", Func::Tan, 1),
+    ("abs", Func::Abs, 1),
+    ("pow", Func::Pow, 2),
+    ("logistic", Func::Logistic, 1),
+    ("ilogit", Func::Logistic, 1),
+    ("logit", Func::Logit, 1),
+    ("phi", Func::Phi, 1),
+];
+
+fn apply<T>(f: Func, x: &[T]) -> T
+where
+    T: Float + Error,
+{
+    match f {
+        Func::Exp => x[0].exp(),
+        Func::Log => x[0].ln(),
+        Func::Log10 => x[0].log10(),
+        Func::Sqrt => x[0].sqrt(),
+        Func::Sin => x[0].sin(),
+        Func::Cos => x[0].cos(),
+        Func::Tan => x[0].tan(),
+        Func::Abs => x[0].abs(),
+        Func::Pow => x[0].powf(x[1]),
+        Func::Logistic => T::one() / (T::one() + (-x[0]).exp()),
+        Func::Logit => (x[0] / (T::one() - x[0])).ln(),
+        Func::Phi => phi(x[0]),
+    }
+}
+
+fn binary<T>(op: char, a: T, b: T) -> T
+where
+    T: Float,
+{
+    match op {
+        '+' => a + b,
+        '-' => a - b,
+        '*' => a * b,
+        '/' => a / b,
+        _ => a.powf(b),
+    }
+}
+
+///An expression over the parent values of a deterministic node
+enum CExpr<T> {
+    Const(T),
+    Parent(usize),
+    Neg(Box<CExpr<T>>),
+    Bin(char, Box<CExpr<T>>, Box<CExpr<T>>),
+    Call(Func, Vec<CExpr<T>>),
+}
+
+impl<T> CExpr<T>
+where
+    T: Float + Error,
+{
+    fn eval(&self, x: &[T]) -> T {
+        match self {
+            CExpr::Const(c) => *c,
+            CExpr::Parent(k) => x[*k],
+            CExpr::Neg(e) => -e.eval(x),
+            CExpr::Bin(op, a, b) => binary(*op, a.eval(x), b.eval(x)),
+            CExpr::Call(f, args) => {
+                let v: Vec<T> = args.iter().map(|a| a.eval(x)).collect();
+                apply(*f, &v)
+            }
+        }
+    }
+}
+
+enum Lowered<T> {
+    Const(T),
+    Node(NodeHandle),
+    Func(CExpr<T>, Vec<(NodeHandle, usize)>),
+}
+
+enum Rhs<'a> {
+    Stochastic(&'a str, &'a [Expr], Pos),
+    Deterministic(&'a Expr),
+}
+
+struct Relation<'a> {
+    rhs: Rhs<'a>,
+    env: HashMap<String, i64>,
+    pos: Pos,
+}
+
+struct Builder<'a, T>
+where
+    T: Float + Sync + Send + Display,
+{
+    data: &'a HashMap<String, Vec<T>>,
+    inits: &'a HashMap<String, Vec<T>>,
+    relations: HashMap<NodeTag<String>, Relation<'a>>,
+    order: Vec<NodeTag<String>>,
+    ///Whether each name of the model is indexed
+    names: HashMap<String, bool>,
+    graph: Graph<NodeTag<String>, T>,
+    built: HashMap<NodeTag<String>, NodeHandle>,
+    visiting: HashSet<NodeTag<String>>,
+    constants: HashMap<u64, NodeHandle>,
+    nauto: usize,
+}
+
+impl<'a, T> Builder<'a, T>
+where
+    T: 'static + Float + FloatConst + Gamma + Error + Sync + Send + Display + Debug + SampleUniform,
+    Standard: Distribution<T>,
+{
+    fn unroll(
+        &mut self,
+        stmts: &'a [Stmt],
+        env: &mut HashMap<String, i64>,
+    ) -> Result<(), ModelError> {
+        for s in stmts {
+            match s {
+                Stmt::For {
+                    var,
+                    from,
+                    to,
+                    body,
+                } => {
+                    let from = self.integer(from, env)?;
+                    let to = self.integer(to, env)?;
+                    let saved = env.get(var).cloned();
+                    for i in from..=to {
+                        env.insert(var.clone(), i);
+                        self.unroll(body, env)?;
+                    }
+                    match saved {
+                        Some(i) => env.insert(var.clone(), i),
+                        None => env.remove(var),
+                    };
+                }
+                Stmt::Stochastic {
+                    lhs,
+                    dist,
+                    args,
+                    pos,
+                } => {
+                    let rhs = Rhs::Stochastic(dist, args, *pos);
+                    self.add_relation(lhs, rhs, env)?;
+                }
+                Stmt::Deterministic { lhs, expr } => {
+                    self.add_relation(lhs, Rhs::Deterministic(expr), env)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn add_relation(
+        &mut self,
+        lhs: &'a LValue,
+        rhs: Rhs<'a>,
+        env: &HashMap<String, i64>,
+    ) -> Result<(), ModelError> {
+        let indexed = lhs.index.is_some();
+        if *self.names.entry(lhs.name.clone()).or_insert(indexed) != indexed {
+            return semantic_error(
+                lhs.pos,
+                format!("'{}' is used both with and without an index", lhs.name),
+            );
+        }
+        let key = match lhs.index {
+            Some(ref e) => NodeTag::Vector(lhs.name.clone(), self.index(e, env)?),
+            None => NodeTag::Scalar(lhs.name.clone()),
+        };
+        if self.relations.contains_key(&key) {
+            return semantic_error(lhs.pos, format!("{} is defined more than once", show(&key)));
+        }
+        self.order.push(key.clone());
+        self.relations.insert(
+            key,
+            Relation {
+                rhs,
+                env: env.clone(),
+                pos: lhs.pos,
+            },
+        );
+        Ok(())
+    }
+
+    ///The value of a data array for `key`, `None` if not given
+    fn lookup(
+        &self,
+        map: &HashMap<String, Vec<T>>,
+        key: &NodeTag<String>,
+        pos: Pos,
+    ) -> Result<Option<T>, ModelError> {
+        let (name, i) = match key {
+            NodeTag::Scalar(name) => (name, 1),
+            NodeTag::Vector(name, i) => (name, *i),
+        };
+        match map.get(name) {
+            None => Ok(None),
+            Some(v) => match v.get(i - 1) {
+                Some(&x) if matches!(key, NodeTag::Vector(..)) || v.len() == 1 => Ok(Some(x)),
+                Some(_) => semantic_error(pos, format!("'{}' should be a scalar", name)),
+                None => semantic_error(
+                    pos,
+                    format!(
+                        "index {} is out of the range of '{}' of length {}",
+                        i,
+                        name,
+                        v.len()
+                    ),
+                ),
+            },
+        }
+    }
+
+    fn key_of(
+        &self,
+        name: &str,
+        index: &Option<Box<Expr>>,
+        env: &HashMap<String, i64>,
+    ) -> Result<NodeTag<String>, ModelError> {
+        Ok(match index {
+            Some(e) => NodeTag::Vector(name.to_string(), self.index(e, env)?),
+            None => NodeTag::Scalar(name.to_string()),
+        })
+    }
+
+    ///The value of `e` if it does not depend on any node of the model
+    fn const_eval(&self, e: &Expr, env: &HashMap<String, i64>) -> Result<Option<T>, ModelError> {
+        Ok(match e.kind {
+            ExprKind::Num(x) => Some(T::from(x).unwrap()),
+            ExprKind::Var(ref name, ref index) => {
+                if let (Some(&i), None) = (env.get(name), index) {
+                    return Ok(Some(T::from(i).unwrap()));
+                }
+                if self.names.contains_key(name) {
+                    return Ok(None);
+                }
+                if !self.data.contains_key(name) {
+                    return semantic_error(e.pos, format!("'{}' is not defined", name));
+                }
+                let key = self.key_of(name, index, env)?;
+                self.lookup(self.data, &key, e.pos)?
+            }
+            ExprKind::Neg(ref a) => self.const_eval(a, env)?.map(|a| -a),
+            ExprKind::Bin(op, ref a, ref b) => {
+                match (self.const_eval(a, env)?, self.const_eval(b, env)?) {
+                    (Some(a), Some(b)) => Some(binary(op, a, b)),
+                    _ => None,
+                }
+            }
+            ExprKind::Call(ref name, ref args) => {
+                if name == "length" {
+                    return match args.as_slice() {
+                        [Expr {
+                            kind: ExprKind::Var(a, None),
+                            ..
+                        }] if self.data.contains_key(a) => {
+                            Ok(Some(T::from(self.data[a].len()).unwrap()))
+                        }
+                        _ => semantic_error(
+                            e.pos,
+                            "length() takes the name of a data array".to_string(),
+                        ),
+                    };
+                }
+                let f = function(name, args.len(), e.pos)?;
+                let mut v = Vec::new();
+                for a in args {
+                    match self.const_eval(a, env)? {
+                        Some(x) => v.push(x),
+                        None => return Ok(None),
+                    }
+                }
+                Some(apply(f, &v))
+            }
+        })
+    }
+
+    fn integer(&self, e: &Expr, env: &HashMap<String, i64>) -> Result<i64, ModelError> {
+        match self.const_eval(e, env)? {
+            Some(x) if x.fract() == T::zero() => Ok(x.to_i64().unwrap()),
+            Some(x) => semantic_error(e.pos, format!("{} is not an integer", x)),
+            None => semantic_error(
+                e.pos,
+                "the value must not depend on the nodes of the model".to_string(),
+            ),
+        }
+    }
+
+    fn index(&self, e: &Expr, env: &HashMap<String, i64>) -> Result<usize, ModelError> {
+        match self.integer(e, env)? {
+            i if i >= 1 => Ok(i as usize),
+            i => semantic_error(e.pos, format!("index {} is not positive", i)),
+        }
+    }
+
+    fn new_key(&mut self) -> NodeTag<String> {
+        self.nauto += 1;
+        NodeTag::Scalar(format!("#{}", self.nauto))
+    }
+
+    fn constant(&mut self, x: T) -> NodeHandle {
+        let bits = x.to_f64().unwrap().to_bits();
+        if let Some(&h) = self.constants.get(&bits) {
+            return h;
+        }
+        let key = self.new_key();
+        let h = const_node(x).add_to(&mut self.graph, &key);
+        self.constants.insert(bits, h);
+        h
+    }
+
+    fn lower(&mut self, e: &Expr, env: &HashMap<String, i64>) -> Result<Lowered<T>, ModelError> {
+        if let Some(x) = self.const_eval(e, env)? {
+            return Ok(Lowered::Const(x));
+        }
+        if let ExprKind::Var(ref name, ref index) = e.kind {
+            let key = self.key_of(name, index, env)?;
+            return Ok(Lowered::Node(self.node(&key, e.pos)?));
+        }
+        let mut parents = Vec::new();
+        let mut keys = Vec::new();
+        let c = self.compile(e, env, &mut parents, &mut keys)?;
+        Ok(Lowered::Func(c, parents))
+    }
+
+    fn compile(
+        &mut self,
+        e: &Expr,
+        env: &HashMap<String, i64>,
+        parents: &mut Vec<(NodeHandle, usize)>,
+        keys: &mut Vec<NodeTag<String>>,
+    ) -> Result<CExpr<T>, ModelError> {
+        if let Some(x) = self.const_eval(e, env)? {
+            return Ok(CExpr::Const(x));
+        }
+        Ok(match e.kind {
+            ExprKind::Num(_) => unreachable!(),
+            ExprKind::Var(ref name, ref index) => {
+                let key = self.key_of(name, index, env)?;
+                match keys.iter().position(|k| *k == key) {
+                    Some(k) => CExpr::Parent(k),
+                    None => {
+                        parents.push((self.node(&key, e.pos)?, 0));
+                        keys.push(key);
+                        CExpr::Parent(keys.len() - 1)
+                    }
+                }
+            }
+            ExprKind::Neg(ref a) => CExpr::Neg(Box::new(self.compile(a, env, parents, keys)?)),
+            ExprKind::Bin(op, ref a, ref b) => CExpr::Bin(
+                op,
+                Box::new(self.compile(a, env, parents, keys)?),
+                Box::new(self.compile(b, env, parents, keys)?),
+            ),
+            ExprKind::Call(ref name, ref args) => {
+                let f = function(name, args.len(), e.pos)?;
+                let mut v = Vec::new();
+                for a in args {
+                    v.push(self.compile(a, env, parents, keys)?);
+                }
+                CExpr::Call(f, v)
+            }
+        })
+    }
+
+    ///The node for `key`, adding it and its ancestors to the graph if needed
+    fn node(&mut self, key: &NodeTag<String>, pos: Pos) -> Result<NodeHandle, ModelError> {
+        if let Some(&h) = self.built.get(key) {
+            return Ok(h);
+        }
+        if self.visiting.contains(key) {
+            return semantic_error(pos, format!("{} depends on itself", show(key)));
+        }
+        let relation = match self.relations.remove(key) {
+            Some(r) => r,
+            None => return semantic_error(pos, format!("{} is not defined", show(key))),
+        };
+        self.visiting.insert(key.clone());
+        let h = match relation.rhs {
+            Rhs::Deterministic(e) => {
+                if self.data.contains_key(key_name(key)) {
+                    return semantic_error(
+                        relation.pos,
+                        format!(
+                            "data cannot be given for the deterministic node {}",
+                            show(key)
+                        ),
+                    );
+                }
+                match self.lower(e, &relation.env)? {
+                    Lowered::Const(x) => const_node(x),
+                    Lowered::Node(h) => scalar_func_node(&[(h, 0)], Box::new(|x: &[T]| x[0])),
+                    Lowered::Func(c, parents) => {
+                        scalar_func_node(&parents, Box::new(move |x: &[T]| c.eval(x)))
+                    }
+                }
+                .add_to(&mut self.graph, key)
+            }
+            Rhs::Stochastic(dist, args, dist_pos) => {
+                self.stochastic(key, dist, args, dist_pos, &relation)?
+            }
+        };
+        self.visiting.remove(key);
+        self.built.insert(key.clone(), h);
+        Ok(h)
+    }
+
+    fn stochastic(
+        &mut self,
+        key: &NodeTag<String>,
+        dist: &str,
+        args: &[Expr],
+        dist_pos: Pos,
+        relation: &Relation,
+    ) -> Result<NodeHandle, ModelError> {
+        let (nargs, has_int) = match DISTRIBUTIONS.iter().find(|d| d.0 == dist) {
+            Some(&(_, nargs, has_int)) => (nargs, has_int),
+            None => return semantic_error(dist_pos, format!("unknown distribution '{}'", dist)),
+        };
+        let expected = nargs + has_int as usize;
+        if (nargs > 0 && args.len() != expected) || (nargs == 0 && args.is_empty()) {
+            return semantic_error(
+                dist_pos,
+                format!(
+                    "'{}' takes {} arguments, but {} are given",
+                    dist,
+                    expected,
+                    args.len()
+                ),
+            );
+        }
+        let env = &relation.env;
+        let int_arg = if has_int {
+            let i = self.integer(&args[args.len() - 1], env)?;
+            if i < 0 {
+                return semantic_error(args[args.len() - 1].pos, format!("{} is negative", i));
+            }
+            i as usize
+        } else {
+            0
+        };
+        let node_args = &args[..args.len() - has_int as usize];
+        let mut consts = Vec::new();
+        let mut h = Vec::new();
+        for a in node_args {
+            let lowered = self.lower(a, env)?;
+            consts.push(match lowered {
+                Lowered::Const(x) => Some(x),
+                _ => None,
+            });
+            h.push((
+                match lowered {
+                    Lowered::Const(x) => self.constant(x),
+                    Lowered::Node(h) => h,
+                    Lowered::Func(c, parents) => {
+                        let k = self.new_key();
+                        scalar_func_node(&parents, Box::new(move |x: &[T]| c.eval(x)))
+                            .add_to(&mut self.graph, &k)
+                    }
+                },
+                0,
+            ));
+        }
+        let observed = self
+            .lookup(self.data, key, relation.pos)?
+            .filter(|x| !x.is_nan());
+        if dist == "poisson" && observed.is_none() {
+            return semantic_error(
+                dist_pos,
+                "poisson is only available for observed data, use trunc_poisson".to_string(),
+            );
+        }
+        let value = match observed {
+            Some(x) => Observed(x),
+            None => {
+                let init = match self.lookup(self.inits, key, relation.pos)? {
+                    Some(x) => Some(x),
+                    None => default_init(dist, &consts),
+                };
+                match init {
+                    Some(x) => UnObserved(x),
+                    None => {
+                        return semantic_error(
+                            relation.pos,
+                            format!("an initial value is required for {}", show(key)),
+                        )
+                    }
+                }
+            }
+        };
+        let adder: NodeAdder<T> = match dist {
+            "normal" => normal_node(h[0], h[1]),
+            "normal_var" => normal_var_node(h[0], h[1]),
+            "uniform" => uniform_node(h[0], h[1]),
+            "t" => t_node(h[0], h[1], int_arg),
+            "pareto" => pareto_node(h[0], h[1]),
+            "gamma" => gamma_node(h[0], h[1]),
+            "inv_gamma" => inv_gamma_node(h[0], h[1]),
+            "beta" => beta_node(h[0], h[1]),
+            "exp" => exp_node(h[0]),
+            "lognormal" => lognormal_node(h[0], h[1]),
+            "cauchy" => cauchy_node(h[0], h[1]),
+            "half_normal" => half_normal_node(h[0]),
+            "half_cauchy" => half_cauchy_node(h[0]),
+            "weibull" => weibull_node(h[0], h[1]),
+            "laplace" => laplace_node(h[0], h[1]),
+            "logistic" => logistic_node(h[0], h[1]),
+            "poisson" => match value {
+                Observed(k) => poisson_obs_node(h[0], k),
+                UnObserved(_) => unreachable!(),
+            },
+            "trunc_poisson" => trunc_poisson_node(h[0], int_arg),
+            "bernoulli" => bernoulli_node(h[0]),
+            "binomial" => binomial_node(h[0], h[1]),
+            "categorical" => categorical_node(&h),
+            "discrete_uniform" => discrete_uniform_node(h[0], h[1]),
+            _ => unreachable!(),
+        };
+        Ok(adder.with_all_values(&[value]).add_to(&mut self.graph, key))
+    }
+}
+
+///Name, number of node arguments (0 if variadic), and whether a constant integer argument
+///follows
+const DISTRIBUTIONS: &[(&str, usize, bool)] = &[
+    ("normal", 2, false),
+    ("normal_var", 2, false),
+    ("uniform", 2, false),
+    ("t", 2, true),
+    ("pareto", 2, false),
+    ("gamma", 2, false),
+    ("inv_gamma", 2, false),
+    ("beta", 2, false),
+    ("exp", 1, false),
+    ("lognormal", 2, false),
+    ("cauchy", 2, false),
+    ("half_normal", 1, false),
+    ("half_cauchy", 1, false),
+    ("weibull", 2, false),
+    ("laplace", 2, false),
+    ("logistic", 2, false),
+    ("poisson", 1, false),
+    ("trunc_poisson", 1, true),
+    ("bernoulli", 1, false),
+    ("binomial", 2, false),
+    ("categorical", 0, false),
+    ("discrete_uniform", 2, false),
+];
+
+///An initial value inside the support, if it can be determined without the values of the
+///parents that are not constant
+fn default_init<T>(dist: &str, consts: &[Option<T>]) -> Option<T>
+where
+    T: Float,
+{
+    let two = T::one() + T::one();
+    match dist {
+        "uniform" => match (consts[0], consts[1]) {
+            (Some(a), Some(b)) => Some((a + b) / two),
+            _ => None,
+        },
+        "discrete_uniform" => consts[0].map(|a| a.ceil()),
+        "pareto" => consts[1].map(|c| c * two),
+        "gamma" | "inv_gamma" | "exp" | "lognormal" | "half_normal" | "half_cauchy" | "weibull" => {
+            Some(T::one())
+        }
+        "beta" => Some(T::one() / two),
+        "normal" | "normal_var" | "t" | "cauchy" | "laplace" | "logistic" | "bernoulli"
+        | "binomial" | "categorical" | "trunc_poisson" => Some(T::zero()),
+        _ => None,
+    }
+}
+
+fn function(name: &str, nargs: usize, pos: Pos) -> Result<Func, ModelError> {
+    match FUNCTIONS.iter().find(|f| f.0 == name) {
+        Some(&(_, f, n)) if n == nargs => Ok(f),
+        Some(&(_, _, n)) => semantic_error(
+            pos,
+            format!("'{}' takes {} arguments, but {} are given", name, n, nargs),
+        ),
+        None => semantic_error(pos, format!("unknown function '{}'", name)),
+    }
+}
+
+fn key_name(key: &NodeTag<String>) -> &str {
+    match key {
+        NodeTag::Scalar(name) | NodeTag::Vector(name, _) => name,
+    }
+}
+
+fn show(key: &NodeTag<String>) -> String {
+    match key {
+        NodeTag::Scalar(name) => format!("'{}'", name),
+        NodeTag::Vector(name, i) => format!("'{}[{}]'", name, i),
+    }
+}
+
+///Build a graph from a BUGS/JAGS-like model description, e.g.,
+///```text
+///model {
+///    mu ~ normal(0, 10)
+///    s ~ half_cauchy(5)
+///    for (i in 1:length(y)) {
+///        y[i] ~ normal(mu + b * x[i], s)
+///    }
+///    b ~ normal(0, 1)
+///}
+///```
+///Stochastic relations `~` use the node constructors in `nodes` without the `_node` suffix,
+///with the same parameters, and deterministic relations `<-` accept `+ - * / ^` and the
+///functions `exp`, `log`, `log10`, `sqrt`, `sin`, `cos`, `tan`, `abs`, `pow`, `logistic`
+///(`ilogit`), `logit` and `phi`. Relations may appear in any order.
+///
+///The nodes are keyed by `NodeTag::Scalar(name)` or `NodeTag::Vector(name, i)`, where `i` is
+///the 1-based index as written in the model, and intermediate nodes get keys starting with
+///`#`. The names in `data` that are not defined by the model are constants, such as covariates
+///and sizes; the others are the observed values of stochastic nodes, where NaN marks a missing
+///value. `inits` gives the initial values of the unobserved nodes in the same way.
+pub fn compile_model<T>(
+    src: &str,
+    data: &HashMap<String, Vec<T>>,
+    inits: &HashMap<String, Vec<T>>,
+) -> Result<Graph<NodeTag<String>, T>, ModelError>
+where
+    T: 'static + Float + FloatConst + Gamma + Error + Sync + Send + Display + Debug + SampleUniform,
+    Standard: Distribution<T>,
+{
+    let stmts = Parser {
+        tokens: tokenize(src)?,
+        cur: 0,
+    }
+    .model()?;
+    let mut builder = Builder {
+        data,
+        inits,
+        relations: HashMap::new(),
+        order: Vec::new(),
+        names: HashMap::new(),
+        graph: Graph::new(),
+        built: HashMap::new(),
+        visiting: HashSet::new(),
+        constants: HashMap::new(),
+        nauto: 0,
+    };
+    builder.unroll(&stmts, &mut HashMap::new())?;
+    for key in std::mem::take(&mut builder.order) {
+        if !builder.built.contains_key(&key) {
+            let pos = builder.relations[&key].pos;
+            builder.node(&key, pos)?;
+        }
+    }
+    Ok(builder.graph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcmc::graph::graph::Sampler;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn data(v: &[(&str, &[f64])]) -> HashMap<String, Vec<f64>> {
+        v.iter().map(|(k, x)| (k.to_string(), x.to_vec())).collect()
+    }
+
+    fn scalar(name: &str) -> NodeTag<String> {
+        NodeTag::Scalar(name.to_string())
+    }
+
+    fn error_at(src: &str, d: &HashMap<String, Vec<f64>>) -> (usize, usize, String) {
+        match compile_model(src, d, &HashMap::new()) {
+            Ok(_) => panic!("no error in {}", src),
+            Err(ModelError::Parse { line, column, msg })
+            | Err(ModelError::Semantic { line, column, msg }) => (line, column, msg),
+        }
+    }
+
+    #[test]
+    fn model_test() {
+        let src = "
+model {
+    # the mean of normal observations with known errors
+    for (i in 1:N) {
+        y[i] ~ normal(mu, s[i]);
+    }
+    mu ~ normal(m0, 2)
+    lmu <- 2 * exp(-mu / 2) ^ 2
+}";
+        let (ys, ss) = ([0.5, 2.0, 1.5], [1.0, 0.5, 1.5]);
+        let d = data(&[("y", &ys), ("s", &ss), ("N", &[3.0]), ("m0", &[1.0])]);
+        let mut g = compile_model(src, &d, &HashMap::new()).unwrap();
+        g.seal();
+        assert_eq!(g.sampler_of(&scalar("mu")), Some(Sampler::NormalNormal));
+        assert_eq!(g.sampler_of(&NodeTag::Vector("y".to_string(), 2)), None);
+
+        let mut gv = g.init_gv();
+        assert_eq!(gv.sampleable_values.len(), 1);
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut nchanged = 0;
+        let n = 20000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            g.sample_all(&mut gv, &mut rng, 10, &mut nchanged);
+            let mu = g.values_of(&scalar("mu"), &gv)[0];
+            let lmu = g.values_of(&scalar("lmu"), &gv)[0];
+            assert!((lmu - 2.0 * (-mu).exp()).abs() < 1e-10);
+            sum += mu;
+        }
+        let prec = 0.25 + ss.iter().map(|s| 1.0 / (s * s)).sum::<f64>();
+        let mean = (0.25
+            + ys.iter()
+                .zip(ss.iter())
+                .map(|(y, s)| y / (s * s))
+                .sum::<f64>())
+            / prec;
+        assert!((sum / n as f64 - mean).abs() < 6.0 / (prec * n as f64).sqrt());
+    }
+
+    #[test]
+    fn missing_data_test() {
+        let src = "
+            theta <- logistic(a)
+            a ~ normal(0, 1.5)
+            for (i in 1:length(k)) { k[i] ~ binomial(theta, n[i]) }";
+        let d = data(&[("k", &[3.0, f64::NAN, 4.0]), ("n", &[10.0, 10.0, 8.0])]);
+        let inits = data(&[("k", &[0.0, 5.0, 0.0])]);
+        let mut g = compile_model(src, &d, &inits).unwrap();
+        g.seal();
+        let k2 = NodeTag::Vector("k".to_string(), 2);
+        assert_eq!(g.sampler_of(&k2), Some(Sampler::Enumeration));
+        let mut gv = g.init_gv();
+        assert_eq!(gv.sampleable_values.len(), 2);
+        assert_eq!(g.values_of(&k2, &gv), vec![5.0]);
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut nchanged = 0;
+        for _ in 0..100 {
+            g.sample_all(&mut gv, &mut rng, 10, &mut nchanged);
+            let k = g.values_of(&k2, &gv)[0];
+            assert!((0.0..=10.0).contains(&k) && k.fract() == 0.0);
+        }
+    }
+
+    #[test]
+    fn error_test() {
+        let d = data(&[("y", &[1.0, 2.0])]);
+        let cases: &[(&str, (usize, usize), &str)] = &[
+            (
+                "mu ~ normal(0, 1)\nx ~ normal(mu, 1",
+                (2, 17),
+                "expected ',' or ')'",
+            ),
+            ("mu ~ normal(0, 1) @", (1, 19), "unexpected character"),
+            (
+                "mu ~ normal(0, 1)\n  x <- mu +* 2",
+                (2, 12),
+                "expected an expression",
+            ),
+            (
+                "mu ~ normal(0, 1)\ny[1] ~ normal(nu, 1)",
+                (2, 15),
+                "'nu' is not defined",
+            ),
+            ("a <- b + 1\nb ~ normal(a, 1)", (2, 12), "depends on itself"),
+            ("a ~ normal(0)", (1, 5), "takes 2 arguments"),
+            ("a ~ norm(0, 1)", (1, 5), "unknown distribution"),
+            ("a <- foo(1)", (1, 6), "unknown function"),
+            (
+                "a ~ normal(0, 1)\na ~ normal(1, 1)",
+                (2, 1),
+                "defined more than once",
+            ),
+            (
+                "for (i in 1:3) {\n  y[i] ~ normal(0, 1)\n}",
+                (2, 3),
+                "out of the range",
+            ),
+            (
+                "for (i in 1:2) {\n  y[i + 0.5] ~ normal(0, 1)\n}",
+                (2, 7),
+                "not an integer",
+            ),
+            (
+                "m ~ normal(0, 1)\nfor (i in 1:m) {}",
+                (2, 13),
+                "must not depend",
+            ),
+            ("y ~ normal(0, 1)", (1, 1), "should be a scalar"),
+            (
+                "lambda ~ gamma(1, 1)\nk ~ poisson(lambda)",
+                (2, 5),
+                "only available",
+            ),
+        ];
+        for &(src, (line, column), msg) in cases {
+            let e = error_at(src, &d);
+            assert_eq!((e.0, e.1), (line, column), "{}: {}", src, e.2);
+            assert!(e.2.contains(msg), "{}: {}", src, e.2);
+        }
+    }
+}
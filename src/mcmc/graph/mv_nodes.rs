@@ -0,0 +1,746 @@
+#![cfg(not(target_family = "wasm"))]
+#![allow(clippy::needless_range_loop)]
+#![allow(clippy::type_complexity)]
+use std::fmt::Display;
+
+use num::traits::float::{Float, FloatConst};
+use rand::{Rng, RngCore};
+use rand_distr::{Beta as BetaDist, Binomial, ChiSquared, Gamma as GammaDist, StandardNormal};
+use special::Gamma;
+
+use super::super::functions::lbeta;
+use super::graph::NodeAdder;
+use super::graph::NodeHandle;
+use super::node::BlockUpdate;
+use super::node::Node;
+use crate::linear_space::utils::{cholesky, forward_solve};
+
+///A stochastic node with `ndim_output` values updated as a block
+fn block_node<T, L, R>(
+    parents: &[(NodeHandle, usize)],
+    init_values: Vec<T>,
+    logprob: L,
+    range: R,
+    discrete: bool,
+    block: BlockUpdate<T>,
+) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+    L: 'static + Fn(&[T], &[T]) -> T + Send + Sync,
+    R: 'static + Fn(&[T]) -> Vec<(T, T)> + Send + Sync,
+{
+    let n = NodeAdder::new(
+        Node::stochastic(parents.len(), init_values, logprob, range),
+        parents,
+    )
+    .with_block(block);
+    if discrete {
+        n.discrete()
+    } else {
+        n
+    }
+}
+
+fn std_normal<T>(rng: &mut dyn RngCore) -> T
+where
+    T: Float,
+{
+    T::from(rng.sample::<f64, _>(StandardNormal)).unwrap()
+}
+
+///Row-major `d*d` values as a matrix
+fn to_matrix<T>(x: &[T], d: usize) -> Vec<Vec<T>>
+where
+    T: Float,
+{
+    x.chunks(d).take(d).map(|r| r.to_vec()).collect()
+}
+
+fn identity<T>(d: usize) -> Vec<T>
+where
+    T: Float,
+{
+    (0..d * d)
+        .map(|k| if k / d == k % d { T::one() } else { T::zero() })
+        .collect()
+}
+
+///`l l^T` in row-major order
+fn lower_self_transpose<T>(l: &[Vec<T>]) -> Vec<T>
+where
+    T: Float,
+{
+    let d = l.len();
+    (0..d * d)
+        .map(|k| {
+            let (i, j) = (k / d, k % d);
+            (0..=i.min(j)).fold(T::zero(), |s, q| s + l[i][q] * l[j][q])
+        })
+        .collect()
+}
+
+fn log_sum_diag<T>(l: &[Vec<T>]) -> T
+where
+    T: Float,
+{
+    (0..l.len()).fold(T::zero(), |s, i| s + l[i][i].ln())
+}
+
+///Multivariate normal distribution with mean `mean` and the row-major covariance matrix `cov`,
+///updated by elliptical slice sampling
+pub fn mvnormal_node<T>(mean: &[(NodeHandle, usize)], cov: &[(NodeHandle, usize)]) -> NodeAdder<T>
+where
+    T: 'static + Float + FloatConst + Sync + Send + Display,
+{
+    let d = mean.len();
+    assert_eq!(cov.len(), d * d, "the covariance must be a d*d matrix");
+    let parents: Vec<_> = mean.iter().chain(cov.iter()).cloned().collect();
+    block_node(
+        &parents,
+        vec![T::zero(); d],
+        move |x: &[T], p: &[T]| {
+            let l = match cholesky(&to_matrix(&p[d..], d)) {
+                Some(l) => l,
+                None => return -T::infinity(),
+            };
+            let dx: Vec<T> = x.iter().zip(p.iter()).map(|(&x, &m)| x - m).collect();
+            let z = forward_solve(&l, &dx);
+            let two = T::one() + T::one();
+            -z.iter().fold(T::zero(), |s, &z| s + z * z) / two
+                - log_sum_diag(&l)
+                - T::from(d).unwrap() * (two * T::PI()).ln() / two
+        },
+        move |p: &[T]| {
+            (0..d)
+                .map(|k| {
+                    let s = p[d + k * d + k].sqrt() * T::from(10).unwrap();
+                    (p[k] - s, p[k] + s)
+                })
+                .collect()
+        },
+        false,
+        BlockUpdate::Elliptical(Box::new(move |p: &[T]| {
+            let l = cholesky(&to_matrix(&p[d..], d))
+                .expect("the covariance matrix is not positive definite");
+            (p[..d].to_vec(), l)
+        })),
+    )
+    .with_draw(move |p, rng| {
+        let l = cholesky(&to_matrix(&p[d..], d))
+            .expect("the covariance matrix is not positive definite");
+        let z: Vec<T> = (0..d).map(|_| std_normal(rng)).collect();
+        (0..d)
+            .map(|k| (0..=k).fold(p[k], |s, q| s + l[k][q] * z[q]))
+            .collect()
+    })
+}
+
+///Dirichlet distribution, updated by slice sampling in additive log-ratio coordinates
+pub fn dirichlet_node<T>(alpha: &[(NodeHandle, usize)]) -> NodeAdder<T>
+where
+    T: 'static + Float + Gamma + Sync + Send + Display,
+{
+    let k = alpha.len();
+    assert!(k >= 2);
+    block_node(
+        alpha,
+        vec![T::one() / T::from(k).unwrap(); k],
+        |x: &[T], a: &[T]| {
+            if x.iter().any(|&x| x <= T::zero()) {
+                return -T::infinity();
+            }
+            let a0 = a.iter().fold(T::zero(), |s, &a| s + a);
+            x.iter().zip(a.iter()).fold(a0.ln_gamma().0, |s, (&x, &a)| {
+                s + (a - T::one()) * x.ln() - a.ln_gamma().0
+            })
+        },
+        move |_p| vec![(T::min_positive_value(), T::one()); k],
+        false,
+        BlockUpdate::Transformed {
+            to_unconstrained: Box::new(|x: &[T]| {
+                let last = x[x.len() - 1].ln();
+                x[..x.len() - 1].iter().map(|&x| x.ln() - last).collect()
+            }),
+            from_unconstrained: Box::new(|y: &[T]| {
+                let ymax = y.iter().fold(T::zero(), |a, &b| a.max(b));
+                let e: Vec<T> = y
+                    .iter()
+                    .map(|&y| (y - ymax).exp())
+                    .chain(std::iter::once((-ymax).exp()))
+                    .collect();
+                let total = e.iter().fold(T::zero(), |s, &e| s + e);
+                let x: Vec<T> = e.iter().map(|&e| e / total).collect();
+                let log_jacobian = x.iter().fold(T::zero(), |s, &x| s + x.ln());
+                (x, log_jacobian)
+            }),
+        },
+    )
+    .with_draw(|a, rng| {
+        let g: Vec<f64> = a
+            .iter()
+            .map(|&a| rng.sample(GammaDist::new(a.to_f64().unwrap(), 1.0).unwrap()))
+            .collect();
+        let total: f64 = g.iter().sum();
+        g.iter().map(|&g| T::from(g / total).unwrap()).collect()
+    })
+}
+
+///Multinomial distribution of `n` trials with probabilities proportional to `p`.
+///The values are updated by moving single counts between the categories, of which there must be
+///at least two; unless they are given, all the trials start in the first category.
+pub fn multinomial_node<T>(p: &[(NodeHandle, usize)], n: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Gamma + Sync + Send + Display,
+{
+    let k = p.len();
+    //CountTransfer moves counts between two distinct categories
+    assert!(k >= 2);
+    let parents: Vec<_> = p.iter().cloned().chain(std::iter::once(n)).collect();
+    block_node(
+        &parents,
+        vec![T::zero(); k],
+        move |x: &[T], p: &[T]| {
+            let n = p[k];
+            let total = x.iter().fold(T::zero(), |s, &x| s + x);
+            if total != n || x.iter().any(|&x| x < T::zero() || x.fract() != T::zero()) {
+                return -T::infinity();
+            }
+            let psum = p[..k].iter().fold(T::zero(), |s, &p| s + p);
+            x.iter()
+                .zip(p.iter())
+                .fold((n + T::one()).ln_gamma().0, |s, (&x, &p)| {
+                    let t = if x == T::zero() {
+                        T::zero()
+                    } else {
+                        x * (p / psum).ln()
+                    };
+                    s + t - (x + T::one()).ln_gamma().0
+                })
+        },
+        move |p| vec![(T::zero(), p[k]); k],
+        true,
+        BlockUpdate::CountTransfer,
+    )
+    .with_draw(move |p, rng| {
+        //successive binomial draws from the remaining trials, the last category takes all the
+        //trials left, which the rounding of `rest` could otherwise lose
+        let mut n = p[k].to_u64().unwrap();
+        let mut rest = p[..k].iter().fold(0.0, |s, &p| s + p.to_f64().unwrap());
+        let mut x: Vec<T> = p[..k - 1]
+            .iter()
+            .map(|&p| {
+                let p = p.to_f64().unwrap();
+                let x = if rest > 0.0 {
+                    rng.sample(Binomial::new(n, (p / rest).min(1.0)).unwrap())
+                } else {
+                    0
+                };
+                n -= x;
+                rest -= p;
+                T::from(x).unwrap()
+            })
+            .collect();
+        x.push(T::from(n).unwrap());
+        x
+    })
+}
+
+///`ln(Gamma_d(a))`, the multivariate gamma function
+fn ln_mv_gamma<T>(a: T, d: usize) -> T
+where
+    T: Float + Gamma + FloatConst,
+{
+    let two = T::one() + T::one();
+    let dd = T::from(d).unwrap();
+    (0..d).fold(dd * (dd - T::one()) / (two * two) * T::PI().ln(), |s, j| {
+        s + (a - T::from(j).unwrap() / two).ln_gamma().0
+    })
+}
+
+///Unconstrained coordinates of a positive definite matrix, i.e., the logarithms of the diagonal
+///and the lower off-diagonal elements of its Cholesky factor
+fn spd_transform<T>(d: usize) -> BlockUpdate<T>
+where
+    T: 'static + Float,
+{
+    BlockUpdate::Transformed {
+        to_unconstrained: Box::new(move |x: &[T]| {
+            let l = cholesky(&to_matrix(x, d)).expect("the matrix is not positive definite");
+            let mut u: Vec<T> = (0..d).map(|i| l[i][i].ln()).collect();
+            for i in 0..d {
+                for j in 0..i {
+                    u.push(l[i][j]);
+                }
+            }
+            u
+        }),
+        from_unconstrained: Box::new(move |u: &[T]| {
+            let mut l = vec![vec![T::zero(); d]; d];
+            let mut k = d;
+            for i in 0..d {
+                l[i][i] = u[i].exp();
+                for j in 0..i {
+                    l[i][j] = u[k];
+                    k += 1;
+                }
+            }
+            //|dX/dL| = 2^d prod(l_ii^(d-i)) with 0-based i, and dl_ii/du_i = l_ii
+            let two = T::one() + T::one();
+            let log_jacobian = (0..d).fold(T::from(d).unwrap() * two.ln(), |s, i| {
+                s + T::from(d - i + 1).unwrap() * u[i]
+            });
+            (lower_self_transpose(&l), log_jacobian)
+        }),
+    }
+}
+
+///Wishart distribution with the row-major `d*d` scale matrix `scale` and `dof` degrees of
+///freedom; the values are the row-major elements of the matrix, whose density is given with
+///respect to the elements of the lower triangle
+pub fn wishart_node<T>(scale: &[(NodeHandle, usize)], dof: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Gamma + FloatConst + Sync + Send + Display,
+{
+    let d = (scale.len() as f64).sqrt() as usize;
+    assert_eq!(d * d, scale.len(), "the scale must be a d*d matrix");
+    let parents: Vec<_> = scale.iter().cloned().chain(std::iter::once(dof)).collect();
+    block_node(
+        &parents,
+        identity(d),
+        move |x: &[T], p: &[T]| {
+            let lx = match cholesky(&to_matrix(x, d)) {
+                Some(l) => l,
+                None => return -T::infinity(),
+            };
+            let lv = match cholesky(&to_matrix(&p[..d * d], d)) {
+                Some(l) => l,
+                None => return -T::infinity(),
+            };
+            let n = p[d * d];
+            let two = T::one() + T::one();
+            let dd = T::from(d).unwrap();
+            //tr(V^-1 X) = |lv^-1 lx|_F^2
+            let trace = (0..d).fold(T::zero(), |s, j| {
+                let col: Vec<T> = (0..d).map(|i| lx[i][j]).collect();
+                forward_solve(&lv, &col).iter().fold(s, |s, &z| s + z * z)
+            });
+            (n - dd - T::one()) * log_sum_diag(&lx)
+                - trace / two
+                - n * dd / two * two.ln()
+                - n * log_sum_diag(&lv)
+                - ln_mv_gamma(n / two, d)
+        },
+        move |_p| vec![(T::neg_infinity(), T::infinity()); d * d],
+        false,
+        spd_transform(d),
+    )
+    .with_draw(move |p, rng| {
+        //Bartlett decomposition, X = (L A)(L A)^T with the Cholesky factor L of the scale
+        let lv = cholesky(&to_matrix(&p[..d * d], d)).expect("the scale is not positive definite");
+        let n = p[d * d].to_f64().unwrap();
+        let mut a = vec![vec![T::zero(); d]; d];
+        for i in 0..d {
+            let c = rng.sample(ChiSquared::new(n - i as f64).unwrap());
+            a[i][i] = T::from(c).unwrap().sqrt();
+            for j in 0..i {
+                a[i][j] = std_normal(rng);
+            }
+        }
+        let la: Vec<Vec<T>> = (0..d)
+            .map(|i| {
+                (0..d)
+                    .map(|j| (j..=i).fold(T::zero(), |s, q| s + lv[i][q] * a[q][j]))
+                    .collect()
+            })
+            .collect();
+        lower_self_transpose(&la)
+    })
+}
+
+///`ln(c_d(eta))`, the normalizing constant of the LKJ distribution (Lewandowski et al. 2009)
+fn ln_lkj_norm<T>(eta: T, d: usize) -> T
+where
+    T: Float + Gamma,
+{
+    let two = T::one() + T::one();
+    (1..d).fold(T::zero(), |s, k| {
+        let dk = T::from(d - k).unwrap();
+        let b = eta + (dk - T::one()) / two;
+        s + (two * eta - two + dk) * dk * two.ln() + dk * lbeta(b, b)
+    })
+}
+
+///LKJ distribution of `d*d` correlation matrices, with density proportional to
+///`det(R)^(eta-1)`; the values are the row-major elements of the matrix, which are updated in
+///the space of the canonical partial correlations transformed by `atanh`
+pub fn lkj_node<T>(eta: (NodeHandle, usize), d: usize) -> NodeAdder<T>
+where
+    T: 'static + Float + Gamma + Sync + Send + Display,
+{
+    assert!(d >= 2);
+    block_node(
+        &[eta],
+        identity(d),
+        move |x: &[T], p: &[T]| {
+            let eta = p[0];
+            match cholesky(&to_matrix(x, d)) {
+                Some(l) => {
+                    (eta - T::one()) * (T::one() + T::one()) * log_sum_diag(&l)
+                        - ln_lkj_norm(eta, d)
+                }
+                None => -T::infinity(),
+            }
+        },
+        move |_p| vec![(-T::one(), T::one()); d * d],
+        false,
+        BlockUpdate::Transformed {
+            to_unconstrained: Box::new(move |x: &[T]| {
+                let l = cholesky(&to_matrix(x, d)).expect("the matrix is not positive definite");
+                let mut u = Vec::new();
+                for i in 1..d {
+                    let mut sum_sqs = T::zero();
+                    for j in 0..i {
+                        let z = l[i][j] / (T::one() - sum_sqs).sqrt();
+                        u.push(z.atanh());
+                        sum_sqs = sum_sqs + l[i][j] * l[i][j];
+                    }
+                }
+                u
+            }),
+            from_unconstrained: Box::new(move |u: &[T]| {
+                let two = T::one() + T::one();
+                let mut l = vec![vec![T::zero(); d]; d];
+                l[0][0] = T::one();
+                let mut log_jacobian = T::zero();
+                let mut k = 0;
+                for i in 1..d {
+                    let mut sum_sqs = T::zero();
+                    for j in 0..i {
+                        let z = u[k].tanh();
+                        k += 1;
+                        l[i][j] = z * (T::one() - sum_sqs).sqrt();
+                        sum_sqs = sum_sqs + l[i][j] * l[i][j];
+                        //the atanh transform and the Jacobian of the C-vine
+                        log_jacobian =
+                            log_jacobian + T::from(d - j).unwrap() / two * (T::one() - z * z).ln();
+                    }
+                    l[i][i] = (T::one() - sum_sqs).max(T::zero()).sqrt();
+                }
+                (lower_self_transpose(&l), log_jacobian)
+            }),
+        },
+    )
+    .with_draw(move |p, rng| {
+        //the canonical partial correlations in column j are 2*Beta(b, b)-1 with
+        //b = eta + (d-2-j)/2
+        let eta = p[0].to_f64().unwrap();
+        let mut l = vec![vec![T::zero(); d]; d];
+        l[0][0] = T::one();
+        for i in 1..d {
+            let mut sum_sqs = T::zero();
+            for j in 0..i {
+                let b = eta + (d - 2 - j) as f64 / 2.0;
+                let z = T::from(2.0 * rng.sample(BetaDist::new(b, b).unwrap()) - 1.0).unwrap();
+                l[i][j] = z * (T::one() - sum_sqs).sqrt();
+                sum_sqs = sum_sqs + l[i][j] * l[i][j];
+            }
+            l[i][i] = (T::one() - sum_sqs).max(T::zero()).sqrt();
+        }
+        lower_self_transpose(&l)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcmc::graph::graph::Graph;
+    use crate::mcmc::graph::graph::ParamObservability::Observed;
+    use crate::mcmc::graph::nodes::{const_node, normal_node};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const N: usize = 20000;
+
+    fn consts(g: &mut Graph<String, f64>, prefix: &str, v: &[f64]) -> Vec<(NodeHandle, usize)> {
+        v.iter()
+            .enumerate()
+            .map(|(i, &v)| (const_node(v).add_to(g, &format!("{}{}", prefix, i)), 0))
+            .collect()
+    }
+
+    fn draws(g: &mut Graph<String, f64>, n: usize, thin: usize) -> Vec<Vec<f64>> {
+        g.seal();
+        let mut gv = g.init_gv();
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut nchanged = 0;
+        for _ in 0..200 {
+            g.sample_all(&mut gv, &mut rng, 10, &mut nchanged);
+        }
+        (0..n)
+            .map(|_| {
+                for _ in 0..thin {
+                    g.sample_all(&mut gv, &mut rng, 10, &mut nchanged);
+                }
+                gv.sampleable_values.clone()
+            })
+            .collect()
+    }
+
+    fn moments(x: &[Vec<f64>], i: usize, j: usize) -> (f64, f64) {
+        let n = x.len() as f64;
+        let mi = x.iter().map(|x| x[i]).sum::<f64>() / n;
+        let mj = x.iter().map(|x| x[j]).sum::<f64>() / n;
+        let c = x.iter().map(|x| (x[i] - mi) * (x[j] - mj)).sum::<f64>() / (n - 1.0);
+        (mi, c)
+    }
+
+    fn check(name: &str, x: &[Vec<f64>], i: usize, j: usize, mean: f64, cov: f64) {
+        let (m, c) = moments(x, i, j);
+        let sd = (moments(x, i, i).1 / x.len() as f64).sqrt();
+        assert!(
+            (m - mean).abs() < 8.0 * sd,
+            "{} mean[{}] {} {}",
+            name,
+            i,
+            m,
+            mean
+        );
+        assert!(
+            (c - cov).abs() < 0.1 * cov.abs().max(0.1 * moments(x, i, i).1),
+            "{} cov[{}][{}] {} {}",
+            name,
+            i,
+            j,
+            c,
+            cov
+        );
+    }
+
+    fn simulated(g: &mut Graph<String, f64>, n: usize) -> Vec<Vec<f64>> {
+        g.seal();
+        let mut gv = g.init_gv();
+        let mut rng = StdRng::seed_from_u64(12345);
+        (0..n)
+            .map(|_| {
+                g.simulate(&mut gv, &mut rng, None);
+                gv.sampleable_values.clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn draw_test() {
+        let (m, s) = ([1.0, -1.0], [2.0, 1.6, 1.6, 2.0]);
+        let mut g = Graph::new();
+        let mean = consts(&mut g, "m", &m);
+        let cov = consts(&mut g, "s", &s);
+        mvnormal_node(&mean, &cov).add_to(&mut g, &"x".to_string());
+        let x = simulated(&mut g, N);
+        for i in 0..2 {
+            for j in 0..2 {
+                check("mvnormal", &x, i, j, m[i], s[i * 2 + j]);
+            }
+        }
+
+        let alpha = [1.0, 2.0, 3.0];
+        let mut g = Graph::new();
+        let a = consts(&mut g, "a", &alpha);
+        dirichlet_node(&a).add_to(&mut g, &"p".to_string());
+        let x = simulated(&mut g, N);
+        let a0: f64 = alpha.iter().sum();
+        for i in 0..3 {
+            for j in 0..3 {
+                let d = if i == j { alpha[i] / a0 } else { 0.0 };
+                let cov = (d - alpha[i] * alpha[j] / (a0 * a0)) / (a0 + 1.0);
+                check("dirichlet", &x, i, j, alpha[i] / a0, cov);
+            }
+        }
+
+        let (probs, n) = ([0.2, 0.3, 0.5], 10.0);
+        let mut g = Graph::new();
+        let p = consts(&mut g, "p", &probs);
+        let nn = const_node(n).add_to(&mut g, &"n".to_string());
+        multinomial_node(&p, (nn, 0)).add_to(&mut g, &"c".to_string());
+        let x = simulated(&mut g, N);
+        for i in 0..3 {
+            for j in 0..3 {
+                let d = if i == j { probs[i] } else { 0.0 };
+                let cov = n * (d - probs[i] * probs[j]);
+                check("multinomial", &x, i, j, n * probs[i], cov);
+            }
+        }
+        assert!(x.iter().all(|x| x.iter().sum::<f64>() == n));
+
+        let (v, n) = ([[1.0, 0.5], [0.5, 2.0]], 6.0);
+        let mut g = Graph::new();
+        let scale = consts(&mut g, "v", &[v[0][0], v[0][1], v[1][0], v[1][1]]);
+        let dof = const_node(n).add_to(&mut g, &"dof".to_string());
+        wishart_node(&scale, (dof, 0)).add_to(&mut g, &"w".to_string());
+        let x = simulated(&mut g, N);
+        for &(i, j) in &[(0, 0), (0, 1), (1, 1)] {
+            let k = i * 2 + j;
+            let var = n * (v[i][j] * v[i][j] + v[i][i] * v[j][j]);
+            check("wishart", &x, k, k, n * v[i][j], var);
+        }
+
+        let (eta, d) = (2.0, 3);
+        let mut g = Graph::new();
+        let e = const_node(eta).add_to(&mut g, &"eta".to_string());
+        lkj_node((e, 0), d).add_to(&mut g, &"r".to_string());
+        let x = simulated(&mut g, N);
+        let b = eta - 1.0 + d as f64 / 2.0;
+        for &(i, j) in &[(0, 1), (0, 2), (1, 2)] {
+            let k = i * d + j;
+            check("lkj", &x, k, k, 0.0, 1.0 / (2.0 * b + 1.0));
+        }
+    }
+
+    #[test]
+    fn mvnormal_test() {
+        //prior N(m, S) and y_k ~ N(x_k, 1): the posterior is N(P(S^-1 m + y), P) with
+        //P = (S^-1 + I)^-1
+        let (m, s) = ([1.0, -1.0], [[2.0, 1.6], [1.6, 2.0]]);
+        let y = [0.5, 0.0];
+        let mut g = Graph::new();
+        let mean = consts(&mut g, "m", &m);
+        let cov = consts(&mut g, "s", &[s[0][0], s[0][1], s[1][0], s[1][1]]);
+        let one = const_node(1.0).add_to(&mut g, &"one".to_string());
+        let x = mvnormal_node(&mean, &cov).add_to(&mut g, &"x".to_string());
+        for (k, &y) in y.iter().enumerate() {
+            normal_node((x, k), (one, 0))
+                .with_all_values(&[Observed(y)])
+                .add_to(&mut g, &format!("y{}", k));
+        }
+        let det = s[0][0] * s[1][1] - s[0][1] * s[1][0];
+        let si = [
+            [s[1][1] / det, -s[0][1] / det],
+            [-s[1][0] / det, s[0][0] / det],
+        ];
+        let a = [[si[0][0] + 1.0, si[0][1]], [si[1][0], si[1][1] + 1.0]];
+        let deta = a[0][0] * a[1][1] - a[0][1] * a[1][0];
+        let p = [
+            [a[1][1] / deta, -a[0][1] / deta],
+            [-a[1][0] / deta, a[0][0] / deta],
+        ];
+        let b = [
+            si[0][0] * m[0] + si[0][1] * m[1] + y[0],
+            si[1][0] * m[0] + si[1][1] * m[1] + y[1],
+        ];
+        let mp = [
+            p[0][0] * b[0] + p[0][1] * b[1],
+            p[1][0] * b[0] + p[1][1] * b[1],
+        ];
+
+        let x = draws(&mut g, N, 1);
+        for i in 0..2 {
+            for j in 0..2 {
+                check("mvnormal", &x, i, j, mp[i], p[i][j]);
+            }
+        }
+    }
+
+    #[test]
+    fn dirichlet_multinomial_test() {
+        //Dirichlet(a) prior and multinomial counts c: the posterior is Dirichlet(a+c)
+        let alpha = [1.0, 2.0, 3.0];
+        let counts = [4.0, 1.0, 5.0];
+        let mut g = Graph::new();
+        let a = consts(&mut g, "a", &alpha);
+        let n = const_node(counts.iter().sum::<f64>()).add_to(&mut g, &"n".to_string());
+        let p = dirichlet_node(&a).add_to(&mut g, &"p".to_string());
+        multinomial_node(&[(p, 0), (p, 1), (p, 2)], (n, 0))
+            .with_all_values(&[
+                Observed(counts[0]),
+                Observed(counts[1]),
+                Observed(counts[2]),
+            ])
+            .add_to(&mut g, &"c".to_string());
+        let x = draws(&mut g, N, 1);
+        let a1: Vec<f64> = alpha
+            .iter()
+            .zip(counts.iter())
+            .map(|(a, c)| a + c)
+            .collect();
+        let a0: f64 = a1.iter().sum();
+        for i in 0..3 {
+            for j in 0..3 {
+                let d = if i == j { a1[i] / a0 } else { 0.0 };
+                let cov = (d - a1[i] * a1[j] / (a0 * a0)) / (a0 + 1.0);
+                check("dirichlet", &x, i, j, a1[i] / a0, cov);
+            }
+        }
+        assert!(x
+            .iter()
+            .all(|x| (x.iter().sum::<f64>() - 1.0).abs() < 1e-10));
+    }
+
+    #[test]
+    fn multinomial_test() {
+        let probs = [0.2, 0.3, 0.5];
+        let n = 10.0;
+        let mut g = Graph::new();
+        let p = consts(&mut g, "p", &probs);
+        let nn = const_node(n).add_to(&mut g, &"n".to_string());
+        //no initial values given: all the trials start in the first category
+        multinomial_node(&p, (nn, 0)).add_to(&mut g, &"c".to_string());
+        let x = draws(&mut g, N, 10);
+        assert_eq!(g.init_gv().sampleable_values, vec![n, 0.0, 0.0]);
+        for i in 0..3 {
+            for j in 0..3 {
+                let d = if i == j { probs[i] } else { 0.0 };
+                check(
+                    "multinomial",
+                    &x,
+                    i,
+                    j,
+                    n * probs[i],
+                    n * (d - probs[i] * probs[j]),
+                );
+            }
+        }
+        assert!(x.iter().all(|x| x.iter().sum::<f64>() == n));
+    }
+
+    #[test]
+    #[should_panic]
+    fn single_category_multinomial_test() {
+        let mut g: Graph<String, f64> = Graph::new();
+        let p = consts(&mut g, "p", &[1.0]);
+        let nn = const_node(10.0).add_to(&mut g, &"n".to_string());
+        multinomial_node::<f64>(&p, (nn, 0));
+    }
+
+    #[test]
+    fn wishart_test() {
+        let v = [[1.0, 0.5], [0.5, 2.0]];
+        let n = 6.0;
+        let mut g = Graph::new();
+        let scale = consts(&mut g, "v", &[v[0][0], v[0][1], v[1][0], v[1][1]]);
+        let dof = const_node(n).add_to(&mut g, &"dof".to_string());
+        wishart_node(&scale, (dof, 0)).add_to(&mut g, &"w".to_string());
+        let x = draws(&mut g, N, 1);
+        for &(i, j) in &[(0, 0), (0, 1), (1, 1)] {
+            let k = i * 2 + j;
+            let var = n * (v[i][j] * v[i][j] + v[i][i] * v[j][j]);
+            check("wishart", &x, k, k, n * v[i][j], var);
+        }
+        assert!(x.iter().all(|x| x[1] == x[2]));
+    }
+
+    #[test]
+    fn lkj_test() {
+        //the marginal of each correlation is (r+1)/2 ~ Beta(b, b) with b = eta-1+d/2
+        let (eta, d) = (2.0, 3);
+        let mut g = Graph::new();
+        let e = const_node(eta).add_to(&mut g, &"eta".to_string());
+        lkj_node((e, 0), d).add_to(&mut g, &"r".to_string());
+        let x = draws(&mut g, N, 1);
+        let b = eta - 1.0 + d as f64 / 2.0;
+        for &(i, j) in &[(0, 1), (0, 2), (1, 2)] {
+            let k = i * d + j;
+            check("lkj", &x, k, k, 0.0, 1.0 / (2.0 * b + 1.0));
+        }
+        assert!(x
+            .iter()
+            .all(|x| (x[0] - 1.0).abs() < 1e-10 && (x[4] - 1.0).abs() < 1e-10));
+    }
+}
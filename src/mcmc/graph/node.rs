@@ -2,6 +2,7 @@
 use std::fmt::{Display, Error, Formatter};
 
 use num::traits::float::Float;
+use rand::RngCore;
 
 pub enum ValueType {
     FIXED,
@@ -18,21 +19,77 @@ pub struct BasicNode {
     pub ndim_output: usize,
 }
 
+///How a multivariate stochastic node is updated as a block
+pub enum BlockUpdate<T> {
+    ///Elliptical slice sampling, for nodes with a Gaussian distribution, whose mean and lower
+    ///Cholesky factor of the covariance are computed from the parent values
+    Elliptical(Box<dyn Fn(&[T]) -> (Vec<T>, Vec<Vec<T>>) + Send + Sync>),
+    ///Slice sampling along a random direction in an unconstrained space,
+    ///`from_unconstrained` returns the values and `ln|J|` of the transform
+    Transformed {
+        to_unconstrained: Box<dyn Fn(&[T]) -> Vec<T> + Send + Sync>,
+        from_unconstrained: Box<dyn Fn(&[T]) -> (Vec<T>, T) + Send + Sync>,
+    },
+    ///Metropolis moves of one count between two components, which preserve the total given by
+    ///the last parent value. Unobserved values that do not sum to it are replaced at
+    ///`Graph::init_gv` by the total in the first component.
+    CountTransfer,
+}
+
+///Distribution family of a scalar stochastic node, with the parents in the order of the
+///corresponding constructor in `nodes`, used by `Graph::seal` to detect conjugacy
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Family {
+    ///Mean and standard deviation
+    Normal,
+    ///Mean and variance
+    NormalVar,
+    ///Shape and rate
+    Gamma,
+    ///Shape and scale
+    InvGamma,
+    Beta,
+    ///Mean
+    Poisson,
+    ///Success probability and number of trials
+    Binomial,
+    ///Success probability
+    Bernoulli,
+    Other,
+}
+
 pub enum NodeContent<T>
 where
     T: Float + Sync + Send + Display,
 {
+    ///Built with `Node::stochastic`, the optional properties are then set through `NodeAdder`
+    #[non_exhaustive]
     StochasticNode {
         all_stochastic_children: Vec<usize>,
         all_deterministic_children: Vec<usize>,
         is_observed: Vec<bool>,
         values: Vec<T>,
-        logprob: Box<dyn Fn(&[T], &[T]) -> T>,
-        range: Box<dyn Fn(&[T]) -> Vec<(T, T)>>,
+        logprob: Box<dyn Fn(&[T], &[T]) -> T + Send + Sync>,
+        range: Box<dyn Fn(&[T]) -> Vec<(T, T)> + Send + Sync>,
+        ///The values are integers and `range` gives the inclusive bounds of their finite
+        ///support, which is enumerated by `Graph::sample` instead of calling ARMS
+        discrete: bool,
+        ///If set, all the values are updated jointly by `Graph::sample_all`
+        block: Option<BlockUpdate<T>>,
+        family: Family,
+        ///The values are conditionally independent and identically distributed, and `logprob`
+        ///and `range` are those of a single value
+        plate: bool,
+        ///For a plate, the stochastic and the deterministic children that read each value,
+        ///directly or through deterministic nodes, filled by `Graph::seal`
+        element_children: Vec<(Vec<usize>, Vec<usize>)>,
+        ///Draws the values given the parent values, for `Graph::simulate`; for a plate, a single
+        ///value
+        draw: Option<Box<dyn Fn(&[T], &mut dyn RngCore) -> Vec<T> + Send + Sync>>,
     },
 
     DeterministicNode {
-        calc: Box<dyn Fn(&[T]) -> Vec<T>>,
+        calc: Box<dyn Fn(&[T]) -> Vec<T> + Send + Sync>,
     },
 }
 
@@ -51,6 +108,40 @@ where
     pub fn get_children(&self) -> &Vec<usize> {
         &self.info.children
     }
+
+    ///A continuous stochastic node with `ndim_input` parents and the given initial unobserved
+    ///values, updated element by element with ARMS. `logprob` takes the values and the parent
+    ///values, `range` gives the support of every value given the parent values.
+    pub fn stochastic<L, R>(ndim_input: usize, values: Vec<T>, logprob: L, range: R) -> Node<T>
+    where
+        L: 'static + Fn(&[T], &[T]) -> T + Send + Sync,
+        R: 'static + Fn(&[T]) -> Vec<(T, T)> + Send + Sync,
+    {
+        Node {
+            info: BasicNode {
+                parents: Vec::new(),
+                children: Vec::new(),
+                idx_in_var: Vec::new(),
+                value_type: Vec::new(),
+                ndim_input,
+                ndim_output: values.len(),
+            },
+            content: NodeContent::StochasticNode {
+                all_stochastic_children: Vec::new(),
+                all_deterministic_children: Vec::new(),
+                is_observed: vec![false; values.len()],
+                values,
+                logprob: Box::new(logprob),
+                range: Box::new(range),
+                discrete: false,
+                block: None,
+                family: Family::Other,
+                plate: false,
+                element_children: Vec::new(),
+                draw: None,
+            },
+        }
+    }
 }
 
 impl<T> Display for Node<T>
@@ -4,19 +4,42 @@ use std::fmt::Display;
 use special::{Error, Gamma};
 
 #[cfg(not(target_family = "wasm"))]
-use super::super::functions::phi;
+use super::super::functions::{lbeta, logdbin, phi};
 
 use super::graph::NodeAdder;
 use super::graph::NodeHandle;
+use super::graph::ParamObservability::Observed;
 use super::node::BasicNode;
+use super::node::Family;
 use super::node::Node;
 use super::node::NodeContent;
 use num::traits::{
     float::{Float, FloatConst},
     identities::{one, zero},
 };
+use rand::distributions::{Bernoulli, Distribution, Open01, Uniform, WeightedIndex};
+use rand::RngCore;
+#[cfg(not(target_family = "wasm"))]
+use rand_distr::{Beta as BetaDist, Binomial, Gamma as GammaDist, Poisson, StudentT};
+use rand_distr::{Cauchy, Exp, Exp1, LogNormal, Normal, Pareto, Weibull};
 use std::boxed::Box;
 
+fn f64_of<T>(x: T) -> f64
+where
+    T: Float,
+{
+    x.to_f64().unwrap()
+}
+
+///A single value drawn from a distribution over `f64`
+fn draw_f64<T, D>(d: D, rng: &mut dyn RngCore) -> Vec<T>
+where
+    T: Float,
+    D: Distribution<f64>,
+{
+    vec![T::from(d.sample(rng)).unwrap()]
+}
+
 pub fn const_node<T>(v: T) -> NodeAdder<T>
 where
     T: 'static + Float + Sync + Send + Display,
@@ -91,7 +114,7 @@ where
             ndim_output: 1,
         },
         content: NodeContent::DeterministicNode {
-            calc: Box::new(move |x| vec![x[0] / x[1]]),
+            calc: Box::new(move |x| vec![x[0] - x[1]]),
         },
     };
     NodeAdder::new(n, &[a, b])
@@ -144,76 +167,63 @@ where
     let two = one::<T>() + one::<T>();
     let four = two + two;
     let pi = one::<T>().atan() * four;
-    let n = Node {
-        info: BasicNode {
-            parents: Vec::new(),
-            children: Vec::new(),
-            idx_in_var: Vec::new(),
-            value_type: Vec::new(),
-            ndim_input: 2,
-            ndim_output: 1,
+    scalar_stochastic_node(
+        &[m, s],
+        move |x, p| {
+            let x = x[0];
+            let m = p[0];
+            let s = p[1];
+            -((x - m) * (x - m) / ((one::<T>() + one::<T>()) * s * s))
+                - ((two * pi).sqrt() * s).ln()
         },
-        content: NodeContent::StochasticNode {
-            all_stochastic_children: Vec::new(),
-            all_deterministic_children: Vec::new(),
-            is_observed: vec![false],
-            values: vec![zero()],
-            logprob: Box::new(move |x, p| {
-                let x = x[0];
-                let m = p[0];
-                let s = p[1];
-                -((x - m) * (x - m) / ((one::<T>() + one::<T>()) * s * s))
-                    - ((two * pi).sqrt() * s).ln()
-            }),
-            range: Box::new(move |p| {
-                let m = p[0];
-                let s = p[1];
-                let x1 = m - T::from(10).unwrap() * s;
-                let x2 = m + T::from(10).unwrap() * s;
-                vec![(x1, x2)]
-            }),
+        real_range,
+    )
+    .with_family(Family::Normal)
+    .with_draw(|p, rng| draw_f64(Normal::new(f64_of(p[0]), f64_of(p[1])).unwrap(), rng))
+}
+
+///Normal distribution with mean `m` and variance `v`
+pub fn normal_var_node<T>(m: (NodeHandle, usize), v: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + FloatConst + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[m, v],
+        |x: &[T], p: &[T]| {
+            let (x, m, v) = (x[0], p[0], p[1]);
+            let two = T::one() + T::one();
+            -(x - m) * (x - m) / (two * v) - (two * T::PI() * v).ln() / two
         },
-    };
-    NodeAdder::new(n, &[m, s])
+        real_range,
+    )
+    .with_family(Family::NormalVar)
+    .with_draw(|p, rng| draw_f64(Normal::new(f64_of(p[0]), f64_of(p[1]).sqrt()).unwrap(), rng))
 }
 
+///Uniform distribution over `[a, b]`
 pub fn uniform_node<T>(a: (NodeHandle, usize), b: (NodeHandle, usize)) -> NodeAdder<T>
 where
     T: 'static + Float + Sync + Send + Display,
 {
-    let n = Node {
-        info: BasicNode {
-            parents: Vec::new(),
-            children: Vec::new(),
-            idx_in_var: Vec::new(),
-            value_type: Vec::new(),
-            ndim_input: 2,
-            ndim_output: 1,
+    scalar_stochastic_node(
+        &[a, b],
+        move |x: &[T], p: &[T]| {
+            let x1 = p[0];
+            let x2 = p[1];
+            let x = x[0];
+            if x <= x2 && x >= x1 {
+                -(x2 - x1).ln()
+            } else {
+                T::neg_infinity()
+            }
         },
-        content: NodeContent::StochasticNode {
-            all_stochastic_children: Vec::new(),
-            all_deterministic_children: Vec::new(),
-            is_observed: vec![false],
-            values: vec![zero()],
-            logprob: Box::new(move |x, p| {
-                let x1 = p[0];
-                let x2 = p[1];
-                let x = x[0];
-                if x <= x2 && x >= x1 {
-                    zero()
-                } else {
-                    <T as Float>::infinity()
-                }
-            }),
-            range: Box::new(move |p| {
-                let x1 = p[0];
-                let x2 = p[1];
-                vec![(x1, x2)]
-            }),
+        move |p| {
+            let x1 = p[0];
+            let x2 = p[1];
+            vec![(x1, x2)]
         },
-    };
-
-    NodeAdder::new(n, &[a, b])
+    )
+    .with_draw(|p, rng| draw_f64(Uniform::new(f64_of(p[0]), f64_of(p[1])), rng))
 }
 
 #[cfg(not(target_family = "wasm"))]
@@ -238,7 +248,10 @@ where
 }
 
 #[allow(clippy::type_complexity)]
-pub fn scalar_func_node<T>(x: &[(NodeHandle, usize)], func: Box<dyn Fn(&[T]) -> T>) -> NodeAdder<T>
+pub fn scalar_func_node<T>(
+    x: &[(NodeHandle, usize)],
+    func: Box<dyn Fn(&[T]) -> T + Send + Sync>,
+) -> NodeAdder<T>
 where
     T: 'static + Float + Sync + Send + Display,
 {
@@ -258,6 +271,61 @@ where
     NodeAdder::new(n, x)
 }
 
+pub fn neg_node<T>(a: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+{
+    scalar_func_node(&[a], Box::new(|x: &[T]| -x[0]))
+}
+
+///`exp(a)`, the exponential distribution being `exp_node`
+pub fn exp_func_node<T>(a: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+{
+    scalar_func_node(&[a], Box::new(|x: &[T]| x[0].exp()))
+}
+
+pub fn sin_node<T>(a: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+{
+    scalar_func_node(&[a], Box::new(|x: &[T]| x[0].sin()))
+}
+
+pub fn sqrt_node<T>(a: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+{
+    scalar_func_node(&[a], Box::new(|x: &[T]| x[0].sqrt()))
+}
+
+pub fn abs_node<T>(a: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+{
+    scalar_func_node(&[a], Box::new(|x: &[T]| x[0].abs()))
+}
+
+///`a^b`
+pub fn pow_node<T>(a: (NodeHandle, usize), b: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+{
+    scalar_func_node(&[a, b], Box::new(|x: &[T]| x[0].powf(x[1])))
+}
+
+///`1/(1+exp(-a))`, the logistic distribution being `logistic_node`
+pub fn logistic_func_node<T>(a: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+{
+    scalar_func_node(
+        &[a],
+        Box::new(|x: &[T]| T::one() / (T::one() + (-x[0]).exp())),
+    )
+}
+
 pub fn ln_node<T>(x: (NodeHandle, usize)) -> NodeAdder<T>
 where
     T: 'static + Float + Sync + Send + Display,
@@ -301,90 +369,56 @@ where
 #[cfg(not(target_family = "wasm"))]
 pub fn t_node<T>(mu: (NodeHandle, usize), sigma: (NodeHandle, usize), dof: usize) -> NodeAdder<T>
 where
-    T: 'static + Float + Gamma +Error + FloatConst + Sync + Send + Display,
+    T: 'static + Float + Gamma + Error + FloatConst + Sync + Send + Display,
 {
-    let n = Node {
-        info: BasicNode {
-            parents: Vec::new(),
-            children: Vec::new(),
-            idx_in_var: Vec::new(),
-            value_type: Vec::new(),
-            ndim_input: 2,
-            ndim_output: 1,
-        },
-        content: NodeContent::StochasticNode {
-            all_stochastic_children: Vec::new(),
-            all_deterministic_children: Vec::new(),
-            is_observed: vec![false],
-            values: vec![zero()],
-            logprob: Box::new(move |x, p| {
-                let x: T = x[0];
-                let m = p[0];
-                let s = p[1];
-
-                let tau = T::one() / s.powi(2);
-                let k = T::from(dof).unwrap();
-                let two = T::one() + T::one();
-                T::ln_gamma((k + T::one()) / two).0 - T::ln_gamma(k / two).0
-                    + T::ln(tau / k / T::PI()) / two
-                    - (k + T::one()) / two * T::ln(T::one() + tau * (x - m).powi(2) / k)
-            }),
-            range: Box::new(move |p| {
-                let m = p[0];
-                let s = p[1];
-                let x1 = m - T::from(5).unwrap() * s;
-                let x2 = m + T::from(5).unwrap() * s;
-                vec![(x1, x2)]
-            }),
+    scalar_stochastic_node(
+        &[mu, sigma],
+        move |x, p| {
+            let x: T = x[0];
+            let m = p[0];
+            let s = p[1];
+
+            let tau = T::one() / s.powi(2);
+            let k = T::from(dof).unwrap();
+            let two = T::one() + T::one();
+            T::ln_gamma((k + T::one()) / two).0 - T::ln_gamma(k / two).0
+                + T::ln(tau / k / T::PI()) / two
+                - (k + T::one()) / two * T::ln(T::one() + tau * (x - m).powi(2) / k)
         },
-    };
-    NodeAdder::new(n, &[mu, sigma])
+        real_range,
+    )
+    .with_draw(move |p, rng| {
+        let t = StudentT::new(dof as f64).unwrap().sample(rng);
+        vec![p[0] + p[1] * T::from(t).unwrap()]
+    })
 }
 
 pub fn pareto_node<T>(a: (NodeHandle, usize), c: (NodeHandle, usize)) -> NodeAdder<T>
 where
     T: 'static + Float + FloatConst + Sync + Send + Display,
 {
-    let n = Node {
-        info: BasicNode {
-            parents: Vec::new(),
-            children: Vec::new(),
-            idx_in_var: Vec::new(),
-            value_type: Vec::new(),
-            ndim_input: 2,
-            ndim_output: 1,
+    scalar_stochastic_node(
+        &[a, c],
+        move |x, p| {
+            let x: T = x[0];
+            let a = p[0];
+            let c = p[1];
+
+            if x < c {
+                //panic!();
+                -T::infinity()
+            } else {
+                let result = a.ln() + a * c.ln() - (a + T::one()) * x.ln();
+                assert!(result.is_finite());
+                result
+            }
         },
-        content: NodeContent::StochasticNode {
-            all_stochastic_children: Vec::new(),
-            all_deterministic_children: Vec::new(),
-            is_observed: vec![false],
-            values: vec![zero()],
-            logprob: Box::new(move |x, p| {
-                let x: T = x[0];
-                let a = p[0];
-                let c = p[1];
-
-                if x < c {
-                    //panic!();
-                    -T::infinity()
-                } else {
-                    let result = a.ln() + a * c.ln() - (a + T::one()) * x.ln();
-                    assert!(result.is_finite());
-                    result
-                }
-            }),
-            range: Box::new(move |p| {
-                let a = p[0];
-                let c = p[1];
-                let eta = T::from(1e-4).unwrap();
-                let x1 = c + T::from(1e-19).unwrap();
-                let x2 = c * eta.powf(-T::one() / a);
-                assert!(x2 > x1);
-                vec![(x1, x2)]
-            }),
+        move |p| {
+            let c = p[1];
+            vec![(c, T::infinity())]
         },
-    };
-    NodeAdder::new(n, &[a, c])
+    )
+    .with_draw(|p, rng| draw_f64(Pareto::new(f64_of(p[1]), f64_of(p[0])).unwrap(), rng))
 }
 
 #[cfg(not(target_family = "wasm"))]
@@ -397,44 +431,1270 @@ pub fn trunc_pareto_node<T>(
 where
     T: 'static + Float + FloatConst + Sync + Send + Display,
 {
-    let n = Node {
-        info: BasicNode {
-            parents: Vec::new(),
-            children: Vec::new(),
-            idx_in_var: Vec::new(),
-            value_type: Vec::new(),
-            ndim_input: 2,
-            ndim_output: 1,
+    scalar_stochastic_node(
+        &[a, c],
+        move |x, p| {
+            let x: T = x[0];
+            let a = p[0];
+            let c = p[1];
+
+            if x < c {
+                //panic!();
+                -T::infinity()
+            } else {
+                let result = a.ln() + a * c.ln() - (a + T::one()) * x.ln();
+                assert!(result.is_finite());
+                result
+            }
+        },
+        move |p| {
+            let a = p[0];
+            let c = p[1];
+            let eta = T::from(1e-4).unwrap();
+            let x1 = c + T::from(1e-19).unwrap();
+            let x2 = c * eta.powf(-T::one() / a);
+            assert!(x2 > x1);
+            vec![(x1.max(xmin), xmax)]
+        },
+    )
+    .with_draw(move |p, rng| {
+        //inversion of the cdf 1-(c/x)^a between the bounds
+        let (a, c) = (f64_of(p[0]), f64_of(p[1]));
+        let cdf = |x: f64| 1.0 - (c / x).powf(a);
+        let (lo, hi) = (cdf(f64_of(xmin).max(c)), cdf(f64_of(xmax)));
+        let u = Uniform::new(lo, hi).sample(rng);
+        vec![T::from(c * (1.0 - u).powf(-1.0 / a)).unwrap()]
+    })
+}
+
+///A stochastic node with a single output and the given parents
+fn scalar_stochastic_node<T, L, R>(
+    parents: &[(NodeHandle, usize)],
+    logprob: L,
+    range: R,
+) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+    L: 'static + Fn(&[T], &[T]) -> T + Send + Sync,
+    R: 'static + Fn(&[T]) -> Vec<(T, T)> + Send + Sync,
+{
+    NodeAdder::new(
+        Node::stochastic(parents.len(), vec![zero()], logprob, range),
+        parents,
+    )
+}
+
+///`(0, inf)`; ARMS evaluates the log density at the ends of the bracket, so the open lower
+///bound is shifted to the smallest positive value
+fn positive_range<T>(_p: &[T]) -> Vec<(T, T)>
+where
+    T: Float,
+{
+    vec![(T::min_positive_value(), T::infinity())]
+}
+
+fn real_range<T>(_p: &[T]) -> Vec<(T, T)>
+where
+    T: Float,
+{
+    vec![(T::neg_infinity(), T::infinity())]
+}
+
+///Gamma distribution with shape `a` and rate `b`
+#[cfg(not(target_family = "wasm"))]
+pub fn gamma_node<T>(a: (NodeHandle, usize), b: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Gamma + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[a, b],
+        |x: &[T], p: &[T]| {
+            let (x, a, b) = (x[0], p[0], p[1]);
+            if x <= T::zero() {
+                -T::infinity()
+            } else {
+                a * b.ln() - a.ln_gamma().0 + (a - T::one()) * x.ln() - b * x
+            }
+        },
+        positive_range,
+    )
+    .with_family(Family::Gamma)
+    .with_draw(|p, rng| {
+        draw_f64(
+            GammaDist::new(f64_of(p[0]), 1.0 / f64_of(p[1])).unwrap(),
+            rng,
+        )
+    })
+}
+
+///Inverse gamma distribution with shape `a` and scale `b`
+#[cfg(not(target_family = "wasm"))]
+pub fn inv_gamma_node<T>(a: (NodeHandle, usize), b: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Gamma + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[a, b],
+        |x: &[T], p: &[T]| {
+            let (x, a, b) = (x[0], p[0], p[1]);
+            if x <= T::zero() {
+                -T::infinity()
+            } else {
+                a * b.ln() - a.ln_gamma().0 - (a + T::one()) * x.ln() - b / x
+            }
+        },
+        positive_range,
+    )
+    .with_family(Family::InvGamma)
+    .with_draw(|p, rng| {
+        let x: f64 = GammaDist::new(f64_of(p[0]), 1.0 / f64_of(p[1]))
+            .unwrap()
+            .sample(rng);
+        vec![T::from(1.0 / x).unwrap()]
+    })
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn beta_node<T>(a: (NodeHandle, usize), b: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Gamma + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[a, b],
+        |x: &[T], p: &[T]| {
+            let (x, a, b) = (x[0], p[0], p[1]);
+            if x <= T::zero() || x >= T::one() {
+                -T::infinity()
+            } else {
+                (a - T::one()) * x.ln() + (b - T::one()) * (T::one() - x).ln() - lbeta(a, b)
+            }
+        },
+        |_p| vec![(T::min_positive_value(), T::one() - T::epsilon())],
+    )
+    .with_family(Family::Beta)
+    .with_draw(|p, rng| draw_f64(BetaDist::new(f64_of(p[0]), f64_of(p[1])).unwrap(), rng))
+}
+
+///Exponential distribution with rate `lambda`
+pub fn exp_node<T>(lambda: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[lambda],
+        |x: &[T], p: &[T]| {
+            let (x, lambda) = (x[0], p[0]);
+            if x < T::zero() {
+                -T::infinity()
+            } else {
+                lambda.ln() - lambda * x
+            }
+        },
+        positive_range,
+    )
+    .with_draw(|p, rng| draw_f64(Exp::new(f64_of(p[0])).unwrap(), rng))
+}
+
+///Log-normal distribution, `ln(x)~N(m, s)`
+pub fn lognormal_node<T>(m: (NodeHandle, usize), s: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + FloatConst + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[m, s],
+        |x: &[T], p: &[T]| {
+            let (x, m, s) = (x[0], p[0], p[1]);
+            let two = T::one() + T::one();
+            if x <= T::zero() {
+                -T::infinity()
+            } else {
+                let y = x.ln();
+                -(y - m).powi(2) / (two * s * s) - ((two * T::PI()).sqrt() * s * x).ln()
+            }
+        },
+        positive_range,
+    )
+    .with_draw(|p, rng| draw_f64(LogNormal::new(f64_of(p[0]), f64_of(p[1])).unwrap(), rng))
+}
+
+///Cauchy distribution with location `x0` and scale `gamma`
+pub fn cauchy_node<T>(x0: (NodeHandle, usize), gamma: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + FloatConst + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[x0, gamma],
+        |x: &[T], p: &[T]| {
+            let (x, x0, gamma) = (x[0], p[0], p[1]);
+            -(T::PI() * gamma * (T::one() + ((x - x0) / gamma).powi(2))).ln()
+        },
+        real_range,
+    )
+    .with_draw(|p, rng| draw_f64(Cauchy::new(f64_of(p[0]), f64_of(p[1])).unwrap(), rng))
+}
+
+///Normal distribution `N(0, s)` truncated to `x>=0`
+pub fn half_normal_node<T>(s: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + FloatConst + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[s],
+        |x: &[T], p: &[T]| {
+            let (x, s) = (x[0], p[0]);
+            let two = T::one() + T::one();
+            if x < T::zero() {
+                -T::infinity()
+            } else {
+                -x * x / (two * s * s) + (two / T::PI()).sqrt().ln() - s.ln()
+            }
+        },
+        positive_range,
+    )
+    .with_draw(|p, rng| {
+        let x: f64 = Normal::new(0.0, f64_of(p[0])).unwrap().sample(rng);
+        vec![T::from(x.abs()).unwrap()]
+    })
+}
+
+///Cauchy distribution with location zero and scale `gamma` truncated to `x>=0`
+pub fn half_cauchy_node<T>(gamma: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + FloatConst + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[gamma],
+        |x: &[T], p: &[T]| {
+            let (x, gamma) = (x[0], p[0]);
+            let two = T::one() + T::one();
+            if x < T::zero() {
+                -T::infinity()
+            } else {
+                (two / (T::PI() * gamma * (T::one() + (x / gamma).powi(2)))).ln()
+            }
+        },
+        positive_range,
+    )
+    .with_draw(|p, rng| {
+        let x: f64 = Cauchy::new(0.0, f64_of(p[0])).unwrap().sample(rng);
+        vec![T::from(x.abs()).unwrap()]
+    })
+}
+
+///Weibull distribution with shape `k` and scale `lambda`
+pub fn weibull_node<T>(k: (NodeHandle, usize), lambda: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[k, lambda],
+        |x: &[T], p: &[T]| {
+            let (x, k, lambda) = (x[0], p[0], p[1]);
+            if x <= T::zero() {
+                -T::infinity()
+            } else {
+                let y = x / lambda;
+                (k / lambda).ln() + (k - T::one()) * y.ln() - y.powf(k)
+            }
         },
-        content: NodeContent::StochasticNode {
-            all_stochastic_children: Vec::new(),
-            all_deterministic_children: Vec::new(),
-            is_observed: vec![false],
-            values: vec![zero()],
-            logprob: Box::new(move |x, p| {
-                let x: T = x[0];
-                let a = p[0];
-                let c = p[1];
-
-                if x < c {
-                    //panic!();
-                    -T::infinity()
-                } else {
-                    let result = a.ln() + a * c.ln() - (a + T::one()) * x.ln();
-                    assert!(result.is_finite());
-                    result
+        positive_range,
+    )
+    .with_draw(|p, rng| draw_f64(Weibull::new(f64_of(p[1]), f64_of(p[0])).unwrap(), rng))
+}
+
+///Laplace distribution with location `m` and scale `b`
+pub fn laplace_node<T>(m: (NodeHandle, usize), b: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[m, b],
+        |x: &[T], p: &[T]| {
+            let (x, m, b) = (x[0], p[0], p[1]);
+            -(x - m).abs() / b - ((T::one() + T::one()) * b).ln()
+        },
+        real_range,
+    )
+    .with_draw(|p, rng| {
+        //the difference of two exponential variables
+        let (e1, e2): (f64, f64) = (Exp1.sample(rng), Exp1.sample(rng));
+        vec![p[0] + p[1] * T::from(e1 - e2).unwrap()]
+    })
+}
+
+///Logistic distribution with location `m` and scale `s`
+pub fn logistic_node<T>(m: (NodeHandle, usize), s: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[m, s],
+        |x: &[T], p: &[T]| {
+            let (x, m, s) = (x[0], p[0], p[1]);
+            let z = -(x - m).abs() / s;
+            z - s.ln() - (T::one() + T::one()) * z.exp().ln_1p()
+        },
+        real_range,
+    )
+    .with_draw(|p, rng| {
+        let u: f64 = Open01.sample(rng);
+        vec![p[0] + p[1] * T::from((u / (1.0 - u)).ln()).unwrap()]
+    })
+}
+
+///An observed count `k` drawn from the Poisson distribution with mean `lambda`
+#[cfg(not(target_family = "wasm"))]
+pub fn poisson_obs_node<T>(lambda: (NodeHandle, usize), k: T) -> NodeAdder<T>
+where
+    T: 'static + Float + Gamma + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[lambda],
+        |x: &[T], p: &[T]| {
+            let (k, lambda) = (x[0], p[0]);
+            k * lambda.ln() - lambda - (k + T::one()).ln_gamma().0
+        },
+        positive_range,
+    )
+    .with_family(Family::Poisson)
+    .with_draw(|p, rng| draw_f64(Poisson::new(f64_of(p[0])).unwrap(), rng))
+    .with_all_values(&[Observed(k)])
+}
+
+///An observed number of successes `k` drawn from the binomial distribution with `n` trials
+///and success probability `p`
+#[cfg(not(target_family = "wasm"))]
+pub fn binomial_obs_node<T>(p: (NodeHandle, usize), n: (NodeHandle, usize), k: T) -> NodeAdder<T>
+where
+    T: 'static + Float + Gamma + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[p, n],
+        |x: &[T], p: &[T]| logdbin(x[0], p[0], p[1]),
+        |p| vec![(T::zero(), p[1])],
+    )
+    .discrete()
+    .with_family(Family::Binomial)
+    .with_draw(|p, rng| {
+        let k = Binomial::new(f64_of(p[1]) as u64, f64_of(p[0]))
+            .unwrap()
+            .sample(rng);
+        vec![T::from(k).unwrap()]
+    })
+    .with_all_values(&[Observed(k)])
+}
+
+///Bernoulli distribution with success probability `p`
+pub fn bernoulli_node<T>(p: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[p],
+        |x: &[T], p: &[T]| {
+            let (x, p) = (x[0], p[0]);
+            if x == T::one() {
+                p.ln()
+            } else if x == T::zero() {
+                (T::one() - p).ln()
+            } else {
+                -T::infinity()
+            }
+        },
+        |_p| vec![(T::zero(), T::one())],
+    )
+    .discrete()
+    .with_family(Family::Bernoulli)
+    .with_draw(|p, rng| {
+        let x = Bernoulli::new(f64_of(p[0])).unwrap().sample(rng);
+        vec![if x { T::one() } else { T::zero() }]
+    })
+}
+
+///Categorical distribution over `0..probs.len()` with probabilities proportional to `probs`
+pub fn categorical_node<T>(probs: &[(NodeHandle, usize)]) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+{
+    let k = probs.len();
+    scalar_stochastic_node(
+        probs,
+        |x: &[T], p: &[T]| {
+            let x = x[0];
+            match x.to_usize() {
+                Some(i) if i < p.len() && T::from(i).unwrap() == x => {
+                    (p[i] / p.iter().fold(T::zero(), |a, &b| a + b)).ln()
                 }
-            }),
-            range: Box::new(move |p| {
-                let a = p[0];
-                let c = p[1];
-                let eta = T::from(1e-4).unwrap();
-                let x1 = c + T::from(1e-19).unwrap();
-                let x2 = c * eta.powf(-T::one() / a);
-                assert!(x2 > x1);
-                vec![(x1.max(xmin), xmax)]
-            }),
+                _ => -T::infinity(),
+            }
         },
-    };
-    NodeAdder::new(n, &[a, c])
+        move |_p| vec![(T::zero(), T::from(k - 1).unwrap())],
+    )
+    .discrete()
+    .with_draw(|p, rng| {
+        let i = WeightedIndex::new(p.iter().map(|&p| f64_of(p)))
+            .unwrap()
+            .sample(rng);
+        vec![T::from(i).unwrap()]
+    })
+}
+
+///Binomial distribution with success probability `p` and `n` trials
+#[cfg(not(target_family = "wasm"))]
+pub fn binomial_node<T>(p: (NodeHandle, usize), n: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Gamma + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[p, n],
+        |x: &[T], p: &[T]| {
+            let (x, p, n) = (x[0], p[0], p[1]);
+            if x < T::zero() || x > n || x.fract() != T::zero() {
+                -T::infinity()
+            } else {
+                logdbin(x, p, n)
+            }
+        },
+        |p| vec![(T::zero(), p[1])],
+    )
+    .discrete()
+    .with_family(Family::Binomial)
+    .with_draw(|p, rng| {
+        let k = Binomial::new(f64_of(p[1]) as u64, f64_of(p[0]))
+            .unwrap()
+            .sample(rng);
+        vec![T::from(k).unwrap()]
+    })
+}
+
+///Poisson distribution with mean `lambda` truncated to `0..=kmax`
+#[cfg(not(target_family = "wasm"))]
+pub fn trunc_poisson_node<T>(lambda: (NodeHandle, usize), kmax: usize) -> NodeAdder<T>
+where
+    T: 'static + Float + Gamma + Sync + Send + Display,
+{
+    let kmax_t = T::from(kmax).unwrap();
+    let log_pmf = |k: T, lambda: T| k * lambda.ln() - lambda - (k + T::one()).ln_gamma().0;
+    scalar_stochastic_node(
+        &[lambda],
+        move |x: &[T], p: &[T]| {
+            let (x, lambda) = (x[0], p[0]);
+            if x < T::zero() || x > kmax_t || x.fract() != T::zero() {
+                return -T::infinity();
+            }
+            let lp: Vec<T> = (0..=kmax)
+                .map(|k| log_pmf(T::from(k).unwrap(), lambda))
+                .collect();
+            let lpmax = lp
+                .iter()
+                .fold(T::neg_infinity(), |a, &b| if b > a { b } else { a });
+            let log_norm = lpmax
+                + lp.iter()
+                    .fold(T::zero(), |a, &b| a + (b - lpmax).exp())
+                    .ln();
+            log_pmf(x, lambda) - log_norm
+        },
+        move |_p| vec![(T::zero(), kmax_t)],
+    )
+    .discrete()
+    .with_draw(move |p, rng| {
+        let lp: Vec<f64> = (0..=kmax)
+            .map(|k| f64_of(log_pmf(T::from(k).unwrap(), p[0])))
+            .collect();
+        let lpmax = lp.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+        let i = WeightedIndex::new(lp.iter().map(|&l| (l - lpmax).exp()))
+            .unwrap()
+            .sample(rng);
+        vec![T::from(i).unwrap()]
+    })
+}
+
+///Uniform distribution over the integers `a..=b`
+pub fn discrete_uniform_node<T>(a: (NodeHandle, usize), b: (NodeHandle, usize)) -> NodeAdder<T>
+where
+    T: 'static + Float + Sync + Send + Display,
+{
+    scalar_stochastic_node(
+        &[a, b],
+        |x: &[T], p: &[T]| {
+            let (x, a, b) = (x[0], p[0].ceil(), p[1].floor());
+            if x < a || x > b || x.fract() != T::zero() {
+                -T::infinity()
+            } else {
+                -(b - a + T::one()).ln()
+            }
+        },
+        |p| vec![(p[0], p[1])],
+    )
+    .discrete()
+    .with_draw(|p, rng| {
+        let (a, b) = (f64_of(p[0].ceil()) as i64, f64_of(p[1].floor()) as i64);
+        vec![T::from(Uniform::new_inclusive(a, b).sample(rng)).unwrap()]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcmc::graph::graph::ParamObservability::UnObserved;
+    use crate::mcmc::graph::graph::{Graph, Sampler};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::HashMap;
+
+    ///Draws of the single unobserved variable of `g`
+    fn draws(g: &mut Graph<String, f64>, n: usize) -> Vec<f64> {
+        g.seal();
+        let mut gv = g.init_gv();
+        assert_eq!(gv.sampleable_values.len(), 1);
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut nchanged = 0;
+        for _ in 0..100 {
+            g.sample_all(&mut gv, &mut rng, 10, &mut nchanged);
+        }
+        (0..n)
+            .map(|_| {
+                g.sample_all(&mut gv, &mut rng, 10, &mut nchanged);
+                gv[0]
+            })
+            .collect()
+    }
+
+    fn moments(x: &[f64]) -> (f64, f64) {
+        let n = x.len() as f64;
+        let m = x.iter().sum::<f64>() / n;
+        (
+            m,
+            x.iter().map(|x| (x - m).powi(2)).sum::<f64>() / (n - 1.0),
+        )
+    }
+
+    fn quantile(x: &[f64], q: f64) -> f64 {
+        let mut x = x.to_vec();
+        x.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        x[(q * x.len() as f64).ceil() as usize - 1]
+    }
+
+    fn check_moments(name: &str, x: &[f64], mean: f64, var: f64) {
+        let (m, v) = moments(x);
+        let n = x.len() as f64;
+        //a generous allowance for the autocorrelation of the chain
+        assert!(
+            (m - mean).abs() < 6.0 * (var / n).sqrt(),
+            "{}: mean {} {}",
+            name,
+            m,
+            mean
+        );
+        assert!((v / var - 1.0).abs() < 0.1, "{}: var {} {}", name, v, var);
+    }
+
+    fn graph_with<F>(params: &[f64], init: f64, f: F) -> (Graph<String, f64>, NodeHandle)
+    where
+        F: Fn(&[(NodeHandle, usize)]) -> NodeAdder<f64>,
+    {
+        let mut g = Graph::new();
+        let p: Vec<_> = params
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| (const_node(v).add_to(&mut g, &format!("p{}", i)), 0))
+            .collect();
+        let x = f(&p)
+            .with_all_values(&[UnObserved(init)])
+            .add_to(&mut g, &"x".to_string());
+        (g, x)
+    }
+
+    const N: usize = 20000;
+
+    fn node_draws<F>(params: &[f64], init: f64, f: F) -> Vec<f64>
+    where
+        F: Fn(&[(NodeHandle, usize)]) -> NodeAdder<f64>,
+    {
+        draws(&mut graph_with(params, init, f).0, N)
+    }
+
+    #[test]
+    fn arithmetic_nodes_test() {
+        let mut g = Graph::new();
+        let a = const_node(6.0).add_to(&mut g, &"a".to_string());
+        let b = const_node(3.0).add_to(&mut g, &"b".to_string());
+        add_node((a, 0), (b, 0)).add_to(&mut g, &"add".to_string());
+        sub_node((a, 0), (b, 0)).add_to(&mut g, &"sub".to_string());
+        mul_node((a, 0), (b, 0)).add_to(&mut g, &"mul".to_string());
+        div_node((a, 0), (b, 0)).add_to(&mut g, &"div".to_string());
+        g.seal();
+        let gv = g.init_gv();
+        for (k, v) in [("add", 9.0), ("sub", 3.0), ("mul", 18.0), ("div", 2.0)] {
+            assert_eq!(g.values_of(&k.to_string(), &gv), vec![v], "{}", k);
+        }
+    }
+
+    #[test]
+    fn continuous_nodes_test() {
+        let (a, b) = (3.0, 2.0);
+        let x = node_draws(&[a, b], 1.0, |p| gamma_node(p[0], p[1]));
+        check_moments("gamma", &x, a / b, a / (b * b));
+
+        let (a, b) = (5.0, 2.0);
+        let x = node_draws(&[a, b], 1.0, |p| inv_gamma_node(p[0], p[1]));
+        check_moments(
+            "inv_gamma",
+            &x,
+            b / (a - 1.0),
+            b * b / ((a - 1.0).powi(2) * (a - 2.0)),
+        );
+
+        let (a, b) = (2.0, 5.0);
+        let x = node_draws(&[a, b], 0.5, |p| beta_node(p[0], p[1]));
+        check_moments(
+            "beta",
+            &x,
+            a / (a + b),
+            a * b / ((a + b).powi(2) * (a + b + 1.0)),
+        );
+
+        let l = 0.5;
+        let x = node_draws(&[l], 1.0, |p| exp_node(p[0]));
+        check_moments("exp", &x, 1.0 / l, 1.0 / (l * l));
+
+        let (m, s) = (0.5, 0.4);
+        let x = node_draws(&[m, s], 1.0, |p| lognormal_node(p[0], p[1]));
+        check_moments(
+            "lognormal",
+            &x,
+            (m + s * s / 2.0).exp(),
+            ((s * s).exp() - 1.0) * (2.0 * m + s * s).exp(),
+        );
+
+        let s = 2.0;
+        let x = node_draws(&[s], 1.0, |p| half_normal_node(p[0]));
+        let pi = std::f64::consts::PI;
+        check_moments(
+            "half_normal",
+            &x,
+            s * (2.0 / pi).sqrt(),
+            s * s * (1.0 - 2.0 / pi),
+        );
+
+        let (k, l) = (2.0, 3.0);
+        let x = node_draws(&[k, l], 1.0, |p| weibull_node(p[0], p[1]));
+        let g1 = special::Gamma::gamma(1.0 + 1.0 / k);
+        let g2 = special::Gamma::gamma(1.0 + 2.0 / k);
+        check_moments("weibull", &x, l * g1, l * l * (g2 - g1 * g1));
+
+        let (m, b) = (1.0, 2.0);
+        let x = node_draws(&[m, b], 0.0, |p| laplace_node(p[0], p[1]));
+        check_moments("laplace", &x, m, 2.0 * b * b);
+
+        let (m, s) = (-1.0, 0.5);
+        let x = node_draws(&[m, s], 0.0, |p| logistic_node(p[0], p[1]));
+        check_moments("logistic", &x, m, s * s * pi * pi / 3.0);
+
+        //the likelihood pulls the posterior far beyond 10 prior standard deviations
+        let (mut g, mu) = graph_with(&[0.0, 1.0], 0.0, |p| normal_node(p[0], p[1]));
+        let b = const_node(0.1).add_to(&mut g, &"b".to_string());
+        for i in 0..5 {
+            laplace_node((mu, 0), (b, 0))
+                .with_all_values(&[Observed(15.0)])
+                .add_to(&mut g, &format!("y{}", i));
+        }
+        let (m, _) = moments(&draws(&mut g, 2000));
+        assert!((m - 15.0).abs() < 0.1, "{}", m);
+    }
+
+    #[test]
+    fn uniform_parent_test() {
+        //x_i ~ U(0, theta) with a Pareto(a0, c0) prior: the posterior of theta is
+        //Pareto(a0 + n, max(c0, max x_i))
+        let (a0, c0) = (3.0, 1.0);
+        let xs = [0.5, 2.0, 1.2, 0.3, 1.7];
+        let (mut g, theta) = graph_with(&[a0, c0], 2.5, |p| pareto_node(p[0], p[1]));
+        let zero = const_node(0.0).add_to(&mut g, &"zero".to_string());
+        for (i, &x) in xs.iter().enumerate() {
+            uniform_node((zero, 0), (theta, 0))
+                .with_all_values(&[Observed(x)])
+                .add_to(&mut g, &format!("x{}", i));
+        }
+        let x = draws(&mut g, N);
+        let (a, c) = (a0 + xs.len() as f64, 2.0);
+        assert!(x.iter().all(|&t| t >= c));
+        check_moments(
+            "uniform parent",
+            &x,
+            a * c / (a - 1.0),
+            c * c * a / ((a - 1.0).powi(2) * (a - 2.0)),
+        );
+    }
+
+    ///Draws of the single unobserved variable of `g` by `Graph::simulate`
+    fn simulated(g: &mut Graph<String, f64>, n: usize) -> Vec<f64> {
+        g.seal();
+        let mut gv = g.init_gv();
+        let mut rng = StdRng::seed_from_u64(12345);
+        (0..n)
+            .map(|_| {
+                g.simulate(&mut gv, &mut rng, None);
+                gv[0]
+            })
+            .collect()
+    }
+
+    ///Check the draws of a node against MCMC draws from its log density
+    fn check_draws<F>(name: &str, params: &[f64], init: f64, f: F)
+    where
+        F: Fn(&[(NodeHandle, usize)]) -> NodeAdder<f64>,
+    {
+        let x = simulated(&mut graph_with(params, init, &f).0, N);
+        let (m, v) = moments(&node_draws(params, init, f));
+        check_moments(name, &x, m, v);
+    }
+
+    #[test]
+    fn draw_test() {
+        check_draws("normal", &[1.0, 2.0], 0.0, |p| normal_node(p[0], p[1]));
+        check_draws("normal_var", &[1.0, 2.0], 0.0, |p| {
+            normal_var_node(p[0], p[1])
+        });
+        check_draws("uniform", &[-1.0, 3.0], 0.0, |p| uniform_node(p[0], p[1]));
+        check_draws("t", &[1.0, 2.0], 0.0, |p| t_node(p[0], p[1], 5));
+        check_draws("pareto", &[5.0, 2.0], 3.0, |p| pareto_node(p[0], p[1]));
+        check_draws("trunc_pareto", &[1.5, 1.0], 2.0, |p| {
+            trunc_pareto_node(p[0], p[1], 1.0, 10.0)
+        });
+        check_draws("gamma", &[3.0, 2.0], 1.0, |p| gamma_node(p[0], p[1]));
+        check_draws("inv_gamma", &[5.0, 2.0], 1.0, |p| {
+            inv_gamma_node(p[0], p[1])
+        });
+        check_draws("beta", &[2.0, 5.0], 0.5, |p| beta_node(p[0], p[1]));
+        check_draws("exp", &[0.5], 1.0, |p| exp_node(p[0]));
+        check_draws("lognormal", &[0.5, 0.4], 1.0, |p| {
+            lognormal_node(p[0], p[1])
+        });
+        check_draws("half_normal", &[2.0], 1.0, |p| half_normal_node(p[0]));
+        check_draws("weibull", &[2.0, 3.0], 1.0, |p| weibull_node(p[0], p[1]));
+        check_draws("laplace", &[1.0, 2.0], 0.0, |p| laplace_node(p[0], p[1]));
+        check_draws("logistic", &[-1.0, 0.5], 0.0, |p| logistic_node(p[0], p[1]));
+        check_draws("bernoulli", &[0.3], 0.0, |p| bernoulli_node(p[0]));
+        check_draws("binomial", &[0.4, 12.0], 0.0, |p| binomial_node(p[0], p[1]));
+        check_draws("categorical", &[1.0, 3.0, 6.0], 0.0, |p| {
+            categorical_node(p)
+        });
+        check_draws("trunc_poisson", &[4.0], 0.0, |p| {
+            trunc_poisson_node(p[0], 5)
+        });
+        check_draws("discrete_uniform", &[-2.0, 5.0], 0.0, |p| {
+            discrete_uniform_node(p[0], p[1])
+        });
+
+        let (x0, gamma) = (1.0, 2.0);
+        let x = simulated(
+            &mut graph_with(&[x0, gamma], 0.0, |p| cauchy_node(p[0], p[1])).0,
+            N,
+        );
+        assert!((quantile(&x, 0.75) - x0 - gamma).abs() < 0.15);
+        let x = simulated(
+            &mut graph_with(&[gamma], 1.0, |p| half_cauchy_node(p[0])).0,
+            N,
+        );
+        assert!((quantile(&x, 0.5) - gamma).abs() < 0.15);
+    }
+
+    #[test]
+    fn predictive_test() {
+        //mu ~ N(1, 2), y_k ~ N(mu, 1) observed and d = 2*mu
+        let mut g = Graph::new();
+        let (m, s, one) = (
+            const_node(1.0).add_to(&mut g, &"m".to_string()),
+            const_node(2.0).add_to(&mut g, &"s".to_string()),
+            const_node(1.0).add_to(&mut g, &"one".to_string()),
+        );
+        let two = const_node(2.0).add_to(&mut g, &"two".to_string());
+        let mu = normal_node((m, 0), (s, 0))
+            .with_all_values(&[UnObserved(0.0)])
+            .add_to(&mut g, &"mu".to_string());
+        mul_node((mu, 0), (two, 0)).add_to(&mut g, &"d".to_string());
+        normal_node((mu, 0), (one, 0))
+            .plate(3)
+            .with_all_values(&[Observed(0.5), Observed(1.5), Observed(2.5)])
+            .add_to(&mut g, &"y".to_string());
+        g.seal();
+        let mut rng = StdRng::seed_from_u64(12345);
+        let (mu, d, y) = ("mu".to_string(), "d".to_string(), "y".to_string());
+
+        //prior draws leave the data alone
+        let mut gv = g.init_gv();
+        let x: Vec<f64> = (0..N)
+            .map(|_| {
+                g.simulate(&mut gv, &mut rng, None);
+                assert_eq!(g.values_of(&d, &gv)[0], 2.0 * g.values_of(&mu, &gv)[0]);
+                assert_eq!(g.values_of(&y, &gv), vec![0.5, 1.5, 2.5]);
+                g.values_of(&mu, &gv)[0]
+            })
+            .collect();
+        check_moments("prior", &x, 1.0, 4.0);
+
+        //replicated data given a fixed mu
+        let mut gv = g.init_gv();
+        gv[0] = -3.0;
+        let mut y1 = Vec::new();
+        for _ in 0..N {
+            g.simulate(&mut gv, &mut rng, Some(std::slice::from_ref(&y)));
+            assert_eq!(g.values_of(&mu, &gv)[0], -3.0);
+            y1.push(g.values_of(&y, &gv)[2]);
+        }
+        check_moments("replicated", &y1, -3.0, 1.0);
+        assert!(y1.windows(2).all(|w| w[0] != w[1]));
+
+        let lambda = 3.5;
+        let (mut g, l) = graph_with(&[lambda], lambda, |p| exp_node(p[0]));
+        poisson_obs_node((l, 0), 2.0).add_to(&mut g, &"k".to_string());
+        g.seal();
+        let mut gv = g.init_gv();
+        let k: Vec<f64> = (0..N)
+            .map(|_| {
+                g.simulate(&mut gv, &mut rng, Some(&["k".to_string()]));
+                g.values_of(&"k".to_string(), &gv)[0]
+            })
+            .collect();
+        check_moments("poisson", &k, lambda, lambda);
+    }
+
+    #[test]
+    fn heavy_tailed_nodes_test() {
+        let (x0, gamma) = (1.0, 2.0);
+        let x = node_draws(&[x0, gamma], 0.0, |p| cauchy_node(p[0], p[1]));
+        for &(q, expected) in &[(0.25, x0 - gamma), (0.5, x0), (0.75, x0 + gamma)] {
+            let v = quantile(&x, q);
+            assert!(
+                (v - expected).abs() < 0.15,
+                "cauchy {} {} {}",
+                q,
+                v,
+                expected
+            );
+        }
+
+        let x = node_draws(&[gamma], 1.0, |p| half_cauchy_node(p[0]));
+        let v = quantile(&x, 0.5);
+        assert!((v - gamma).abs() < 0.15, "half_cauchy {} {}", v, gamma);
+    }
+
+    #[test]
+    fn observed_nodes_test() {
+        //Gamma(a, b) prior and Poisson counts: the posterior is Gamma(a+sum(k), b+n)
+        let (a, b) = (2.0, 1.0);
+        let counts = [3.0, 5.0, 2.0, 4.0, 6.0, 1.0];
+        let (mut g, lambda) = graph_with(&[a, b], 1.0, |p| gamma_node(p[0], p[1]));
+        for (i, &k) in counts.iter().enumerate() {
+            poisson_obs_node((lambda, 0), k).add_to(&mut g, &format!("k{}", i));
+        }
+        let x = draws(&mut g, N);
+        let (a1, b1) = (a + counts.iter().sum::<f64>(), b + counts.len() as f64);
+        check_moments("poisson", &x, a1 / b1, a1 / (b1 * b1));
+
+        //Beta(a, b) prior and binomial counts: the posterior is Beta(a+sum(k), b+sum(n-k))
+        let trials = [(10.0, 3.0), (8.0, 2.0), (12.0, 5.0)];
+        let (mut g, p) = graph_with(&[a, b], 0.5, |p| beta_node(p[0], p[1]));
+        for (i, &(n, k)) in trials.iter().enumerate() {
+            let n = const_node(n).add_to(&mut g, &format!("n{}", i));
+            binomial_obs_node((p, 0), (n, 0), k).add_to(&mut g, &format!("k{}", i));
+        }
+        let x = draws(&mut g, N);
+        let a1 = a + trials.iter().map(|t| t.1).sum::<f64>();
+        let b1 = b + trials.iter().map(|t| t.0 - t.1).sum::<f64>();
+        check_moments(
+            "binomial",
+            &x,
+            a1 / (a1 + b1),
+            a1 * b1 / ((a1 + b1).powi(2) * (a1 + b1 + 1.0)),
+        );
+    }
+
+    ///Draws of all the unobserved variables of `g`
+    fn all_draws(g: &mut Graph<String, f64>, n: usize) -> Vec<Vec<f64>> {
+        g.seal();
+        let mut gv = g.init_gv();
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut nchanged = 0;
+        for _ in 0..100 {
+            g.sample_all(&mut gv, &mut rng, 10, &mut nchanged);
+        }
+        (0..n)
+            .map(|_| {
+                g.sample_all(&mut gv, &mut rng, 10, &mut nchanged);
+                gv.sampleable_values.clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn conjugacy_test() {
+        //N(m0, s0^2) prior on the mean and normal observations with known variances
+        let (m0, s0) = (1.0, 2.0);
+        let obs = [(0.5, 1.0), (2.0, 0.5), (1.5, 1.5)];
+        let (mut g, mu) = graph_with(&[m0, s0], 0.0, |p| normal_node(p[0], p[1]));
+        for (i, &(y, s)) in obs.iter().enumerate() {
+            let s = const_node(s).add_to(&mut g, &format!("s{}", i));
+            normal_node((mu, 0), (s, 0))
+                .with_all_values(&[Observed(y)])
+                .add_to(&mut g, &format!("y{}", i));
+        }
+        let v = const_node(4.0).add_to(&mut g, &"v".to_string());
+        normal_var_node((mu, 0), (v, 0))
+            .with_all_values(&[Observed(3.0)])
+            .add_to(&mut g, &"y3".to_string());
+        let x = draws(&mut g, N);
+        assert_eq!(g.sampler_of(&"x".to_string()), Some(Sampler::NormalNormal));
+        assert_eq!(g.sampler_of(&"y0".to_string()), None);
+        let prec = 1.0 / (s0 * s0) + obs.iter().map(|o| 1.0 / (o.1 * o.1)).sum::<f64>() + 0.25;
+        let wm = m0 / (s0 * s0) + obs.iter().map(|o| o.0 / (o.1 * o.1)).sum::<f64>() + 0.75;
+        check_moments("normal-normal", &x, wm / prec, 1.0 / prec);
+
+        //InvGamma(a, b) prior on the variance: the posterior is
+        //InvGamma(a+n/2, b+sum((y-m)^2)/2)
+        let (a, b, m) = (3.0, 2.0, 1.0);
+        let ys = [0.0, 2.5, 1.5, -1.0, 1.0];
+        let (mut g, v) = graph_with(&[a, b], 1.0, |p| inv_gamma_node(p[0], p[1]));
+        let mean = const_node(m).add_to(&mut g, &"m".to_string());
+        for (i, &y) in ys.iter().enumerate() {
+            normal_var_node((mean, 0), (v, 0))
+                .with_all_values(&[Observed(y)])
+                .add_to(&mut g, &format!("y{}", i));
+        }
+        let x = draws(&mut g, N);
+        assert_eq!(
+            g.sampler_of(&"x".to_string()),
+            Some(Sampler::NormalInvGamma)
+        );
+        let a1 = a + ys.len() as f64 / 2.0;
+        let b1 = b + ys.iter().map(|y| (y - m) * (y - m)).sum::<f64>() / 2.0;
+        check_moments(
+            "normal-inv_gamma",
+            &x,
+            b1 / (a1 - 1.0),
+            b1 * b1 / ((a1 - 1.0).powi(2) * (a1 - 2.0)),
+        );
+
+        //Beta(a, b) prior and Bernoulli observations
+        let (a, b) = (2.0, 3.0);
+        let flips = [1.0, 0.0, 1.0, 1.0, 1.0, 0.0, 1.0];
+        let (mut g, p) = graph_with(&[a, b], 0.5, |p| beta_node(p[0], p[1]));
+        for (i, &k) in flips.iter().enumerate() {
+            bernoulli_node((p, 0))
+                .with_all_values(&[Observed(k)])
+                .add_to(&mut g, &format!("k{}", i));
+        }
+        let x = draws(&mut g, N);
+        assert_eq!(g.sampler_of(&"x".to_string()), Some(Sampler::BetaBinomial));
+        let a1 = a + flips.iter().sum::<f64>();
+        let b1 = b + flips.len() as f64 - flips.iter().sum::<f64>();
+        check_moments(
+            "beta-bernoulli",
+            &x,
+            a1 / (a1 + b1),
+            a1 * b1 / ((a1 + b1).powi(2) * (a1 + b1 + 1.0)),
+        );
+
+        //a child that depends on the node through a deterministic node, or in another role
+        let (mut g, mu) = graph_with(&[0.0, 1.0], 0.0, |p| normal_node(p[0], p[1]));
+        let ex = scalar_func_node(&[(mu, 0)], Box::new(|x: &[f64]| x[0].exp()))
+            .add_to(&mut g, &"ex".to_string());
+        let one = const_node(1.0).add_to(&mut g, &"one".to_string());
+        normal_node((ex, 0), (one, 0))
+            .with_all_values(&[Observed(1.0)])
+            .add_to(&mut g, &"y".to_string());
+        let (mut g1, s) = graph_with(&[2.0, 1.0], 1.0, |p| gamma_node(p[0], p[1]));
+        let zero = const_node(0.0).add_to(&mut g1, &"zero".to_string());
+        normal_node((zero, 0), (s, 0))
+            .with_all_values(&[Observed(1.0)])
+            .add_to(&mut g1, &"y".to_string());
+        g.seal();
+        g1.seal();
+        assert_eq!(g.sampler_of(&"x".to_string()), Some(Sampler::Arms));
+        assert_eq!(g1.sampler_of(&"x".to_string()), Some(Sampler::Arms));
+        assert_eq!(g.sampler_of(&"one".to_string()), None);
+
+        //mu ~ N(0, 1), y ~ N(mu, exp(mu)): the standard deviation of y depends on mu through a
+        //deterministic node
+        let (mut g, mu) = graph_with(&[0.0, 1.0], 0.0, |p| normal_node(p[0], p[1]));
+        let s = scalar_func_node(&[(mu, 0)], Box::new(|x: &[f64]| x[0].exp()))
+            .add_to(&mut g, &"s".to_string());
+        normal_node((mu, 0), (s, 0))
+            .with_all_values(&[Observed(2.0)])
+            .add_to(&mut g, &"y".to_string());
+        g.seal();
+        assert_eq!(g.sampler_of(&"x".to_string()), Some(Sampler::Arms));
+    }
+
+    #[test]
+    fn plate_test() {
+        //a plate of normal observations gives the same posterior as separate nodes
+        let (m0, s0, s) = (0.0, 3.0, 2.0);
+        let ys: Vec<f64> = (0..500).map(|i| ((i * 37) % 101) as f64 / 50.0).collect();
+        let obs: Vec<_> = ys.iter().map(|&y| Observed(y)).collect();
+        let (mut g, mu) = graph_with(&[m0, s0], 0.0, |p| normal_node(p[0], p[1]));
+        let sd = const_node(s).add_to(&mut g, &"s".to_string());
+        normal_node((mu, 0), (sd, 0))
+            .plate(ys.len())
+            .with_all_values(&obs)
+            .add_to(&mut g, &"y".to_string());
+        let (mut g1, mu1) = graph_with(&[m0, s0], 0.0, |p| normal_node(p[0], p[1]));
+        let sd1 = const_node(s).add_to(&mut g1, &"s".to_string());
+        for (i, &y) in ys.iter().enumerate() {
+            normal_node((mu1, 0), (sd1, 0))
+                .with_all_values(&[Observed(y)])
+                .add_to(&mut g1, &format!("y{}", i));
+        }
+        g1.seal();
+        let gv1 = g1.init_gv();
+        let x = draws(&mut g, N);
+        assert_eq!(g.sampler_of(&"x".to_string()), Some(Sampler::NormalNormal));
+        let gv = g.init_gv();
+        assert!((g.logpost_all(&gv) - g1.logpost_all(&gv1)).abs() < 1e-8);
+        let prec = 1.0 / (s0 * s0) + ys.len() as f64 / (s * s);
+        let mean = (m0 / (s0 * s0) + ys.iter().sum::<f64>() / (s * s)) / prec;
+        check_moments("plate", &x, mean, 1.0 / prec);
+
+        //unobserved values of a plate are sampled one by one
+        let (a, b) = (3.0, 2.0);
+        let mut g = Graph::new();
+        let pa = const_node(a).add_to(&mut g, &"a".to_string());
+        let pb = const_node(b).add_to(&mut g, &"b".to_string());
+        gamma_node((pa, 0), (pb, 0))
+            .plate(3)
+            .with_all_values(&[UnObserved(1.0), Observed(0.5), UnObserved(1.0)])
+            .add_to(&mut g, &"x".to_string());
+        let x = all_draws(&mut g, N);
+        assert_eq!(g.values_of(&"x".to_string(), &g.init_gv())[1], 0.5);
+        for j in 0..2 {
+            let xj: Vec<f64> = x.iter().map(|x| x[j]).collect();
+            check_moments("gamma plate", &xj, a / b, a / (b * b));
+        }
+
+        //each value of a latent plate only sees the children that read it: z_j ~ N(0, 1),
+        //y0 ~ N(z_0, 1), y1 ~ N(-z_1, 1) through a deterministic node and no child of z_2
+        let mut g = Graph::new();
+        let zero = const_node(0.0).add_to(&mut g, &"zero".to_string());
+        let one = const_node(1.0).add_to(&mut g, &"one".to_string());
+        let z = normal_node((zero, 0), (one, 0))
+            .plate(3)
+            .with_all_values(&[UnObserved(0.0); 3])
+            .add_to(&mut g, &"z".to_string());
+        let (y0, y1) = (1.0, 2.0);
+        normal_node((z, 0), (one, 0))
+            .with_all_values(&[Observed(y0)])
+            .add_to(&mut g, &"y0".to_string());
+        let nz1 = neg_node((z, 1)).add_to(&mut g, &"nz1".to_string());
+        normal_node((nz1, 0), (one, 0))
+            .with_all_values(&[Observed(y1)])
+            .add_to(&mut g, &"y1".to_string());
+        g.seal();
+        let zi = (0..g.nodes().len()).find(|&i| g.key_of(i) == "z").unwrap();
+        let gv = g.init_gv();
+        let ln_n01 = -(2.0 * std::f64::consts::PI).sqrt().ln();
+        assert!((g.logpost_at(zi, 2, &gv) - ln_n01).abs() < 1e-12);
+        assert!((g.logpost_at(zi, 1, &gv) - 2.0 * ln_n01 + y1 * y1 / 2.0).abs() < 1e-12);
+        let x = all_draws(&mut g, N);
+        for (j, &mean) in [y0 / 2.0, -y1 / 2.0, 0.0].iter().enumerate() {
+            let xj: Vec<f64> = x.iter().map(|x| x[j]).collect();
+            let var = if j == 2 { 1.0 } else { 0.5 };
+            check_moments("latent plate", &xj, mean, var);
+        }
+    }
+
+    #[test]
+    fn parallel_test() {
+        //l_k ~ Exp(r) with a Poisson count c_k each: the posteriors are Gamma(1+c_k, r+1)
+        let r = 0.5;
+        let counts = [0.0, 3.0, 1.0, 7.0, 2.0, 4.0, 5.0, 1.0];
+        let build = |hyper: bool| {
+            let mut g = Graph::new();
+            let pr = if hyper {
+                let one = const_node(1.0).add_to(&mut g, &"one".to_string());
+                gamma_node((one, 0), (one, 0))
+                    .with_all_values(&[UnObserved(r)])
+                    .add_to(&mut g, &"r".to_string())
+            } else {
+                const_node(r).add_to(&mut g, &"r".to_string())
+            };
+            for (k, &c) in counts.iter().enumerate() {
+                let l = exp_node((pr, 0))
+                    .with_all_values(&[UnObserved(1.0)])
+                    .add_to(&mut g, &format!("l{}", k));
+                poisson_obs_node((l, 0), c).add_to(&mut g, &format!("c{}", k));
+            }
+            g.seal();
+            g
+        };
+        let l: Vec<String> = (0..counts.len()).map(|k| format!("l{}", k)).collect();
+        assert_eq!(build(false).colours(), vec![l.clone()]);
+        assert_eq!(build(true).colours(), vec![vec!["r".to_string()], l]);
+
+        let x = all_draws(&mut build(false), N);
+        for (k, &c) in counts.iter().enumerate() {
+            let xk: Vec<f64> = x.iter().map(|x| x[k]).collect();
+            let (a, b) = (1.0 + c, r + 1.0);
+            check_moments("parallel", &xk, a / b, a / (b * b));
+        }
+
+        //the draws do not depend on the number of threads
+        let run = |nthreads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(nthreads)
+                .build()
+                .unwrap();
+            pool.install(|| {
+                let g = build(true);
+                let mut gv = g.init_gv();
+                let mut rng = StdRng::seed_from_u64(12345);
+                let mut nchanged = 0;
+                for _ in 0..10 {
+                    g.sample_all(&mut gv, &mut rng, 10, &mut nchanged);
+                }
+                gv.sampleable_values
+            })
+        };
+        assert_eq!(run(1), run(4));
+    }
+
+    #[test]
+    fn parallel_sequential_test() {
+        //l_k ~ Exp(r) with Poisson counts of mean 2*l_k, a deterministic child of each l_k
+        let mut g = Graph::new();
+        let one = const_node(1.0).add_to(&mut g, &"one".to_string());
+        let two = const_node(2.0).add_to(&mut g, &"two".to_string());
+        let r = gamma_node((one, 0), (one, 0))
+            .with_all_values(&[UnObserved(0.5)])
+            .add_to(&mut g, &"r".to_string());
+        for (k, &c) in [0.0, 3.0, 1.0, 7.0, 2.0, 4.0].iter().enumerate() {
+            let l = exp_node((r, 0))
+                .with_all_values(&[UnObserved(1.0)])
+                .add_to(&mut g, &format!("l{}", k));
+            let m = mul_node((l, 0), (two, 0)).add_to(&mut g, &format!("m{}", k));
+            poisson_obs_node((m, 0), c).add_to(&mut g, &format!("c{}", k));
+        }
+        g.seal();
+        assert_eq!(g.colours().len(), 2);
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        let mut gv1 = g.init_gv();
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut nchanged = 0;
+        pool.install(|| {
+            for _ in 0..10 {
+                g.sample_all(&mut gv1, &mut rng, 10, &mut nchanged);
+            }
+        });
+
+        //the same updates one node after another, with the generators seeded in the same order
+        let index: HashMap<String, usize> = (0..g.nodes().len())
+            .map(|i| (g.key_of(i).clone(), i))
+            .collect();
+        let mut gv2 = g.init_gv();
+        let mut rng = StdRng::seed_from_u64(12345);
+        for _ in 0..10 {
+            for colour in g.colours() {
+                let seeds: Vec<u64> = colour.iter().map(|_| rng.gen::<u64>()).collect();
+                for (k, s) in colour.iter().zip(seeds) {
+                    let mut rng1 = StdRng::seed_from_u64(s);
+                    g.sample(index[k], 0, &mut gv2, &mut rng1, 10, &mut 0);
+                }
+            }
+        }
+        assert_eq!(gv1.sampleable_values, gv2.sampleable_values);
+        assert_eq!(gv1.deterministic_values, gv2.deterministic_values);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be sealed")]
+    fn unsealed_sample_all_test() {
+        let mut g = Graph::new();
+        let one = const_node(1.0).add_to(&mut g, &"one".to_string());
+        normal_node((one, 0), (one, 0))
+            .with_all_values(&[UnObserved(0.0)])
+            .add_to(&mut g, &"x".to_string());
+        let mut gv = g.init_gv();
+        let mut rng = StdRng::seed_from_u64(12345);
+        g.sample_all(&mut gv, &mut rng, 10, &mut 0);
+    }
+
+    #[test]
+    fn discrete_nodes_test() {
+        let p = 0.3;
+        let x = node_draws(&[p], 0.0, |p| bernoulli_node(p[0]));
+        check_moments("bernoulli", &x, p, p * (1.0 - p));
+
+        let (p, n) = (0.4, 12.0);
+        let x = node_draws(&[p, n], 0.0, |p| binomial_node(p[0], p[1]));
+        check_moments("binomial", &x, n * p, n * p * (1.0 - p));
+
+        let (a, b) = (-2.0, 5.0);
+        let x = node_draws(&[a, b], 0.0, |p| discrete_uniform_node(p[0], p[1]));
+        let m = b - a + 1.0;
+        check_moments("discrete_uniform", &x, (a + b) / 2.0, (m * m - 1.0) / 12.0);
+
+        let probs = [1.0, 3.0, 6.0];
+        let x = node_draws(&probs, 0.0, categorical_node);
+        for (k, &pk) in probs.iter().enumerate() {
+            let f = x.iter().filter(|&&x| x == k as f64).count() as f64 / N as f64;
+            assert!((f - pk / 10.0).abs() < 0.015, "categorical {} {}", f, pk);
+        }
+
+        let (lambda, kmax) = (4.0, 5);
+        let x = node_draws(&[lambda], 0.0, |p| trunc_poisson_node(p[0], kmax));
+        let pmf: Vec<f64> = (0..=kmax)
+            .map(|k| lambda.powi(k as i32) / special::Gamma::gamma(k as f64 + 1.0))
+            .collect();
+        let z: f64 = pmf.iter().sum();
+        let mean = pmf
+            .iter()
+            .enumerate()
+            .map(|(k, p)| k as f64 * p)
+            .sum::<f64>()
+            / z;
+        let var = pmf
+            .iter()
+            .enumerate()
+            .map(|(k, p)| (k as f64 - mean).powi(2) * p)
+            .sum::<f64>()
+            / z;
+        check_moments("trunc_poisson", &x, mean, var);
+        assert!(x.iter().all(|&x| x <= kmax as f64));
+    }
+
+    #[test]
+    fn mixture_test() {
+        //two-component mixture with known means, y_i ~ N(mu[z_i], 1) with z_i ~ Bernoulli(w)
+        let (w, mu0, mu1) = (0.3, -1.0, 2.0);
+        let ys = [-2.0, -0.5, 0.5, 1.0, 3.0];
+        let mut g = Graph::new();
+        let w_node = const_node(w).add_to(&mut g, &"w".to_string());
+        let m0 = const_node(mu0).add_to(&mut g, &"mu0".to_string());
+        let m1 = const_node(mu1).add_to(&mut g, &"mu1".to_string());
+        let one = const_node(1.0).add_to(&mut g, &"one".to_string());
+        for (i, &y) in ys.iter().enumerate() {
+            let z = bernoulli_node((w_node, 0))
+                .with_all_values(&[UnObserved(0.0)])
+                .add_to(&mut g, &format!("z{}", i));
+            let m = scalar_func_node(
+                &[(z, 0), (m0, 0), (m1, 0)],
+                Box::new(|x: &[f64]| if x[0] == 1.0 { x[2] } else { x[1] }),
+            )
+            .add_to(&mut g, &format!("m{}", i));
+            normal_node((m, 0), (one, 0))
+                .with_all_values(&[Observed(y)])
+                .add_to(&mut g, &format!("y{}", i));
+        }
+        let draws = all_draws(&mut g, N);
+        for (i, &y) in ys.iter().enumerate() {
+            let l0 = (1.0 - w) * (-(y - mu0) * (y - mu0) / 2.0).exp();
+            let l1 = w * (-(y - mu1) * (y - mu1) / 2.0).exp();
+            let p1 = l1 / (l0 + l1);
+            let f = draws.iter().filter(|d| d[i] == 1.0).count() as f64 / N as f64;
+            assert!((f - p1).abs() < 0.015, "{} {} {}", i, f, p1);
+        }
+    }
 }
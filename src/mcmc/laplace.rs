@@ -0,0 +1,339 @@
+#![allow(clippy::needless_range_loop)]
+use std::fmt::Debug;
+use std::ops::{Add, Mul, Sub};
+
+use num::traits::{float::Float, NumCast};
+
+use crate::autodiff::{eval, grad, F};
+use crate::linear_space::utils::{cholesky, forward_solve};
+use crate::linear_space::IndexableLinearSpace;
+use crate::opt::opt_errors::OptErr;
+use crate::opt::powell::fmin;
+use crate::opt::tolerance::Tolerance;
+
+///Gaussian approximation of a posterior at its mode
+#[derive(Debug)]
+pub struct LaplaceResult<T, V> {
+    pub mode: V,
+    ///Log posterior at the mode
+    pub logprob: T,
+    ///Hessian of the negative log posterior at the mode
+    pub hessian: Vec<Vec<T>>,
+    ///Inverse of `hessian`. All of `cov`, `sd`, `corr` and `log_evidence` are NaN if the
+    ///Hessian is not positive definite
+    pub cov: Vec<Vec<T>>,
+    pub sd: Vec<T>,
+    pub corr: Vec<Vec<T>>,
+    ///`ln(Z) ~ logprob + n/2 ln(2 pi) - 1/2 ln|hessian|`
+    pub log_evidence: T,
+    ///Whether the Hessian is positive definite. If not, the mode search did not end at a
+    ///maximum and the Laplace approximation is not available; callers should check this flag,
+    ///as no warning is printed
+    pub hessian_pd: bool,
+    ///Status of the mode search
+    pub opt_status: OptErr,
+}
+
+///Find the mode of `flogprob` starting from `x0` with Powell's method
+pub fn map_estimate<T, V, Func>(
+    flogprob: &Func,
+    x0: &V,
+    ftol: Tolerance<T>,
+    itmax: usize,
+) -> (V, OptErr)
+where
+    T: Float + NumCast + std::cmp::PartialOrd + Copy + Debug,
+    V: Clone + IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    Func: Fn(&V) -> T,
+{
+    fmin(&|x: &V| -flogprob(x), x0, ftol, itmax, &mut |_, _| {})
+}
+
+fn fd_step<T>(x: T) -> T
+where
+    T: Float,
+{
+    T::epsilon().powf(T::from(0.25).unwrap()) * x.abs().max(T::one())
+}
+
+///Hessian of `f` at `x` by central finite differences
+pub fn fd_hessian<T, V, Func>(f: &Func, x: &V) -> Vec<Vec<T>>
+where
+    T: Float,
+    V: Clone + IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    Func: Fn(&V) -> T,
+{
+    let n = x.dimension();
+    let two = T::one() + T::one();
+    let four = two * two;
+    let h: Vec<T> = (0..n).map(|i| fd_step(x[i])).collect();
+    let f0 = f(x);
+    let shifted = |i: usize, di: T, j: usize, dj: T| {
+        let mut y = x.clone();
+        y[i] = y[i] + di;
+        y[j] = y[j] + dj;
+        f(&y)
+    };
+    let mut result = vec![vec![T::zero(); n]; n];
+    for i in 0..n {
+        let fp = shifted(i, h[i], i, T::zero());
+        let fm = shifted(i, -h[i], i, T::zero());
+        result[i][i] = (fp - two * f0 + fm) / (h[i] * h[i]);
+        for j in 0..i {
+            let fpp = shifted(i, h[i], j, h[j]);
+            let fpm = shifted(i, h[i], j, -h[j]);
+            let fmp = shifted(i, -h[i], j, h[j]);
+            let fmm = shifted(i, -h[i], j, -h[j]);
+            result[i][j] = (fpp - fpm - fmp + fmm) / (four * h[i] * h[j]);
+            result[j][i] = result[i][j];
+        }
+    }
+    result
+}
+
+///Hessian of `f` at `x` by central finite differences of the gradient.
+///The gradient itself is exact (computed with `autodiff::grad`), so only the second
+///derivative is approximated, which makes it more accurate than `fd_hessian`.
+pub fn fd_grad_hessian<T, G>(f: &G, x: &[T]) -> Vec<Vec<T>>
+where
+    T: Float,
+    G: Fn(&[F<T>]) -> F<T>,
+{
+    let n = x.len();
+    let two = T::one() + T::one();
+    let mut result = vec![vec![T::zero(); n]; n];
+    let mut y = x.to_vec();
+    for j in 0..n {
+        let h = fd_step(x[j]);
+        y[j] = x[j] + h;
+        let gp = grad(f, &y);
+        y[j] = x[j] - h;
+        let gm = grad(f, &y);
+        y[j] = x[j];
+        for i in 0..n {
+            result[i][j] = (gp[i] - gm[i]) / (two * h);
+        }
+    }
+    //symmetrize
+    for i in 0..n {
+        for j in 0..i {
+            let s = (result[i][j] + result[j][i]) / two;
+            result[i][j] = s;
+            result[j][i] = s;
+        }
+    }
+    result
+}
+
+///Laplace approximation given the mode, the log posterior at the mode and the Hessian of the
+///negative log posterior. Prints a warning if the Hessian is not positive definite.
+pub fn laplace_at_mode<T, V>(
+    mode: V,
+    logprob: T,
+    hessian: Vec<Vec<T>>,
+    opt_status: OptErr,
+) -> LaplaceResult<T, V>
+where
+    T: Float + Debug,
+{
+    let n = hessian.len();
+    let two = T::one() + T::one();
+    let nan = T::nan();
+    let (cov, log_evidence, hessian_pd) = match cholesky(&hessian) {
+        Some(l) => {
+            //cov = l^-T l^-1, column by column
+            let mut cov = vec![vec![T::zero(); n]; n];
+            for j in 0..n {
+                let e: Vec<T> = (0..n)
+                    .map(|i| if i == j { T::one() } else { T::zero() })
+                    .collect();
+                let y = forward_solve(&l, &e);
+                let mut z = vec![T::zero(); n];
+                for i in (0..n).rev() {
+                    let s = (i + 1..n).fold(y[i], |s, k| s - l[k][i] * z[k]);
+                    z[i] = s / l[i][i];
+                }
+                for i in 0..n {
+                    cov[i][j] = z[i];
+                }
+            }
+            let half_log_det = (0..n).fold(T::zero(), |s, i| s + l[i][i].ln());
+            let log_evidence = logprob
+                + T::from(n).unwrap() * (two * T::from(std::f64::consts::PI).unwrap()).ln() / two
+                - half_log_det;
+            (cov, log_evidence, true)
+        }
+        None => (vec![vec![nan; n]; n], nan, false),
+    };
+    let sd: Vec<T> = (0..n).map(|i| cov[i][i].sqrt()).collect();
+    let corr = (0..n)
+        .map(|i| (0..n).map(|j| cov[i][j] / (sd[i] * sd[j])).collect())
+        .collect();
+    LaplaceResult {
+        mode,
+        logprob,
+        hessian,
+        cov,
+        sd,
+        corr,
+        log_evidence,
+        hessian_pd,
+        opt_status,
+    }
+}
+
+///Find the mode of `flogprob` with Powell's method starting from `x0`, and approximate the
+///posterior by a Gaussian with the inverse of the finite-difference Hessian as covariance
+pub fn laplace<T, V, Func>(
+    flogprob: &Func,
+    x0: &V,
+    ftol: Tolerance<T>,
+    itmax: usize,
+) -> LaplaceResult<T, V>
+where
+    T: Float + NumCast + std::cmp::PartialOrd + Copy + Debug,
+    V: Clone + IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    Func: Fn(&V) -> T,
+{
+    let (mode, opt_status) = map_estimate(flogprob, x0, ftol, itmax);
+    let hessian = fd_hessian(&|x: &V| -flogprob(x), &mode);
+    let logprob = flogprob(&mode);
+    laplace_at_mode(mode, logprob, hessian, opt_status)
+}
+
+///Same as `laplace`, but `flogprob` is written for `autodiff` and the Hessian is obtained with
+///`fd_grad_hessian` from its exact gradient
+pub fn laplace_ad<T, V, G>(
+    flogprob: &G,
+    x0: &V,
+    ftol: Tolerance<T>,
+    itmax: usize,
+) -> LaplaceResult<T, V>
+where
+    T: Float + NumCast + std::cmp::PartialOrd + Copy + Debug,
+    V: Clone + IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    G: Fn(&[F<T>]) -> F<T>,
+{
+    let to_vec = |x: &V| (0..x.dimension()).map(|i| x[i]).collect::<Vec<T>>();
+    let (mode, opt_status) = map_estimate(&|x: &V| eval(flogprob, &to_vec(x)), x0, ftol, itmax);
+    let x = to_vec(&mode);
+    let hessian = fd_grad_hessian(flogprob, &x)
+        .into_iter()
+        .map(|row| row.into_iter().map(|h| -h).collect())
+        .collect();
+    let logprob = eval(flogprob, &x);
+    laplace_at_mode(mode, logprob, hessian, opt_status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_space::type_wrapper::LsVec;
+
+    //N(mu, S) with S = [[1, rho s], [rho s, s^2]], unnormalized
+    const MU: [f64; 2] = [1.0, -2.0];
+    const S: f64 = 2.0;
+    const RHO: f64 = 0.6;
+
+    fn logprob_ad(x: &[F<f64>]) -> F<f64> {
+        let d0 = x[0] - MU[0];
+        let d1 = (x[1] - MU[1]) / S;
+        -(d0 * d0 - d0 * d1 * (2.0 * RHO) + d1 * d1) / (2.0 * (1.0 - RHO * RHO))
+    }
+
+    fn logprob(x: &LsVec<f64, Vec<f64>>) -> f64 {
+        eval(logprob_ad, &x.0)
+    }
+
+    #[test]
+    fn gaussian_test() {
+        let result = laplace(&logprob, &LsVec(vec![0.0, 0.0]), Tolerance::abs(1e-12), 100);
+        assert!(result.hessian_pd);
+        assert!((result.mode[0] - MU[0]).abs() < 1e-4, "{:?}", result.mode);
+        assert!((result.mode[1] - MU[1]).abs() < 1e-4, "{:?}", result.mode);
+        assert!((result.sd[0] - 1.0).abs() < 1e-3, "{:?}", result.sd);
+        assert!((result.sd[1] - S).abs() < 1e-3, "{:?}", result.sd);
+        assert!((result.corr[0][1] - RHO).abs() < 1e-3, "{:?}", result.corr);
+        assert!(
+            (result.cov[0][1] - RHO * S).abs() < 1e-3,
+            "{:?}",
+            result.cov
+        );
+        let logz = (2.0 * std::f64::consts::PI * S * (1.0 - RHO * RHO).sqrt()).ln();
+        assert!((result.log_evidence - logz).abs() < 1e-3);
+
+        let h = fd_grad_hessian(&logprob_ad, &MU);
+        for i in 0..2 {
+            for j in 0..2 {
+                assert!((h[i][j] + result.hessian[i][j]).abs() < 1e-4);
+            }
+        }
+
+        let result_ad = laplace_ad(
+            &logprob_ad,
+            &LsVec(vec![0.0, 0.0]),
+            Tolerance::abs(1e-12),
+            100,
+        );
+        assert!(result_ad.hessian_pd);
+        //the exact Hessian is the inverse of S
+        let det = S * S * (1.0 - RHO * RHO);
+        let expected = [[S * S / det, -RHO * S / det], [-RHO * S / det, 1.0 / det]];
+        for i in 0..2 {
+            assert!(
+                (result_ad.mode[i] - MU[i]).abs() < 1e-4,
+                "{:?}",
+                result_ad.mode
+            );
+            for j in 0..2 {
+                assert!(
+                    (result_ad.hessian[i][j] - expected[i][j]).abs() < 1e-6,
+                    "{:?}",
+                    result_ad.hessian
+                );
+            }
+        }
+        assert!((result_ad.log_evidence - logz).abs() < 1e-6);
+    }
+
+    #[test]
+    fn gamma_test() {
+        //Gamma(a, 1) in log space: ln p(y) = a y - exp(y), Z = Gamma(a)
+        let a = 50.0;
+        let flogprob = |x: &LsVec<f64, Vec<f64>>| a * x[0] - x[0].exp();
+        let result = laplace(&flogprob, &LsVec(vec![0.0]), Tolerance::abs(1e-12), 100);
+        assert!((result.mode[0] - f64::ln(a)).abs() < 1e-4);
+        assert!((result.sd[0] - 1.0 / a.sqrt()).abs() < 1e-4);
+        //Stirling's approximation
+        let logz = special::Gamma::ln_gamma(a).0;
+        assert!((result.log_evidence - logz).abs() < 0.01);
+    }
+
+    #[test]
+    fn not_pd_test() {
+        let flogprob = |x: &LsVec<f64, Vec<f64>>| -x[0] * x[0] + x[1] * x[1];
+        let result = laplace_at_mode(
+            LsVec(vec![0.0, 0.0]),
+            flogprob(&LsVec(vec![0.0, 0.0])),
+            fd_hessian(
+                &|x: &LsVec<f64, Vec<f64>>| -flogprob(x),
+                &LsVec(vec![0.0, 0.0]),
+            ),
+            OptErr::Normal,
+        );
+        assert!(!result.hessian_pd);
+        assert!(result.log_evidence.is_nan());
+    }
+}
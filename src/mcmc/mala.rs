@@ -0,0 +1,391 @@
+#![allow(clippy::too_many_arguments)]
+use std::ops::{Add, Mul, Sub};
+
+use num::traits::{float::Float, NumCast};
+use rand::{
+    distributions::{uniform::SampleUniform, Distribution, Standard, Uniform},
+    Rng,
+};
+use rand_distr::StandardNormal;
+
+use crate::linear_space::utils::{cholesky, forward_solve, RunningMoments};
+use crate::linear_space::IndexableLinearSpace;
+
+pub struct MalaParam<T>
+where
+    T: Float,
+{
+    target_accept_ratio: T,
+    adj_factor: T,
+    adjusted: bool,
+}
+
+impl<T> MalaParam<T>
+where
+    T: Float,
+{
+    pub fn new(target_accept_ratio: T, adj_factor: T) -> MalaParam<T> {
+        MalaParam {
+            target_accept_ratio,
+            adj_factor,
+            adjusted: true,
+        }
+    }
+
+    pub fn quick_adj(target_accept_ratio: T) -> MalaParam<T> {
+        Self::new(target_accept_ratio, T::from(0.01).unwrap())
+    }
+
+    pub fn fixed(target_accept_ratio: T) -> MalaParam<T> {
+        Self::new(target_accept_ratio, T::zero())
+    }
+
+    ///Unadjusted Langevin algorithm: every proposal is accepted and the step size is kept fixed,
+    ///so that the chain is only approximately invariant for the target
+    pub fn unadjusted() -> MalaParam<T> {
+        MalaParam {
+            target_accept_ratio: T::one(),
+            adj_factor: T::zero(),
+            adjusted: false,
+        }
+    }
+}
+
+impl<T> std::default::Default for MalaParam<T>
+where
+    T: Float,
+{
+    fn default() -> Self {
+        MalaParam::quick_adj(T::from(0.574).unwrap())
+    }
+}
+
+///The preconditioning matrix `M` of the Langevin dynamics, stored as its Cholesky factor.
+///An adaptive preconditioner is set to the covariance of the visited states every
+///`update_interval` iterations until it is frozen.
+pub struct Preconditioner<T, V>
+where
+    T: Float,
+{
+    chol: Vec<Vec<T>>,
+    moments: Option<RunningMoments<T, V>>,
+    update_interval: usize,
+}
+
+impl<T, V> Preconditioner<T, V>
+where
+    T: Float + NumCast + std::fmt::Debug,
+    V: Clone + IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+{
+    pub fn identity(ndim: usize) -> Preconditioner<T, V> {
+        Self::diagonal(&vec![T::one(); ndim])
+    }
+
+    pub fn diagonal(m: &[T]) -> Preconditioner<T, V> {
+        let n = m.len();
+        let chol = (0..n)
+            .map(|i| {
+                (0..n)
+                    .map(|j| if i == j { m[i].sqrt() } else { T::zero() })
+                    .collect()
+            })
+            .collect();
+        Preconditioner {
+            chol,
+            moments: None,
+            update_interval: 0,
+        }
+    }
+
+    pub fn dense(m: &[Vec<T>]) -> Preconditioner<T, V> {
+        Preconditioner {
+            chol: cholesky(m).expect("preconditioner is not positive definite"),
+            moments: None,
+            update_interval: 0,
+        }
+    }
+
+    pub fn adaptive(ndim: usize, update_interval: usize) -> Preconditioner<T, V> {
+        Preconditioner {
+            moments: Some(RunningMoments::new()),
+            update_interval: update_interval.max(1),
+            ..Self::identity(ndim)
+        }
+    }
+
+    pub fn freeze(&mut self) {
+        self.moments = None;
+    }
+
+    pub fn matrix(&self) -> Vec<Vec<T>> {
+        let n = self.chol.len();
+        (0..n)
+            .map(|i| {
+                (0..n)
+                    .map(|j| {
+                        (0..=i.min(j)).fold(T::zero(), |s, k| s + self.chol[i][k] * self.chol[j][k])
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[allow(clippy::manual_is_multiple_of)]
+    fn update(&mut self, x: &V) {
+        let ndim = self.chol.len();
+        if let Some(ref mut m) = self.moments {
+            m.push(x);
+            if m.count() > 2 * ndim && m.count() % self.update_interval == 0 {
+                let mut c = m.cov();
+                for (i, row) in c.iter_mut().enumerate() {
+                    row[i] = row[i] + T::epsilon().sqrt() * (T::one() + row[i].abs());
+                }
+                if let Some(l) = cholesky(&c) {
+                    self.chol = l;
+                }
+            }
+        }
+    }
+
+    ///`L*u`
+    fn mul_l(&self, u: &[T]) -> Vec<T> {
+        (0..u.len())
+            .map(|i| (0..=i).fold(T::zero(), |s, k| s + self.chol[i][k] * u[k]))
+            .collect()
+    }
+
+    ///`M*g=L*L^T*g`
+    fn mul_m(&self, g: &V) -> Vec<T> {
+        let n = self.chol.len();
+        let ltg: Vec<T> = (0..n)
+            .map(|i| (i..n).fold(T::zero(), |s, k| s + self.chol[k][i] * g[k]))
+            .collect();
+        self.mul_l(&ltg)
+    }
+}
+
+///Mean of the Langevin proposal from `x`, i.e., `x+epsilon^2/2*M*grad`
+fn drift<T, V>(x: &V, grad: &V, epsilon: T, precond: &Preconditioner<T, V>) -> V
+where
+    T: Float + NumCast + std::fmt::Debug,
+    V: Clone + IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+{
+    let h = epsilon * epsilon / (T::one() + T::one());
+    let mg = precond.mul_m(grad);
+    let mut result = x.clone();
+    for i in 0..x.dimension() {
+        result[i] = x[i] + h * mg[i];
+    }
+    result
+}
+
+///Log density (up to a constant) of proposing `y` from the proposal with mean `mean`
+fn log_q<T, V>(y: &V, mean: &V, epsilon: T, precond: &Preconditioner<T, V>) -> T
+where
+    T: Float + NumCast + std::fmt::Debug,
+    V: Clone + IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+{
+    let r: Vec<T> = (0..y.dimension()).map(|i| y[i] - mean[i]).collect();
+    let z = forward_solve(&precond.chol, &r);
+    -z.iter().fold(T::zero(), |s, &z| s + z * z) / ((T::one() + T::one()) * epsilon * epsilon)
+}
+
+///One (preconditioned) Metropolis-adjusted Langevin step, or an unadjusted one if so set in `param`.
+///`lp` and `last_grad_logprob` are the log probability and its gradient at `q0`, which are updated
+///together with it. `epsilon` is adapted towards `param`'s target acceptance ratio.
+///In unadjusted mode `flogprob` is never evaluated and `lp` is left untouched.
+///Returns whether the proposal has been accepted.
+pub fn sample<T, U, V, F, G>(
+    flogprob: &F,
+    grad_logprob: &G,
+    q0: &mut V,
+    lp: &mut T,
+    last_grad_logprob: &mut V,
+    rng: &mut U,
+    epsilon: &mut T,
+    param: &MalaParam<T>,
+    precond: &mut Preconditioner<T, V>,
+) -> bool
+where
+    T: Float + NumCast + SampleUniform + std::fmt::Debug,
+    Standard: Distribution<T>,
+    StandardNormal: Distribution<T>,
+    U: Rng,
+    V: Clone + IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    F: Fn(&V) -> T,
+    G: Fn(&V) -> V,
+{
+    let ndim = q0.dimension();
+    assert_eq!(precond.chol.len(), ndim);
+    let unit = Uniform::new(T::zero(), T::one());
+    let u: Vec<T> = (0..ndim).map(|_| rng.sample(StandardNormal)).collect();
+    let noise = precond.mul_l(&u);
+    let mean_fwd = drift(q0, last_grad_logprob, *epsilon, precond);
+    let mut q = mean_fwd.clone();
+    for i in 0..ndim {
+        q[i] = q[i] + *epsilon * noise[i];
+    }
+    let grad_q = grad_logprob(&q);
+
+    let (accepted, lp_q) = if param.adjusted {
+        let lp_q = flogprob(&q);
+        let mean_bwd = drift(&q, &grad_q, *epsilon, precond);
+        let log_alpha = lp_q - *lp + log_q(q0, &mean_bwd, *epsilon, precond)
+            - log_q(&q, &mean_fwd, *epsilon, precond);
+        let accepted = !log_alpha.is_nan() && rng.sample(&unit).ln() < log_alpha;
+        let factor = T::one() + param.adj_factor;
+        if accepted {
+            if rng.sample(&unit) < T::one() - param.target_accept_ratio {
+                *epsilon = *epsilon * factor;
+            }
+        } else if rng.sample(&unit) < param.target_accept_ratio {
+            *epsilon = *epsilon / factor;
+        }
+        (accepted, lp_q)
+    } else {
+        (true, *lp)
+    };
+
+    if accepted {
+        *q0 = q;
+        *lp = lp_q;
+        *last_grad_logprob = grad_q;
+    }
+    precond.update(q0);
+    accepted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_space::type_wrapper::LsVec;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type Vf = LsVec<f64, Vec<f64>>;
+
+    //zero mean Gaussian with standard deviations 1 and 5 and correlation 0.5
+    const S: [f64; 2] = [1.0, 5.0];
+    const RHO: f64 = 0.5;
+
+    fn flogprob(x: &Vf) -> f64 {
+        let (a, b) = (x[0] / S[0], x[1] / S[1]);
+        -(a * a - 2.0 * RHO * a * b + b * b) / (2.0 * (1.0 - RHO * RHO))
+    }
+
+    fn grad(x: &Vf) -> Vf {
+        let (a, b) = (x[0] / S[0], x[1] / S[1]);
+        let k = 1.0 - RHO * RHO;
+        LsVec(vec![-(a - RHO * b) / k / S[0], -(b - RHO * a) / k / S[1]])
+    }
+
+    fn run(
+        param: &MalaParam<f64>,
+        precond: &mut Preconditioner<f64, Vf>,
+        epsilon: f64,
+        n: usize,
+    ) -> ([f64; 2], [f64; 3], f64) {
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut x = LsVec(vec![1.0, 1.0]);
+        let mut lp = flogprob(&x);
+        let mut g = grad(&x);
+        let mut eps = epsilon;
+        for _ in 0..5000 {
+            sample(
+                &flogprob, &grad, &mut x, &mut lp, &mut g, &mut rng, &mut eps, param, precond,
+            );
+        }
+        precond.freeze();
+        let (mut m, mut c) = ([0.0; 2], [0.0; 3]);
+        let mut nacc = 0;
+        for _ in 0..n {
+            if sample(
+                &flogprob, &grad, &mut x, &mut lp, &mut g, &mut rng, &mut eps, param, precond,
+            ) {
+                nacc += 1;
+            }
+            m[0] += x[0];
+            m[1] += x[1];
+            c[0] += x[0] * x[0];
+            c[1] += x[1] * x[1];
+            c[2] += x[0] * x[1];
+        }
+        let n = n as f64;
+        (
+            [m[0] / n, m[1] / n],
+            [c[0] / n, c[1] / n, c[2] / n],
+            nacc as f64 / n,
+        )
+    }
+
+    fn check(m: [f64; 2], c: [f64; 3], tol: f64) {
+        assert!(m[0].abs() < tol * S[0], "{:?}", m);
+        assert!(m[1].abs() < tol * S[1], "{:?}", m);
+        assert!((c[0] / (S[0] * S[0]) - 1.0).abs() < tol, "{:?}", c);
+        assert!((c[1] / (S[1] * S[1]) - 1.0).abs() < tol, "{:?}", c);
+        assert!((c[2] / (S[0] * S[1]) - RHO).abs() < tol, "{:?}", c);
+    }
+
+    #[test]
+    fn mala_test() {
+        let (m, c, acc) = run(
+            &MalaParam::default(),
+            &mut Preconditioner::identity(2),
+            0.1,
+            200000,
+        );
+        check(m, c, 0.1);
+        assert!((acc - 0.574).abs() < 0.05, "{}", acc);
+    }
+
+    #[test]
+    fn preconditioned_test() {
+        let mut precond = Preconditioner::adaptive(2, 100);
+        let (m, c, acc) = run(&MalaParam::default(), &mut precond, 0.1, 50000);
+        check(m, c, 0.1);
+        assert!((acc - 0.574).abs() < 0.05, "{}", acc);
+        let mm = precond.matrix();
+        assert!((mm[1][1] / mm[0][0] - 25.0).abs() < 10.0, "{:?}", mm);
+    }
+
+    #[test]
+    fn ula_test() {
+        let mut precond = Preconditioner::dense(&[
+            vec![S[0] * S[0], RHO * S[0] * S[1]],
+            vec![RHO * S[0] * S[1], S[1] * S[1]],
+        ]);
+        let (m, c, acc) = run(&MalaParam::unadjusted(), &mut precond, 0.1, 200000);
+        check(m, c, 0.1);
+        assert_eq!(acc, 1.0);
+
+        //only the gradient is needed without the Metropolis correction
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut x = LsVec(vec![1.0, 1.0]);
+        let (mut lp, mut g, mut eps) = (0.0, grad(&x), 0.1);
+        for _ in 0..100 {
+            sample(
+                &|_: &Vf| -> f64 { panic!("flogprob called by ULA") },
+                &grad,
+                &mut x,
+                &mut lp,
+                &mut g,
+                &mut rng,
+                &mut eps,
+                &MalaParam::unadjusted(),
+                &mut precond,
+            );
+        }
+        assert_eq!(lp, 0.0);
+    }
+}
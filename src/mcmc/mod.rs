@@ -1,12 +1,25 @@
+pub mod abc;
+pub mod adaptive_metropolis;
+pub mod advi;
 pub mod arms;
+pub mod de_mcz;
 pub mod ensemble_sample;
 pub mod functions;
+pub mod gibbs;
 pub mod graph;
 pub mod init_ensemble;
+pub mod laplace;
+pub mod mala;
 pub mod mcmc_errors;
+pub mod nested_sampling;
 //pub mod mcmc_vec;
 pub mod hmc;
 pub mod nuts;
+pub mod psis;
+pub mod rjmcmc;
+pub mod sgmcmc;
+pub mod slice;
+pub mod smc;
 pub mod twalk;
 pub mod utils;
 
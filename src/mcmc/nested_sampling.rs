@@ -0,0 +1,612 @@
+use num::traits::{float::Float, NumCast};
+use rand::{
+    distributions::{uniform::SampleUniform, Distribution, Standard, Uniform},
+    Rng,
+};
+use rand_distr::StandardNormal;
+
+use crate::kmeans::kmeans2;
+use crate::linear_space::type_wrapper::LsVec;
+use crate::linear_space::utils::{cholesky, cov, forward_solve, mean};
+
+type Point<T> = LsVec<T, Vec<T>>;
+
+pub struct NestedSamplingParams<T>
+where
+    T: Float,
+{
+    ///Number of live points
+    pub nlive: usize,
+    ///Factor by which the volume of every bounding ellipsoid is enlarged
+    pub enlarge: T,
+    ///The bounding ellipsoids are rebuilt every `update_interval` iterations
+    pub update_interval: usize,
+    ///Number of draws from the ellipsoids, counting those outside of the unit cube or below the
+    ///likelihood constraint, after which the random-walk fallback is used
+    pub max_tries: usize,
+    ///Number of random-walk steps of the fallback
+    pub nwalk: usize,
+    ///Stop when the estimated remaining evidence is below this fraction (in log) of the current one
+    pub dlogz: T,
+    pub max_iter: usize,
+}
+
+impl<T> NestedSamplingParams<T>
+where
+    T: Float,
+{
+    pub fn new(nlive: usize) -> NestedSamplingParams<T> {
+        NestedSamplingParams {
+            nlive,
+            enlarge: T::from(1.25).unwrap(),
+            update_interval: (nlive / 5).max(1),
+            max_tries: 100,
+            nwalk: 25,
+            dlogz: T::from(0.01).unwrap(),
+            max_iter: usize::MAX,
+        }
+    }
+}
+
+pub struct NestedSamplingResult<T, V>
+where
+    T: Float,
+{
+    pub logz: T,
+    ///Standard deviation of `logz`, estimated as `sqrt(H/nlive)`
+    pub logz_err: T,
+    ///Information `H`, i.e., the KL divergence from the prior to the posterior
+    pub information: T,
+    ///Dead points followed by the final live points
+    pub samples: Vec<V>,
+    pub logl: Vec<T>,
+    ///Log posterior weights of `samples`, normalized so that the weights sum to one
+    pub log_weights: Vec<T>,
+    pub niter: usize,
+    ///Number of likelihood evaluations
+    pub ncall: usize,
+}
+
+impl<T, V> NestedSamplingResult<T, V>
+where
+    T: Float + SampleUniform,
+    V: Clone,
+{
+    pub fn weights(&self) -> Vec<T> {
+        self.log_weights.iter().map(|w| w.exp()).collect()
+    }
+
+    ///Draw `n` equally weighted posterior samples by systematic resampling
+    pub fn resample_equal<U>(&self, n: usize, rng: &mut U) -> Vec<V>
+    where
+        U: Rng,
+    {
+        let nn = T::from(n).unwrap();
+        let u0 = rng.sample(Uniform::new(T::zero(), T::one()));
+        let mut result = Vec::with_capacity(n);
+        let mut cum = T::zero();
+        let mut k = 0;
+        for (x, w) in self.samples.iter().zip(self.weights()) {
+            cum = cum + w * nn;
+            while k < n && T::from(k).unwrap() + u0 < cum {
+                result.push(x.clone());
+                k += 1;
+            }
+        }
+        while result.len() < n {
+            result.push(self.samples.last().unwrap().clone());
+        }
+        result
+    }
+}
+
+fn log_add_exp<T>(a: T, b: T) -> T
+where
+    T: Float,
+{
+    if a == T::neg_infinity() {
+        b
+    } else if b == T::neg_infinity() {
+        a
+    } else {
+        let m = a.max(b);
+        m + ((a - m).exp() + (b - m).exp()).ln()
+    }
+}
+
+///The information `h` after adding a point of log likelihood `l` and log weight `logwt` to the
+///evidence `logz`, which becomes `logz_new`
+fn update_information<T>(h: T, logz: T, logz_new: T, l: T, logwt: T) -> T
+where
+    T: Float,
+{
+    if logwt == T::neg_infinity() {
+        //no weight, e.g., a point of zero likelihood
+        h
+    } else if logz == T::neg_infinity() {
+        //the first point of nonzero weight, the term of the previous information vanishes
+        (logwt - logz_new).exp() * l - logz_new
+    } else {
+        (logwt - logz_new).exp() * l + (logz - logz_new).exp() * (h + logz) - logz_new
+    }
+}
+
+#[allow(clippy::manual_is_multiple_of)]
+fn log_unit_ball_volume<T>(ndim: usize) -> T
+where
+    T: Float,
+{
+    let pi = T::from(std::f64::consts::PI).unwrap();
+    let mut v = if ndim % 2 == 0 {
+        T::one()
+    } else {
+        T::one() + T::one()
+    };
+    let mut d = if ndim % 2 == 0 { 2 } else { 3 };
+    while d <= ndim {
+        v = v * (pi + pi) / T::from(d).unwrap();
+        d += 2;
+    }
+    v.ln()
+}
+
+#[derive(Clone, Debug)]
+struct Ellipsoid<T> {
+    center: Vec<T>,
+    ///Cholesky factor of the shape matrix `A`, the ellipsoid is `(x-c)^T A^-1 (x-c)<=1`
+    chol: Vec<Vec<T>>,
+    log_vol: T,
+}
+
+impl<T> Ellipsoid<T>
+where
+    T: Float + NumCast + SampleUniform + std::fmt::Debug,
+    Standard: Distribution<T>,
+    StandardNormal: Distribution<T>,
+{
+    fn from_points(points: &[Point<T>], enlarge: T) -> Ellipsoid<T> {
+        let ndim = points[0].len();
+        let center = mean(points);
+        let mut c = vec![vec![T::zero(); ndim]; ndim];
+        cov(points, &mut |i, j, x| c[i][j] = x);
+        let trace = (0..ndim).fold(T::zero(), |s, i| s + c[i][i]);
+        let mut eps =
+            (trace / T::from(ndim).unwrap()).max(T::min_positive_value()) * T::from(1e-10).unwrap();
+        let l = loop {
+            if let Some(l) = cholesky(&c) {
+                break l;
+            }
+            for (i, row) in c.iter_mut().enumerate() {
+                row[i] = row[i] + eps;
+            }
+            eps = eps * T::from(10).unwrap();
+        };
+        let r2 = points
+            .iter()
+            .map(|x| {
+                let d: Vec<T> = (0..ndim).map(|i| x[i] - center[i]).collect();
+                forward_solve(&l, &d)
+                    .iter()
+                    .fold(T::zero(), |s, &y| s + y * y)
+            })
+            .fold(T::zero(), |a, b| a.max(b));
+        let scale = r2.sqrt() * enlarge.powf(T::one() / T::from(ndim).unwrap());
+        let chol: Vec<Vec<T>> = l
+            .into_iter()
+            .map(|row| row.into_iter().map(|x| x * scale).collect())
+            .collect();
+        let log_vol = (0..ndim).fold(log_unit_ball_volume(ndim), |s, i| s + chol[i][i].ln());
+        Ellipsoid {
+            center: center.0,
+            chol,
+            log_vol,
+        }
+    }
+
+    fn contains(&self, x: &[T]) -> bool {
+        let d: Vec<T> = x
+            .iter()
+            .zip(self.center.iter())
+            .map(|(&a, &b)| a - b)
+            .collect();
+        forward_solve(&self.chol, &d)
+            .iter()
+            .fold(T::zero(), |s, &y| s + y * y)
+            <= T::one()
+    }
+
+    ///A uniformly distributed point inside the unit ball mapped by `chol`, without the center
+    fn sample_offset<U>(&self, rng: &mut U) -> Vec<T>
+    where
+        U: Rng,
+    {
+        let ndim = self.center.len();
+        let z: Vec<T> = loop {
+            let z: Vec<T> = (0..ndim).map(|_| rng.sample(StandardNormal)).collect();
+            let norm = z.iter().fold(T::zero(), |s, &x| s + x * x).sqrt();
+            if norm > T::zero() {
+                let r = rng
+                    .sample(Uniform::new(T::zero(), T::one()))
+                    .powf(T::one() / T::from(ndim).unwrap());
+                break z.into_iter().map(|x| x / norm * r).collect();
+            }
+        };
+        (0..ndim)
+            .map(|i| (0..=i).fold(T::zero(), |s, k| s + self.chol[i][k] * z[k]))
+            .collect()
+    }
+
+    fn sample<U>(&self, rng: &mut U) -> Vec<T>
+    where
+        U: Rng,
+    {
+        self.sample_offset(rng)
+            .into_iter()
+            .zip(self.center.iter())
+            .map(|(a, &b)| a + b)
+            .collect()
+    }
+}
+
+///Recursively split `points` into clusters with `kmeans2`, as long as the total volume of the
+///bounding ellipsoids is reduced by at least a half
+fn bound<T>(points: &[Point<T>], enlarge: T) -> Vec<Ellipsoid<T>>
+where
+    T: Float + NumCast + SampleUniform + std::fmt::Debug,
+    Standard: Distribution<T>,
+    StandardNormal: Distribution<T>,
+{
+    let ndim = points[0].len();
+    let e = Ellipsoid::from_points(points, enlarge);
+    if points.len() < 2 * (ndim + 1) {
+        return vec![e];
+    }
+
+    let far = |x0: &Point<T>| {
+        points
+            .iter()
+            .max_by(|a, b| {
+                let da = (0..ndim).fold(T::zero(), |s, i| s + (a[i] - x0[i]).powi(2));
+                let db = (0..ndim).fold(T::zero(), |s, i| s + (b[i] - x0[i]).powi(2));
+                da.partial_cmp(&db).unwrap()
+            })
+            .unwrap()
+            .clone()
+    };
+    let p1 = far(&points[0]);
+    let p2 = far(&p1);
+    let mut centroids = vec![p1, p2];
+    let labels = kmeans2(points, &mut centroids, 10);
+    let clusters: Vec<Vec<Point<T>>> = (0..2)
+        .map(|k| {
+            points
+                .iter()
+                .zip(labels.iter())
+                .filter(|&(_, &l)| l == k)
+                .map(|(x, _)| x.clone())
+                .collect()
+        })
+        .collect();
+    if clusters.iter().any(|c| c.len() < ndim + 1) {
+        return vec![e];
+    }
+    let e1 = Ellipsoid::from_points(&clusters[0], enlarge);
+    let e2 = Ellipsoid::from_points(&clusters[1], enlarge);
+    if log_add_exp(e1.log_vol, e2.log_vol) < e.log_vol - T::from(2).unwrap().ln() {
+        clusters.iter().flat_map(|c| bound(c, enlarge)).collect()
+    } else {
+        vec![e]
+    }
+}
+
+fn in_unit_cube<T>(x: &[T]) -> bool
+where
+    T: Float,
+{
+    x.iter().all(|&x| x > T::zero() && x < T::one())
+}
+
+///One draw from the union of the ellipsoids, `None` if it falls outside the unit cube or is
+///rejected to correct for the overlaps, so that accepted points are uniform in the union
+fn sample_from_union<T, U>(ellipsoids: &[Ellipsoid<T>], rng: &mut U) -> Option<Vec<T>>
+where
+    T: Float + NumCast + SampleUniform + std::fmt::Debug,
+    Standard: Distribution<T>,
+    StandardNormal: Distribution<T>,
+    U: Rng,
+{
+    let log_vmax = ellipsoids
+        .iter()
+        .map(|e| e.log_vol)
+        .fold(T::neg_infinity(), |a, b| a.max(b));
+    let cum: Vec<T> = ellipsoids
+        .iter()
+        .scan(T::zero(), |s, e| {
+            *s = *s + (e.log_vol - log_vmax).exp();
+            Some(*s)
+        })
+        .collect();
+    let total = *cum.last().unwrap();
+    let r = rng.sample(Uniform::new(T::zero(), total));
+    let k = cum
+        .iter()
+        .position(|&c| r < c)
+        .unwrap_or(ellipsoids.len() - 1);
+    let x = ellipsoids[k].sample(rng);
+    if !in_unit_cube(&x) {
+        return None;
+    }
+    let q = ellipsoids.iter().filter(|e| e.contains(&x)).count().max(1);
+    if q == 1 || rng.sample(Uniform::new(T::zero(), T::one())) < T::one() / T::from(q).unwrap() {
+        Some(x)
+    } else {
+        None
+    }
+}
+
+///Static nested sampling (Skilling 2004) with multi-ellipsoidal bounds (Feroz et al. 2009).
+///`prior_transform` maps a point of the `ndim` dimensional unit hypercube to the parameter space,
+///`floglike` is the log likelihood of the parameters.
+pub fn sample<T, U, V, F, P>(
+    floglike: &F,
+    prior_transform: &P,
+    ndim: usize,
+    params: &NestedSamplingParams<T>,
+    rng: &mut U,
+) -> NestedSamplingResult<T, V>
+where
+    T: Float + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
+    Standard: Distribution<T>,
+    StandardNormal: Distribution<T>,
+    U: Rng,
+    V: Clone,
+    F: Fn(&V) -> T + ?Sized,
+    P: Fn(&[T]) -> V + ?Sized,
+{
+    let nlive = params.nlive;
+    assert!(nlive > ndim + 1);
+    let unit = Uniform::new(T::zero(), T::one());
+    let mut ncall = 0;
+    let mut eval = |u: &[T]| {
+        ncall += 1;
+        let x = prior_transform(u);
+        let l = floglike(&x);
+        (x, if l.is_nan() { T::neg_infinity() } else { l })
+    };
+
+    let mut live_u: Vec<Point<T>> = Vec::with_capacity(nlive);
+    let mut live_x: Vec<V> = Vec::with_capacity(nlive);
+    let mut live_l: Vec<T> = Vec::with_capacity(nlive);
+    for _ in 0..nlive {
+        let u: Vec<T> = (0..ndim).map(|_| rng.sample(&unit)).collect();
+        let (x, l) = eval(&u);
+        live_u.push(LsVec(u));
+        live_x.push(x);
+        live_l.push(l);
+    }
+
+    let nn = T::from(nlive).unwrap();
+    let log_shrink = (T::one() - (-T::one() / nn).exp()).ln();
+    let mut logx = T::zero();
+    let mut logz = T::neg_infinity();
+    let mut h = T::zero();
+    let mut samples = Vec::new();
+    let mut logl = Vec::new();
+    let mut logwt_list = Vec::new();
+
+    let mut ellipsoids = bound(&live_u, params.enlarge);
+    let mut rw_scale = T::one();
+    let mut niter = 0;
+    while niter < params.max_iter {
+        let lmax = live_l.iter().fold(T::neg_infinity(), |a, &b| a.max(b));
+        if niter > 0 && log_add_exp(logz, lmax + logx) - logz < params.dlogz {
+            break;
+        }
+
+        let worst = (0..nlive)
+            .min_by(|&a, &b| live_l[a].partial_cmp(&live_l[b]).unwrap())
+            .unwrap();
+        let lmin = live_l[worst];
+        let logwt = logx + log_shrink + lmin;
+        let logz_new = log_add_exp(logz, logwt);
+        h = update_information(h, logz, logz_new, lmin, logwt);
+        logz = logz_new;
+        logx = logx - T::one() / nn;
+        samples.push(live_x[worst].clone());
+        logl.push(lmin);
+        logwt_list.push(logwt);
+
+        if niter % params.update_interval == 0 {
+            ellipsoids = bound(&live_u, params.enlarge);
+        }
+
+        let mut replaced = false;
+        for _ in 0..params.max_tries {
+            if let Some(u) = sample_from_union(&ellipsoids, rng) {
+                let (x, l) = eval(&u);
+                if l > lmin {
+                    live_u[worst] = LsVec(u);
+                    live_x[worst] = x;
+                    live_l[worst] = l;
+                    replaced = true;
+                    break;
+                }
+            }
+        }
+
+        if !replaced {
+            //random walk within the likelihood constraint starting from a random live point
+            let e = ellipsoids
+                .iter()
+                .max_by(|a, b| a.log_vol.partial_cmp(&b.log_vol).unwrap())
+                .unwrap();
+            let start = loop {
+                let k = rng.gen_range(0..nlive);
+                if k != worst || nlive == 1 {
+                    break k;
+                }
+            };
+            let mut u = live_u[start].0.clone();
+            let mut x = live_x[start].clone();
+            let mut l = live_l[start];
+            let mut naccepted = 0;
+            for _ in 0..params.nwalk {
+                let u1: Vec<T> = e
+                    .sample_offset(rng)
+                    .into_iter()
+                    .zip(u.iter())
+                    .map(|(d, &u0)| u0 + d * rw_scale)
+                    .collect();
+                if !in_unit_cube(&u1) {
+                    continue;
+                }
+                let (x1, l1) = eval(&u1);
+                if l1 > lmin {
+                    u = u1;
+                    x = x1;
+                    l = l1;
+                    naccepted += 1;
+                }
+            }
+            let acc = T::from(naccepted).unwrap() / T::from(params.nwalk.max(1)).unwrap();
+            let half = T::from(0.5).unwrap();
+            rw_scale = (rw_scale * (acc - half).exp()).min(T::one());
+            live_u[worst] = LsVec(u);
+            live_x[worst] = x;
+            live_l[worst] = l;
+        }
+        niter += 1;
+    }
+
+    let log_wlive = logx - nn.ln();
+    let mut order: Vec<usize> = (0..nlive).collect();
+    order.sort_by(|&a, &b| live_l[a].partial_cmp(&live_l[b]).unwrap());
+    for i in order {
+        let logwt = log_wlive + live_l[i];
+        let logz_new = log_add_exp(logz, logwt);
+        h = update_information(h, logz, logz_new, live_l[i], logwt);
+        logz = logz_new;
+        samples.push(live_x[i].clone());
+        logl.push(live_l[i]);
+        logwt_list.push(logwt);
+    }
+
+    NestedSamplingResult {
+        logz,
+        logz_err: (h.max(T::zero()) / nn).sqrt(),
+        information: h,
+        samples,
+        logl,
+        log_weights: logwt_list.into_iter().map(|w| w - logz).collect(),
+        niter,
+        ncall,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn check<F>(floglike: &F, m_expected: f64, v_expected: f64, h_expected: f64)
+    where
+        F: Fn(&Vec<f64>) -> f64,
+    {
+        let mut rng = StdRng::seed_from_u64(12345);
+        let prior_transform = |u: &[f64]| u.iter().map(|&u| 20.0 * u - 10.0).collect::<Vec<_>>();
+        let result = sample(
+            floglike,
+            &prior_transform,
+            2,
+            &NestedSamplingParams::new(400),
+            &mut rng,
+        );
+        //uniform prior on [-10, 10]^2 and normalized likelihood
+        let logz_expected = -(400.0_f64).ln();
+        assert!(
+            (result.logz - logz_expected).abs() < 3.0 * result.logz_err + 0.05,
+            "{} {}",
+            result.logz,
+            result.logz_err
+        );
+        assert!(
+            (result.information - h_expected).abs() < 0.3,
+            "{} {}",
+            result.information,
+            h_expected
+        );
+
+        let w = result.weights();
+        assert!((w.iter().sum::<f64>() - 1.0).abs() < 1e-8);
+        for i in 0..2 {
+            let m = result
+                .samples
+                .iter()
+                .zip(w.iter())
+                .map(|(x, w)| x[i] * w)
+                .sum::<f64>();
+            let v = result
+                .samples
+                .iter()
+                .zip(w.iter())
+                .map(|(x, w)| (x[i] - m).powi(2) * w)
+                .sum::<f64>();
+            assert!((m - m_expected).abs() < 0.1, "{}", m);
+            assert!((v / v_expected - 1.0).abs() < 0.15, "{}", v);
+        }
+
+        let eq = result.resample_equal(1000, &mut rng);
+        assert_eq!(eq.len(), 1000);
+    }
+
+    #[test]
+    fn information_test() {
+        //H = sum_i p_i ln(L_i / Z) with p_i = w_i L_i / Z, including a point of zero likelihood
+        let points = [
+            (f64::NEG_INFINITY, 0.5f64.ln()),
+            (-2.0, 0.25f64.ln()),
+            (-1.0, 0.125f64.ln()),
+            (0.5, 0.125f64.ln()),
+        ];
+        let (mut logz, mut h) = (f64::NEG_INFINITY, 0.0);
+        for &(l, logw) in &points {
+            let logwt = logw + l;
+            let logz_new = log_add_exp(logz, logwt);
+            h = update_information(h, logz, logz_new, l, logwt);
+            assert!(h.is_finite());
+            logz = logz_new;
+        }
+        let z: f64 = points.iter().map(|&(l, logw)| (logw + l).exp()).sum();
+        let h_expected: f64 = points
+            .iter()
+            .filter(|&&(l, _)| l.is_finite())
+            .map(|&(l, logw)| (logw + l).exp() / z * (l - z.ln()))
+            .sum();
+        assert!((logz - z.ln()).abs() < 1e-12);
+        assert!((h - h_expected).abs() < 1e-12, "{} {}", h, h_expected);
+    }
+
+    #[test]
+    fn gaussian_test() {
+        let floglike = |x: &Vec<f64>| {
+            -x.iter().map(|x| x * x).sum::<f64>() / 2.0 - (2.0 * std::f64::consts::PI).ln()
+        };
+        let h_expected = 400.0_f64.ln() - 1.0 - (2.0 * std::f64::consts::PI).ln();
+        check(&floglike, 0.0, 1.0, h_expected);
+    }
+
+    #[test]
+    fn bimodal_test() {
+        //equal mixture of two Gaussians with sd 0.5 centered at (-3,-3) and (3,3)
+        let s2 = 0.25;
+        let floglike = |x: &Vec<f64>| {
+            let l1 = -((x[0] - 3.0).powi(2) + (x[1] - 3.0).powi(2)) / (2.0 * s2);
+            let l2 = -((x[0] + 3.0).powi(2) + (x[1] + 3.0).powi(2)) / (2.0 * s2);
+            log_add_exp(l1, l2) - (2.0 * (2.0 * std::f64::consts::PI * s2)).ln()
+        };
+        //well separated components, each carries half of the evidence
+        let h_expected =
+            400.0_f64.ln() - 1.0 - (2.0 * std::f64::consts::PI * s2).ln() - 2.0_f64.ln();
+        check(&floglike, 0.0, 9.0 + s2, h_expected);
+    }
+}
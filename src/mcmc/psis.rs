@@ -0,0 +1,383 @@
+use num::traits::Float;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+///Fit a generalized Pareto distribution to the positive exceedances `x` with the empirical Bayes
+///estimator of Zhang & Stephens (2009), including the weakly informative prior on the shape used
+///by Vehtari et al. (2015). Returns the shape `k` and the scale `sigma`.
+pub fn gpdfit<T>(x: &[T]) -> (T, T)
+where
+    T: Float,
+{
+    let mut x = x.to_vec();
+    x.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = x.len();
+    let nn = T::from(n).unwrap();
+    let half = T::from(0.5).unwrap();
+    let prior_bs = T::from(3).unwrap();
+    let prior_k = T::from(10).unwrap();
+    let m = 30 + (n as f64).sqrt() as usize;
+    let mm = T::from(m).unwrap();
+    let xq = x[((n as f64) / 4.0 + 0.5) as usize - 1];
+    let xmax = x[n - 1];
+
+    let bs: Vec<T> = (1..=m)
+        .map(|j| {
+            (T::one() - (mm / (T::from(j).unwrap() - half)).sqrt()) / (prior_bs * xq)
+                + T::one() / xmax
+        })
+        .collect();
+    let ks: Vec<T> = bs
+        .iter()
+        .map(|&b| x.iter().fold(T::zero(), |s, &x| s + (-b * x).ln_1p()) / nn)
+        .collect();
+    let ls: Vec<T> = bs
+        .iter()
+        .zip(ks.iter())
+        .map(|(&b, &k)| nn * ((-b / k).ln() - k - T::one()))
+        .collect();
+    let ws: Vec<T> = ls
+        .iter()
+        .map(|&l1| T::one() / ls.iter().fold(T::zero(), |s, &l2| s + (l2 - l1).exp()))
+        .collect();
+    let threshold = T::from(10).unwrap() * T::epsilon();
+    let (bw, sw) = bs
+        .iter()
+        .zip(ws.iter())
+        .filter(|(_, &w)| w >= threshold)
+        .fold((T::zero(), T::zero()), |(bw, sw), (&b, &w)| {
+            (bw + b * w, sw + w)
+        });
+    let b = bw / sw;
+    let k = x.iter().fold(T::zero(), |s, &x| s + (-b * x).ln_1p()) / nn;
+    let sigma = -k / b;
+    ((nn * k + prior_k * half) / (nn + prior_k), sigma)
+}
+
+///Number of the largest importance ratios used to fit the Pareto tail
+fn tail_len(n: usize) -> usize {
+    let n = n as f64;
+    (0.2 * n).min(3.0 * n.sqrt()).ceil() as usize
+}
+
+///Quantile function of the generalized Pareto distribution with location zero
+fn gpd_quantile<T>(p: T, k: T, sigma: T) -> T
+where
+    T: Float,
+{
+    if k == T::zero() {
+        -sigma * (-p).ln_1p()
+    } else {
+        sigma * ((-k * (-p).ln_1p()).exp_m1()) / k
+    }
+}
+
+///`log_ratios` shifted so that their maximum is zero
+fn shift_to_max<T>(log_ratios: &[T]) -> Vec<T>
+where
+    T: Float,
+{
+    let lmax = log_ratios
+        .iter()
+        .fold(T::neg_infinity(), |a, &b| if b > a { b } else { a });
+    log_ratios.iter().map(|&l| l - lmax).collect()
+}
+
+///Fit a generalized Pareto distribution to the largest of the log ratios `lw`, whose maximum is
+///zero. Returns the shape `k` together with the indices of the tail in increasing order of the
+///ratios, the ratio above which the tail lies and the scale `sigma`.
+///`k` is infinite if there are too few ratios, and negative infinite if the tail is flat.
+fn fit_tail<T>(lw: &[T]) -> (T, Option<(Vec<usize>, T, T)>)
+where
+    T: Float,
+{
+    let n = lw.len();
+    let m = tail_len(n);
+    if m < 5 || m >= n {
+        return (T::infinity(), None);
+    }
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&a, &b| lw[a].partial_cmp(&lw[b]).unwrap());
+    let tail = order[n - m..].to_vec();
+    let cutoff = lw[order[n - m - 1]].exp();
+    let exceedances: Vec<T> = tail.iter().map(|&i| lw[i].exp() - cutoff).collect();
+    if exceedances.iter().all(|&x| x <= T::zero()) {
+        return (T::neg_infinity(), None);
+    }
+    let (k, sigma) = gpdfit(&exceedances);
+    (k, Some((tail, cutoff, sigma)))
+}
+
+///The Pareto `k` diagnostic of importance sampling with log importance ratios `log_ratios`
+///(Vehtari et al. 2015). Values below 0.5 indicate reliable estimates, values above 0.7 that
+///the importance sampling is unreliable. Returns infinity if there are too few ratios.
+pub fn pareto_k<T>(log_ratios: &[T]) -> T
+where
+    T: Float,
+{
+    fit_tail(&shift_to_max(log_ratios)).0
+}
+
+///Pareto smoothed importance sampling (Vehtari et al. 2015).
+///The largest importance ratios are replaced by the expected order statistics of a generalized
+///Pareto distribution fitted to them, truncated at the largest raw ratio.
+///Returns the normalized log weights and the Pareto `k` diagnostic.
+pub fn psis_smooth<T>(log_ratios: &[T]) -> (Vec<T>, T)
+where
+    T: Float,
+{
+    let mut lw = shift_to_max(log_ratios);
+    let (k, tail) = fit_tail(&lw);
+    if let Some((tail, cutoff, sigma)) = tail {
+        if k.is_finite() {
+            let half = T::from(0.5).unwrap();
+            let mm = T::from(tail.len()).unwrap();
+            for (z, &i) in tail.iter().enumerate() {
+                let p = (T::from(z).unwrap() + half) / mm;
+                lw[i] = (cutoff + gpd_quantile(p, k, sigma)).min(T::one()).ln();
+            }
+        }
+    }
+    let lse = log_sum_exp(&lw);
+    (lw.into_iter().map(|l| l - lse).collect(), k)
+}
+
+fn log_sum_exp<T>(x: &[T]) -> T
+where
+    T: Float,
+{
+    let xmax = x
+        .iter()
+        .fold(T::neg_infinity(), |a, &b| if b > a { b } else { a });
+    if xmax == T::neg_infinity() {
+        return xmax;
+    }
+    xmax + x.iter().fold(T::zero(), |s, &x| s + (x - xmax).exp()).ln()
+}
+
+///Sum of `x` and its standard error `sqrt(n var(x))`
+fn sum_and_se<T>(x: &[T]) -> (T, T)
+where
+    T: Float,
+{
+    let n = T::from(x.len()).unwrap();
+    let sum = x.iter().fold(T::zero(), |s, &x| s + x);
+    let mean = sum / n;
+    let var = x.iter().fold(T::zero(), |s, &x| s + (x - mean).powi(2)) / (n - T::one());
+    (sum, (n * var).sqrt())
+}
+
+///Column `i` of the draws-by-observations matrix `log_lik`
+fn observation<T>(log_lik: &[Vec<T>], i: usize) -> Vec<T>
+where
+    T: Float,
+{
+    log_lik.iter().map(|s| s[i]).collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct LooResult<T> {
+    ///Expected log pointwise predictive density
+    pub elpd_loo: T,
+    pub se: T,
+    ///Effective number of parameters
+    pub p_loo: T,
+    ///`-2 elpd_loo`
+    pub looic: T,
+    pub pointwise: Vec<T>,
+    ///Pareto `k` of each observation; the estimate of the observations with `k>0.7` is unreliable
+    pub pareto_k: Vec<T>,
+}
+
+///Pareto smoothed importance sampling leave-one-out cross-validation (Vehtari et al. 2017).
+///`log_lik[s][i]` is the log likelihood of observation `i` given the posterior draw `s`.
+pub fn loo<T>(log_lik: &[Vec<T>]) -> LooResult<T>
+where
+    T: Float + Send + Sync,
+{
+    let nobs = log_lik[0].len();
+    let ln_s = T::from(log_lik.len()).unwrap().ln();
+    let (pointwise, (lpd, pareto_k)): (Vec<T>, (Vec<T>, Vec<T>)) = (0..nobs)
+        .into_par_iter()
+        .map(|i| {
+            let ll = observation(log_lik, i);
+            let log_ratios: Vec<T> = ll.iter().map(|&l| -l).collect();
+            let (lw, k) = psis_smooth(&log_ratios);
+            let lw_ll: Vec<T> = lw.iter().zip(ll.iter()).map(|(&w, &l)| w + l).collect();
+            (log_sum_exp(&lw_ll), (log_sum_exp(&ll) - ln_s, k))
+        })
+        .unzip();
+    let (elpd_loo, se) = sum_and_se(&pointwise);
+    let lpd = lpd.iter().fold(T::zero(), |s, &x| s + x);
+    LooResult {
+        elpd_loo,
+        se,
+        p_loo: lpd - elpd_loo,
+        looic: -(T::one() + T::one()) * elpd_loo,
+        pointwise,
+        pareto_k,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WaicResult<T> {
+    pub elpd_waic: T,
+    pub se: T,
+    pub p_waic: T,
+    ///`-2 elpd_waic`
+    pub waic: T,
+    pub pointwise: Vec<T>,
+}
+
+///Widely applicable information criterion (Watanabe 2010), with the effective number of
+///parameters estimated by the variance of the log likelihood.
+///`log_lik[s][i]` is the log likelihood of observation `i` given the posterior draw `s`.
+pub fn waic<T>(log_lik: &[Vec<T>]) -> WaicResult<T>
+where
+    T: Float + Send + Sync,
+{
+    let nobs = log_lik[0].len();
+    let ns = T::from(log_lik.len()).unwrap();
+    let (pointwise, p): (Vec<T>, Vec<T>) = (0..nobs)
+        .into_par_iter()
+        .map(|i| {
+            let ll = observation(log_lik, i);
+            let mean = ll.iter().fold(T::zero(), |s, &x| s + x) / ns;
+            let var = ll.iter().fold(T::zero(), |s, &x| s + (x - mean).powi(2)) / (ns - T::one());
+            (log_sum_exp(&ll) - ns.ln() - var, var)
+        })
+        .unzip();
+    let (elpd_waic, se) = sum_and_se(&pointwise);
+    WaicResult {
+        elpd_waic,
+        se,
+        p_waic: p.iter().fold(T::zero(), |s, &x| s + x),
+        waic: -(T::one() + T::one()) * elpd_waic,
+        pointwise,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use rand_distr::StandardNormal;
+
+    #[test]
+    fn gpdfit_test() {
+        let mut rng = StdRng::seed_from_u64(12345);
+        for &k in &[0.2, 0.5, 0.9] {
+            let sigma = 2.0;
+            let x: Vec<f64> = (0..5000)
+                .map(|_| {
+                    let u: f64 = rng.gen();
+                    sigma * (u.powf(-k) - 1.0) / k
+                })
+                .collect();
+            let (k1, s1) = gpdfit(&x);
+            assert!((k1 - k).abs() < 0.1, "{} {}", k1, k);
+            assert!((s1 / sigma - 1.0).abs() < 0.1, "{} {}", s1, sigma);
+        }
+    }
+
+    #[test]
+    fn pareto_k_test() {
+        let mut rng = StdRng::seed_from_u64(12345);
+        //importance sampling of N(0,1) with the proposal N(0, 2^2): the ratios are bounded
+        let lr: Vec<f64> = (0..4000)
+            .map(|_| {
+                let x: f64 = 2.0 * rng.sample::<f64, _>(StandardNormal);
+                -x * x / 2.0 + x * x / 8.0
+            })
+            .collect();
+        let k = pareto_k(&lr);
+        assert!(k < 0.5, "{}", k);
+
+        //N(0, 2^2) with the proposal N(0, 1): the ratios have infinite variance
+        let lr: Vec<f64> = (0..4000)
+            .map(|_| {
+                let x: f64 = rng.sample(StandardNormal);
+                -x * x / 8.0 + x * x / 2.0
+            })
+            .collect();
+        let k = pareto_k(&lr);
+        assert!(k > 0.5, "{}", k);
+
+        assert_eq!(pareto_k(&lr[..10]), f64::INFINITY);
+    }
+
+    #[test]
+    fn psis_smooth_test() {
+        let mut rng = StdRng::seed_from_u64(12345);
+        //importance sampling of N(0,1) with the proposal N(0, 2^2): the ratios are bounded
+        let lr: Vec<f64> = (0..4000)
+            .map(|_| {
+                let x: f64 = 2.0 * rng.sample::<f64, _>(StandardNormal);
+                -x * x / 2.0 + x * x / 8.0
+            })
+            .collect();
+        let (lw, k) = psis_smooth(&lr);
+        assert!(k < 0.5, "{}", k);
+        let total: f64 = lw.iter().map(|l| l.exp()).sum();
+        assert!((total - 1.0).abs() < 1e-10);
+
+        //N(0, 2^2) with the proposal N(0, 1): the ratios have infinite variance
+        let lr: Vec<f64> = (0..4000)
+            .map(|_| {
+                let x: f64 = rng.sample(StandardNormal);
+                -x * x / 8.0 + x * x / 2.0
+            })
+            .collect();
+        let (_, k) = psis_smooth(&lr);
+        assert!(k > 0.5, "{}", k);
+        assert!((pareto_k(&lr) - k).abs() < 1e-10);
+    }
+
+    #[test]
+    fn loo_test() {
+        //y_i ~ N(mu, 1) with a flat prior: mu|y ~ N(ybar, 1/n), and the leave-one-out
+        //predictive density of y_i is N(ybar_{-i}, 1 + 1/(n-1))
+        let mut rng = StdRng::seed_from_u64(12345);
+        let n = 30;
+        let y: Vec<f64> = (0..n)
+            .map(|_| 1.0 + rng.sample::<f64, _>(StandardNormal))
+            .collect();
+        let ybar = y.iter().sum::<f64>() / n as f64;
+        let log_norm = |x: f64, m: f64, v: f64| {
+            -(x - m).powi(2) / (2.0 * v) - (2.0 * std::f64::consts::PI * v).ln() / 2.0
+        };
+        let log_lik: Vec<Vec<f64>> = (0..8000)
+            .map(|_| {
+                let mu = ybar + rng.sample::<f64, _>(StandardNormal) / (n as f64).sqrt();
+                y.iter().map(|&y| log_norm(y, mu, 1.0)).collect()
+            })
+            .collect();
+        let exact: f64 = y
+            .iter()
+            .map(|&yi| {
+                let m = (ybar * n as f64 - yi) / (n - 1) as f64;
+                log_norm(yi, m, 1.0 + 1.0 / (n - 1) as f64)
+            })
+            .sum();
+
+        let result = loo(&log_lik);
+        assert!(
+            (result.elpd_loo - exact).abs() < 0.1,
+            "{} {}",
+            result.elpd_loo,
+            exact
+        );
+        assert!((result.p_loo - 1.0).abs() < 0.2, "{}", result.p_loo);
+        assert!(result.pareto_k.iter().all(|&k| k < 0.5));
+        assert!(result.se > 0.0);
+
+        let w = waic(&log_lik);
+        assert!(
+            (w.elpd_waic - exact).abs() < 0.2,
+            "{} {}",
+            w.elpd_waic,
+            exact
+        );
+        assert!((w.p_waic - 1.0).abs() < 0.2, "{}", w.p_waic);
+        assert!((w.waic + 2.0 * w.elpd_waic).abs() < 1e-10);
+    }
+}
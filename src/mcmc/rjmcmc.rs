@@ -0,0 +1,227 @@
+#![allow(clippy::type_complexity)]
+use std::collections::BTreeMap;
+
+use num::traits::{float::Float, NumCast};
+use rand::{
+    distributions::{uniform::SampleUniform, Distribution, Standard, Uniform},
+    Rng,
+};
+
+///A state proposed by a move, together with the terms of the acceptance ratio that the move knows
+pub struct JumpProposal<T, S> {
+    pub state: S,
+    ///`ln(g'(u')/g(u))`, the log density ratio of the auxiliary variables drawn by the reverse
+    ///and the forward move, including e.g. the probabilities of choosing a component to remove
+    pub log_proposal_ratio: T,
+    ///`ln|d(x',u')/d(x,u)|` of the dimension-matching transform
+    pub log_jacobian: T,
+}
+
+impl<T, S> JumpProposal<T, S>
+where
+    T: Float,
+{
+    ///A proposal of a symmetric within-model move
+    pub fn symmetric(state: S) -> JumpProposal<T, S> {
+        JumpProposal {
+            state,
+            log_proposal_ratio: T::zero(),
+            log_jacobian: T::zero(),
+        }
+    }
+}
+
+///A move type, e.g., a within-model random walk, a birth, a death, a split or a merge.
+///A move is chosen with probability proportional to `weight`, and `reverse` is the index of the
+///move that undoes it (the move itself for within-model moves), so that the probabilities of
+///choosing the forward and the reverse moves enter the acceptance ratio automatically.
+///`propose` returns `None` if the move is impossible from the given state, e.g., a birth at the
+///maximum model index, which counts as a rejection.
+pub struct RjMove<'a, T, S, U> {
+    pub name: String,
+    pub weight: T,
+    pub reverse: usize,
+    pub propose: Box<dyn Fn(&S, &mut U) -> Option<JumpProposal<T, S>> + 'a>,
+}
+
+impl<'a, T, S, U> RjMove<'a, T, S, U> {
+    pub fn new<P>(name: &str, weight: T, reverse: usize, propose: P) -> RjMove<'a, T, S, U>
+    where
+        P: Fn(&S, &mut U) -> Option<JumpProposal<T, S>> + 'a,
+    {
+        RjMove {
+            name: name.to_string(),
+            weight,
+            reverse,
+            propose: Box::new(propose),
+        }
+    }
+}
+
+///Model index trace and per-move acceptance statistics
+#[derive(Clone, Debug, Default)]
+pub struct RjTrace {
+    pub model_indices: Vec<usize>,
+    pub proposed: Vec<usize>,
+    pub accepted: Vec<usize>,
+}
+
+impl RjTrace {
+    pub fn new(nmoves: usize) -> RjTrace {
+        RjTrace {
+            model_indices: Vec::new(),
+            proposed: vec![0; nmoves],
+            accepted: vec![0; nmoves],
+        }
+    }
+
+    pub fn record(&mut self, move_id: usize, accepted: bool, model_index: usize) {
+        self.proposed[move_id] += 1;
+        if accepted {
+            self.accepted[move_id] += 1;
+        }
+        self.model_indices.push(model_index);
+    }
+
+    ///Posterior model probabilities estimated from the fraction of iterations spent in each model,
+    ///discarding the first `nburn` records
+    pub fn model_probabilities(&self, nburn: usize) -> BTreeMap<usize, f64> {
+        let mut result = BTreeMap::new();
+        let kept = &self.model_indices[nburn.min(self.model_indices.len())..];
+        for &k in kept {
+            *result.entry(k).or_insert(0.0) += 1.0;
+        }
+        let n = kept.len() as f64;
+        for v in result.values_mut() {
+            *v /= n;
+        }
+        result
+    }
+
+    pub fn accept_ratio(&self, move_id: usize) -> Option<f64> {
+        if self.proposed[move_id] == 0 {
+            None
+        } else {
+            Some(self.accepted[move_id] as f64 / self.proposed[move_id] as f64)
+        }
+    }
+}
+
+///One reversible-jump step (Green 1995): choose a move, propose and accept with probability
+///`min(1, pi(x')/pi(x) * j(reverse)/j(forward) * g'(u')/g(u) * |J|)`.
+///`flogprob` is the log posterior of a state, including the prior of the model index,
+///and `lp` the cached log posterior of `state`.
+///Returns the index of the chosen move and whether it has been accepted.
+pub fn sample<T, S, U, F>(
+    flogprob: &F,
+    moves: &[RjMove<T, S, U>],
+    state: &mut S,
+    lp: &mut T,
+    rng: &mut U,
+) -> (usize, bool)
+where
+    T: Float + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
+    Standard: Distribution<T>,
+    U: Rng,
+    F: Fn(&S) -> T + ?Sized,
+{
+    assert!(!moves.is_empty());
+    let total = moves.iter().fold(T::zero(), |s, m| s + m.weight);
+    let r = rng.sample(Uniform::new(T::zero(), total));
+    let mut cum = T::zero();
+    let move_id = moves
+        .iter()
+        .position(|m| {
+            cum = cum + m.weight;
+            r < cum
+        })
+        .unwrap_or(moves.len() - 1);
+    let mv = &moves[move_id];
+
+    let proposal = match (mv.propose)(state, rng) {
+        Some(p) => p,
+        None => return (move_id, false),
+    };
+    let lp_new = flogprob(&proposal.state);
+    let log_alpha = lp_new - *lp
+        + (moves[mv.reverse].weight / mv.weight).ln()
+        + proposal.log_proposal_ratio
+        + proposal.log_jacobian;
+    if !log_alpha.is_nan() && rng.sample(Uniform::new(T::zero(), T::one())).ln() < log_alpha {
+        *state = proposal.state;
+        *lp = lp_new;
+        (move_id, true)
+    } else {
+        (move_id, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use rand_distr::StandardNormal;
+
+    #[test]
+    fn nested_gaussian_test() {
+        //model 1: pi(x) = exp(-x^2/2), Z1 = sqrt(2 pi)
+        //model 2: pi(x, y) = exp(-x^2/2-y^2/(2 s^2)), Z2 = 2 pi s
+        //the model index is the length of the state
+        let s = 3.0;
+        let flogprob = |x: &Vec<f64>| match x.len() {
+            1 => -x[0] * x[0] / 2.0,
+            2 => -x[0] * x[0] / 2.0 - x[1] * x[1] / (2.0 * s * s),
+            _ => f64::NEG_INFINITY,
+        };
+        //the new coordinate is drawn from N(0, sb^2) by the birth move
+        let sb = 2.0;
+        let log_g =
+            |u: f64| -u * u / (2.0 * sb * sb) - (sb * (2.0 * std::f64::consts::PI).sqrt()).ln();
+
+        let moves: Vec<RjMove<f64, Vec<f64>, StdRng>> = vec![
+            RjMove::new("walk", 2.0, 0, |x: &Vec<f64>, rng: &mut StdRng| {
+                let mut y = x.clone();
+                let i = rng.gen_range(0..y.len());
+                y[i] += rng.sample::<f64, _>(StandardNormal);
+                Some(JumpProposal::symmetric(y))
+            }),
+            RjMove::new("birth", 1.0, 2, |x: &Vec<f64>, rng: &mut StdRng| {
+                if x.len() == 2 {
+                    return None;
+                }
+                let u = sb * rng.sample::<f64, _>(StandardNormal);
+                Some(JumpProposal {
+                    state: vec![x[0], u],
+                    log_proposal_ratio: -log_g(u),
+                    log_jacobian: 0.0,
+                })
+            }),
+            RjMove::new("death", 1.0, 1, |x: &Vec<f64>, _rng: &mut StdRng| {
+                if x.len() == 1 {
+                    return None;
+                }
+                Some(JumpProposal {
+                    state: vec![x[0]],
+                    log_proposal_ratio: log_g(x[1]),
+                    log_jacobian: 0.0,
+                })
+            }),
+        ];
+
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut x = vec![0.0];
+        let mut lp = flogprob(&x);
+        let mut trace = RjTrace::new(moves.len());
+        for _ in 0..200000 {
+            let (m, a) = sample(&flogprob, &moves, &mut x, &mut lp, &mut rng);
+            trace.record(m, a, x.len());
+        }
+        let p = trace.model_probabilities(1000);
+        let z1 = (2.0 * std::f64::consts::PI).sqrt();
+        let z2 = 2.0 * std::f64::consts::PI * s;
+        let p2 = z2 / (z1 + z2);
+        assert!((p[&2] - p2).abs() < 0.02, "{:?} {}", p, p2);
+        assert!((p[&1] + p[&2] - 1.0).abs() < 1e-10);
+        assert!(trace.accept_ratio(1).unwrap() > 0.0);
+    }
+}
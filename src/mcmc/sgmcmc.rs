@@ -0,0 +1,387 @@
+#![allow(clippy::too_many_arguments)]
+use std::ops::{Add, Mul, Sub};
+
+use num::traits::{float::Float, NumCast};
+use rand::{
+    distributions::{uniform::SampleUniform, Distribution, Standard},
+    seq::SliceRandom,
+    Rng,
+};
+use rand_distr::StandardNormal;
+
+use crate::linear_space::IndexableLinearSpace;
+
+///Step size as a function of the iteration number `t`
+#[derive(Clone, Copy, Debug)]
+pub enum StepSchedule<T> {
+    Constant(T),
+    ///`a*(b+t)^-gamma`, with `gamma` in (0.5, 1] for the convergence of SGLD
+    Polynomial {
+        a: T,
+        b: T,
+        gamma: T,
+    },
+}
+
+impl<T> StepSchedule<T>
+where
+    T: Float,
+{
+    pub fn at(&self, t: usize) -> T {
+        match *self {
+            StepSchedule::Constant(epsilon) => epsilon,
+            StepSchedule::Polynomial { a, b, gamma } => a * (b + T::from(t).unwrap()).powf(-gamma),
+        }
+    }
+}
+
+///Draws minibatches of data indices without replacement, reshuffling after every epoch
+pub struct Minibatch {
+    perm: Vec<usize>,
+    batch_size: usize,
+    pos: usize,
+}
+
+impl Minibatch {
+    pub fn new(ndata: usize, batch_size: usize) -> Minibatch {
+        assert!(batch_size > 0 && batch_size <= ndata);
+        Minibatch {
+            perm: (0..ndata).collect(),
+            batch_size,
+            pos: ndata,
+        }
+    }
+
+    pub fn next_batch<U>(&mut self, rng: &mut U) -> &[usize]
+    where
+        U: Rng,
+    {
+        if self.pos + self.batch_size > self.perm.len() {
+            self.perm.shuffle(rng);
+            self.pos = 0;
+        }
+        let result = &self.perm[self.pos..self.pos + self.batch_size];
+        self.pos += self.batch_size;
+        result
+    }
+}
+
+///RMSprop preconditioner of pSGLD (Li et al. 2016)
+pub struct RmsProp<T> {
+    pub alpha: T,
+    pub lambda: T,
+    v: Vec<T>,
+}
+
+impl<T> RmsProp<T>
+where
+    T: Float,
+{
+    pub fn new(alpha: T, lambda: T) -> RmsProp<T> {
+        RmsProp {
+            alpha,
+            lambda,
+            v: Vec::new(),
+        }
+    }
+
+    ///Update the moving average of the squared gradient and return the diagonal preconditioner
+    fn update<V>(&mut self, g: &V) -> Vec<T>
+    where
+        V: IndexableLinearSpace<T>,
+        for<'b> &'b V: Add<Output = V>,
+        for<'b> &'b V: Sub<Output = V>,
+        for<'b> &'b V: Mul<T, Output = V>,
+    {
+        let ndim = g.dimension();
+        if self.v.len() != ndim {
+            self.v = (0..ndim).map(|i| g[i] * g[i]).collect();
+        } else {
+            for i in 0..ndim {
+                self.v[i] = self.alpha * self.v[i] + (T::one() - self.alpha) * g[i] * g[i];
+            }
+        }
+        self.v
+            .iter()
+            .map(|&v| T::one() / (self.lambda + v.sqrt()))
+            .collect()
+    }
+}
+
+impl<T> std::default::Default for RmsProp<T>
+where
+    T: Float,
+{
+    fn default() -> Self {
+        RmsProp::new(T::from(0.99).unwrap(), T::from(1e-5).unwrap())
+    }
+}
+
+///Stochastic gradient Langevin dynamics (Welling & Teh 2011) step, preconditioned with RMSprop if
+///`precond` is given (pSGLD).
+///`grad` returns an unbiased estimate of the gradient of the log posterior from the data in
+///`batch`, e.g., the gradient of the log prior plus `N/batch.len()` times the sum of the
+///gradients of the log likelihoods of the data in `batch`.
+pub fn sgld<T, U, V, G>(
+    grad: &G,
+    x: &mut V,
+    batch: &[usize],
+    epsilon: T,
+    precond: Option<&mut RmsProp<T>>,
+    rng: &mut U,
+) where
+    T: Float + NumCast + SampleUniform + std::fmt::Debug,
+    Standard: Distribution<T>,
+    StandardNormal: Distribution<T>,
+    U: Rng,
+    V: Clone + IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    G: Fn(&V, &[usize]) -> V + ?Sized,
+{
+    let g = grad(x, batch);
+    let ndim = x.dimension();
+    let m = match precond {
+        Some(p) => p.update(&g),
+        None => vec![T::one(); ndim],
+    };
+    let half = T::from(0.5).unwrap();
+    for i in 0..ndim {
+        let noise: T = rng.sample(StandardNormal);
+        x[i] = x[i] + half * epsilon * m[i] * g[i] + (epsilon * m[i]).sqrt() * noise;
+    }
+}
+
+///Stochastic gradient Hamiltonian Monte Carlo (Chen et al. 2014) step, in the parametrization with
+///learning rate `eta` (the square of the step size) and momentum decay `alpha`.
+///`v` is the momentum, which should be kept between successive calls. See `sgld` for `grad`.
+pub fn sghmc<T, U, V, G>(
+    grad: &G,
+    x: &mut V,
+    v: &mut V,
+    batch: &[usize],
+    eta: T,
+    alpha: T,
+    rng: &mut U,
+) where
+    T: Float + NumCast + SampleUniform + std::fmt::Debug,
+    Standard: Distribution<T>,
+    StandardNormal: Distribution<T>,
+    U: Rng,
+    V: Clone + IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    G: Fn(&V, &[usize]) -> V + ?Sized,
+{
+    let g = grad(x, batch);
+    let two = T::one() + T::one();
+    let sigma = (two * alpha * eta).sqrt();
+    for i in 0..x.dimension() {
+        let noise: T = rng.sample(StandardNormal);
+        v[i] = (T::one() - alpha) * v[i] + eta * g[i] + sigma * noise;
+        x[i] = x[i] + v[i];
+    }
+}
+
+///Stochastic gradient Nosé-Hoover thermostat (Ding et al. 2014) step with step size `h` and
+///diffusion `a`. The momentum `p` and the thermostat `xi` should be kept between successive calls,
+///`xi` is usually initialized to `a`. See `sgld` for `grad`.
+pub fn sgnht<T, U, V, G>(
+    grad: &G,
+    x: &mut V,
+    p: &mut V,
+    xi: &mut T,
+    batch: &[usize],
+    h: T,
+    a: T,
+    rng: &mut U,
+) where
+    T: Float + NumCast + SampleUniform + std::fmt::Debug,
+    Standard: Distribution<T>,
+    StandardNormal: Distribution<T>,
+    U: Rng,
+    V: Clone + IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    G: Fn(&V, &[usize]) -> V + ?Sized,
+{
+    let g = grad(x, batch);
+    let ndim = x.dimension();
+    let two = T::one() + T::one();
+    let sigma = (two * a * h).sqrt();
+    for i in 0..ndim {
+        let noise: T = rng.sample(StandardNormal);
+        p[i] = p[i] - *xi * p[i] * h + g[i] * h + sigma * noise;
+        x[i] = x[i] + p[i] * h;
+    }
+    let p2 = (0..ndim).fold(T::zero(), |s, i| s + p[i] * p[i]);
+    *xi = *xi + (p2 / T::from(ndim).unwrap() - T::one()) * h;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_space::type_wrapper::LsVec;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type Vf = LsVec<f64, Vec<f64>>;
+
+    const NDATA: usize = 1000;
+    const PRIOR_VAR: f64 = 100.0;
+
+    struct Regression {
+        x: Vec<f64>,
+        y: Vec<f64>,
+        mean: [f64; 2],
+        cov: [f64; 3],
+    }
+
+    //y=1+2x+e, e~N(0,1), with prior N(0,PRIOR_VAR) on both coefficients
+    fn regression() -> Regression {
+        let mut rng = StdRng::seed_from_u64(1);
+        let x: Vec<f64> = (0..NDATA)
+            .map(|_| rng.sample::<f64, _>(StandardNormal))
+            .collect();
+        let y: Vec<f64> = x
+            .iter()
+            .map(|&x| 1.0 + 2.0 * x + rng.sample::<f64, _>(StandardNormal))
+            .collect();
+        let p00 = NDATA as f64 + 1.0 / PRIOR_VAR;
+        let p01 = x.iter().sum::<f64>();
+        let p11 = x.iter().map(|x| x * x).sum::<f64>() + 1.0 / PRIOR_VAR;
+        let b0 = y.iter().sum::<f64>();
+        let b1 = x.iter().zip(y.iter()).map(|(x, y)| x * y).sum::<f64>();
+        let det = p00 * p11 - p01 * p01;
+        let cov = [p11 / det, p00 / det, -p01 / det];
+        let mean = [cov[0] * b0 + cov[2] * b1, cov[2] * b0 + cov[1] * b1];
+        Regression { x, y, mean, cov }
+    }
+
+    fn grad(r: &Regression, p: &Vf, batch: &[usize]) -> Vf {
+        let scale = NDATA as f64 / batch.len() as f64;
+        let mut g = vec![-p[0] / PRIOR_VAR, -p[1] / PRIOR_VAR];
+        for &i in batch {
+            let e = r.y[i] - p[0] - p[1] * r.x[i];
+            g[0] += scale * e;
+            g[1] += scale * e * r.x[i];
+        }
+        LsVec(g)
+    }
+
+    fn check<S>(r: &Regression, mut step: S)
+    where
+        S: FnMut(&mut Vf, &[usize], &mut StdRng),
+    {
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut batches = Minibatch::new(NDATA, 100);
+        let mut p = LsVec(vec![0.0, 0.0]);
+        let (mut m, mut c) = ([0.0; 2], [0.0; 3]);
+        let nburn = 5000;
+        let n = 100000;
+        for k in 0..nburn + n {
+            let batch = batches.next_batch(&mut rng).to_vec();
+            step(&mut p, &batch, &mut rng);
+            if k >= nburn {
+                m[0] += p[0];
+                m[1] += p[1];
+                c[0] += p[0] * p[0];
+                c[1] += p[1] * p[1];
+                c[2] += p[0] * p[1];
+            }
+        }
+        let n = n as f64;
+        let m = [m[0] / n, m[1] / n];
+        let c = [
+            c[0] / n - m[0] * m[0],
+            c[1] / n - m[1] * m[1],
+            c[2] / n - m[0] * m[1],
+        ];
+        for i in 0..2 {
+            let sd = r.cov[i].sqrt();
+            assert!((m[i] - r.mean[i]).abs() < 0.3 * sd, "{:?} {:?}", m, r.mean);
+            assert!((c[i] / r.cov[i] - 1.0).abs() < 0.3, "{:?} {:?}", c, r.cov);
+        }
+    }
+
+    #[test]
+    fn sgld_test() {
+        let r = regression();
+        check(&r, |p, batch, rng| {
+            sgld(
+                &|p: &Vf, b: &[usize]| grad(&r, p, b),
+                p,
+                batch,
+                1e-4,
+                None,
+                rng,
+            )
+        });
+    }
+
+    #[test]
+    fn psgld_test() {
+        let r = regression();
+        let mut precond = RmsProp::default();
+        check(&r, |p, batch, rng| {
+            sgld(
+                &|p: &Vf, b: &[usize]| grad(&r, p, b),
+                p,
+                batch,
+                3e-3,
+                Some(&mut precond),
+                rng,
+            )
+        });
+    }
+
+    #[test]
+    fn sghmc_test() {
+        let r = regression();
+        let mut v = LsVec(vec![0.0, 0.0]);
+        check(&r, |p, batch, rng| {
+            sghmc(
+                &|p: &Vf, b: &[usize]| grad(&r, p, b),
+                p,
+                &mut v,
+                batch,
+                1e-5,
+                0.1,
+                rng,
+            )
+        });
+    }
+
+    #[test]
+    fn sgnht_test() {
+        let r = regression();
+        let mut mom = LsVec(vec![0.0, 0.0]);
+        let mut xi = 1.0;
+        check(&r, |p, batch, rng| {
+            sgnht(
+                &|p: &Vf, b: &[usize]| grad(&r, p, b),
+                p,
+                &mut mom,
+                &mut xi,
+                batch,
+                3e-3,
+                1.0,
+                rng,
+            )
+        });
+    }
+
+    #[test]
+    fn schedule_test() {
+        let s = StepSchedule::Polynomial {
+            a: 1.0,
+            b: 1.0,
+            gamma: 0.55,
+        };
+        assert_eq!(s.at(0), 1.0);
+        assert!(s.at(100) < s.at(10));
+        assert_eq!(StepSchedule::Constant(0.1).at(1000), 0.1);
+    }
+}
@@ -0,0 +1,474 @@
+#![allow(clippy::too_many_arguments)]
+#![allow(clippy::needless_range_loop)]
+use num::traits::{
+    float::Float,
+    identities::{one, zero},
+    NumCast,
+};
+use rand::{
+    distributions::{uniform::SampleUniform, Distribution, Standard, Uniform},
+    Rng,
+};
+use rand_distr::Exp1;
+use std::ops::{Add, Mul, Sub};
+
+use crate::linear_space::{IndexableLinearSpace, LinearSpace};
+
+///How the initial interval around the current point is enlarged
+#[derive(Clone, Copy, Debug)]
+pub enum SliceMethod {
+    ///Step out by `w` on each side, using at most `max_steps` steps in total
+    SteppingOut { max_steps: usize },
+    ///Double the interval at most `max_doublings` times
+    Doubling { max_doublings: usize },
+}
+
+impl std::default::Default for SliceMethod {
+    fn default() -> Self {
+        SliceMethod::SteppingOut { max_steps: 64 }
+    }
+}
+
+///The initial interval width of a univariate slice sampler.
+///When `adapt` is true, the width is set to twice the mean absolute jump observed so far,
+///which should be switched off after the burn-in period.
+#[derive(Clone, Copy, Debug)]
+pub struct SliceWidth<T>
+where
+    T: Float,
+{
+    pub w: T,
+    pub adapt: bool,
+    n: usize,
+    sum_jump: T,
+}
+
+impl<T> SliceWidth<T>
+where
+    T: Float,
+{
+    pub fn new(w: T) -> SliceWidth<T> {
+        SliceWidth {
+            w,
+            adapt: false,
+            n: 0,
+            sum_jump: zero(),
+        }
+    }
+
+    pub fn adaptive(w: T) -> SliceWidth<T> {
+        SliceWidth {
+            adapt: true,
+            ..Self::new(w)
+        }
+    }
+
+    pub fn freeze(&mut self) {
+        self.adapt = false;
+    }
+
+    pub fn update(&mut self, jump: T) {
+        if !self.adapt {
+            return;
+        }
+        self.n += 1;
+        self.sum_jump = self.sum_jump + jump.abs();
+        if self.sum_jump > zero() {
+            self.w = (one::<T>() + one::<T>()) * self.sum_jump / T::from(self.n).unwrap();
+        }
+    }
+}
+
+fn eval_in_range<T, F>(pd: &F, x: T, xrange: (T, T)) -> T
+where
+    T: Float,
+    F: Fn(T) -> T + ?Sized,
+{
+    if x < xrange.0 || x > xrange.1 {
+        T::neg_infinity()
+    } else {
+        pd(x)
+    }
+}
+
+fn doubling_acceptable<T, F>(pd: &F, x0: T, x1: T, y: T, w: T, xrange: (T, T), l: T, r: T) -> bool
+where
+    T: Float,
+    F: Fn(T) -> T + ?Sized,
+{
+    let two = one::<T>() + one::<T>();
+    let (mut l, mut r) = (l, r);
+    let mut differ = false;
+    while r - l > T::from(1.1).unwrap() * w {
+        let m = (l + r) / two;
+        if (x0 < m && x1 >= m) || (x0 >= m && x1 < m) {
+            differ = true;
+        }
+        if x1 < m {
+            r = m;
+        } else {
+            l = m;
+        }
+        if differ && y >= eval_in_range(pd, l, xrange) && y >= eval_in_range(pd, r, xrange) {
+            return false;
+        }
+    }
+    true
+}
+
+///One univariate slice sampling update (Neal 2003) of `x0`, whose log probability is `lp0`.
+///`pd` is the unnormalized log probability, which is treated as `-inf` outside `xrange`
+///(either end may be infinite).
+///Returns the new point and its log probability.
+pub fn sample_univariate<T, F, U>(
+    pd: &F,
+    x0: T,
+    lp0: T,
+    xrange: (T, T),
+    width: &mut SliceWidth<T>,
+    method: SliceMethod,
+    rng: &mut U,
+) -> (T, T)
+where
+    T: Float + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
+    Standard: Distribution<T>,
+    Exp1: Distribution<T>,
+    U: Rng,
+    F: Fn(T) -> T + ?Sized,
+{
+    assert!(
+        lp0.is_finite(),
+        "log probability of the current point must be finite"
+    );
+    let w = width.w;
+    let y = lp0 - rng.sample(Exp1);
+    let unit = Uniform::new(zero::<T>(), one::<T>());
+
+    let mut l = x0 - w * rng.sample(&unit);
+    let mut r = l + w;
+    match method {
+        SliceMethod::SteppingOut { max_steps } => {
+            let mut j = (T::from(max_steps).unwrap() * rng.sample(&unit))
+                .to_usize()
+                .unwrap();
+            let mut k = max_steps.saturating_sub(1).saturating_sub(j);
+            while j > 0 && y < eval_in_range(pd, l, xrange) {
+                l = l - w;
+                j -= 1;
+            }
+            while k > 0 && y < eval_in_range(pd, r, xrange) {
+                r = r + w;
+                k -= 1;
+            }
+        }
+        SliceMethod::Doubling { max_doublings } => {
+            let mut fl = eval_in_range(pd, l, xrange);
+            let mut fr = eval_in_range(pd, r, xrange);
+            let mut k = max_doublings;
+            while k > 0 && (y < fl || y < fr) {
+                if rng.sample(&unit) < T::from(0.5).unwrap() {
+                    l = l - (r - l);
+                    fl = eval_in_range(pd, l, xrange);
+                } else {
+                    r = r + (r - l);
+                    fr = eval_in_range(pd, r, xrange);
+                }
+                k -= 1;
+            }
+        }
+    }
+
+    let (l0, r0) = (l, r);
+    loop {
+        let x1 = l + rng.sample(&unit) * (r - l);
+        let lp1 = eval_in_range(pd, x1, xrange);
+        let accepted = y < lp1
+            && match method {
+                SliceMethod::SteppingOut { .. } => true,
+                SliceMethod::Doubling { .. } => {
+                    doubling_acceptable(pd, x0, x1, y, w, xrange, l0, r0)
+                }
+            };
+        if accepted {
+            width.update(x1 - x0);
+            return (x1, lp1);
+        }
+        if x1 < x0 {
+            l = x1;
+        } else {
+            r = x1;
+        }
+    }
+}
+
+///Slice sampling update of the `i`th coordinate of `x`, whose log probability is `lp`.
+pub fn sample_coordinate<T, U, V, F>(
+    flogprob: &F,
+    x: &mut V,
+    lp: &mut T,
+    i: usize,
+    xrange: (T, T),
+    width: &mut SliceWidth<T>,
+    method: SliceMethod,
+    rng: &mut U,
+) -> bool
+where
+    T: Float + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
+    Standard: Distribution<T>,
+    Exp1: Distribution<T>,
+    U: Rng,
+    V: Clone + IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    F: Fn(&V) -> T + ?Sized,
+{
+    let x0 = x[i];
+    let (x1, lp1) = {
+        let x = &*x;
+        let pd = |xi| {
+            let mut y = x.clone();
+            y[i] = xi;
+            flogprob(&y)
+        };
+        sample_univariate(&pd, x0, *lp, xrange, width, method, rng)
+    };
+    x[i] = x1;
+    *lp = lp1;
+    x1 != x0
+}
+
+///One systematic sweep of univariate slice sampling over all coordinates of `x`.
+///`bounds` gives the support of every coordinate and `widths` the interval width of each coordinate.
+///Returns the log probability of the updated `x`.
+pub fn sample<T, U, V, F>(
+    flogprob: &F,
+    x: &mut V,
+    bounds: &[(T, T)],
+    widths: &mut [SliceWidth<T>],
+    method: SliceMethod,
+    rng: &mut U,
+) -> T
+where
+    T: Float + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
+    Standard: Distribution<T>,
+    Exp1: Distribution<T>,
+    U: Rng,
+    V: Clone + IndexableLinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    F: Fn(&V) -> T + ?Sized,
+{
+    let ndim = x.dimension();
+    assert_eq!(bounds.len(), ndim);
+    assert_eq!(widths.len(), ndim);
+    let mut lp = flogprob(x);
+    for i in 0..ndim {
+        sample_coordinate(
+            flogprob,
+            x,
+            &mut lp,
+            i,
+            bounds[i],
+            &mut widths[i],
+            method,
+            rng,
+        );
+    }
+    lp
+}
+
+///Elliptical slice sampling (Murray, Adams & MacKay 2010) for a posterior proportional to
+///`exp(floglik(x))` times a Gaussian prior.
+///`prior_sample` draws from the prior, whose mean is `prior_mean` (zero if `None`).
+///`loglik` is the log likelihood of `x` and is updated together with it.
+///Returns the number of shrinking steps taken.
+pub fn elliptical_slice<T, U, V, F, P>(
+    floglik: &F,
+    x: &mut V,
+    loglik: &mut T,
+    prior_sample: &P,
+    prior_mean: Option<&V>,
+    rng: &mut U,
+) -> usize
+where
+    T: Float + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
+    Standard: Distribution<T>,
+    Exp1: Distribution<T>,
+    U: Rng,
+    V: Clone + LinearSpace<T>,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    F: Fn(&V) -> T + ?Sized,
+    P: Fn(&mut U) -> V + ?Sized,
+{
+    let two_pi = T::from(2.0 * std::f64::consts::PI).unwrap();
+    let (x0, nu) = match prior_mean {
+        Some(m) => (&*x - m, &prior_sample(rng) - m),
+        None => (x.clone(), prior_sample(rng)),
+    };
+    let y = *loglik - rng.sample(Exp1);
+    let mut theta = rng.sample(Uniform::new(zero::<T>(), two_pi));
+    let mut theta_min = theta - two_pi;
+    let mut theta_max = theta;
+    let mut nshrink = 0;
+    loop {
+        let mut x1 = &(&x0 * theta.cos()) + &(&nu * theta.sin());
+        if let Some(m) = prior_mean {
+            x1 = &x1 + m;
+        }
+        let l1 = floglik(&x1);
+        if l1 > y {
+            *x = x1;
+            *loglik = l1;
+            return nshrink;
+        }
+        if theta < zero() {
+            theta_min = theta;
+        } else {
+            theta_max = theta;
+        }
+        theta = rng.sample(Uniform::new(theta_min, theta_max));
+        nshrink += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_space::type_wrapper::LsVec;
+    use rand::{rngs::StdRng, SeedableRng};
+    use rand_distr::StandardNormal;
+
+    type Vf = LsVec<f64, Vec<f64>>;
+
+    fn moments(x: &[f64]) -> (f64, f64) {
+        let n = x.len() as f64;
+        let m = x.iter().sum::<f64>() / n;
+        let v = x.iter().map(|x| (x - m).powi(2)).sum::<f64>() / n;
+        (m, v)
+    }
+
+    fn draw<F>(pd: &F, xrange: (f64, f64), x0: f64, method: SliceMethod, n: usize) -> Vec<f64>
+    where
+        F: Fn(f64) -> f64,
+    {
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut width = SliceWidth::adaptive(0.1);
+        let (mut x, mut lp) = (x0, pd(x0));
+        let mut result = Vec::new();
+        for k in 0..n + 1000 {
+            if k == 1000 {
+                width.freeze();
+            }
+            let (x1, lp1) = sample_univariate(pd, x, lp, xrange, &mut width, method, &mut rng);
+            x = x1;
+            lp = lp1;
+            if k >= 1000 {
+                result.push(x);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn normal_test() {
+        let pd = |x: f64| -x * x / 2.0;
+        for &method in &[
+            SliceMethod::SteppingOut { max_steps: 32 },
+            SliceMethod::Doubling { max_doublings: 10 },
+        ] {
+            let x = draw(&pd, (f64::NEG_INFINITY, f64::INFINITY), 3.0, method, 40000);
+            let (m, v) = moments(&x);
+            assert!(m.abs() < 0.05, "{:?} {}", method, m);
+            assert!((v - 1.0).abs() < 0.05, "{:?} {}", method, v);
+        }
+    }
+
+    #[test]
+    fn bounded_exponential_test() {
+        let pd = |x: f64| -x;
+        for &method in &[
+            SliceMethod::SteppingOut { max_steps: 32 },
+            SliceMethod::Doubling { max_doublings: 10 },
+        ] {
+            let x = draw(&pd, (0.0, f64::INFINITY), 1.0, method, 40000);
+            assert!(x.iter().all(|&x| x >= 0.0));
+            let (m, v) = moments(&x);
+            assert!((m - 1.0).abs() < 0.05, "{:?} {}", method, m);
+            assert!((v - 1.0).abs() < 0.1, "{:?} {}", method, v);
+        }
+    }
+
+    #[test]
+    fn correlated_gaussian_test() {
+        let rho = 0.8;
+        let flogprob = |x: &Vf| {
+            -(x[0] * x[0] - 2.0 * rho * x[0] * x[1] + x[1] * x[1]) / (2.0 * (1.0 - rho * rho))
+        };
+        let mut rng = StdRng::seed_from_u64(12345);
+        let bounds = vec![(f64::NEG_INFINITY, f64::INFINITY); 2];
+        let mut widths = vec![SliceWidth::new(1.0); 2];
+        let mut x = LsVec(vec![0.0, 0.0]);
+        let (mut s0, mut s1, mut s01) = (0.0, 0.0, 0.0);
+        let n = 40000;
+        for _ in 0..n {
+            sample(
+                &flogprob,
+                &mut x,
+                &bounds,
+                &mut widths,
+                SliceMethod::default(),
+                &mut rng,
+            );
+            s0 += x[0] * x[0];
+            s1 += x[1] * x[1];
+            s01 += x[0] * x[1];
+        }
+        let n = n as f64;
+        assert!((s0 / n - 1.0).abs() < 0.1, "{}", s0 / n);
+        assert!((s1 / n - 1.0).abs() < 0.1, "{}", s1 / n);
+        assert!((s01 / n - rho).abs() < 0.1, "{}", s01 / n);
+    }
+
+    #[test]
+    fn elliptical_slice_test() {
+        //prior N(1, 4) in each dimension, likelihood N(y=3 | x, 1)
+        //posterior N(2.6, 0.8)
+        let floglik = |x: &Vf| -x.iter().map(|x| (x - 3.0).powi(2)).sum::<f64>() / 2.0;
+        let mean = LsVec(vec![1.0, 1.0]);
+        let prior_sample = |rng: &mut StdRng| {
+            LsVec(
+                (0..2)
+                    .map(|_| 1.0 + 2.0 * rng.sample::<f64, _>(StandardNormal))
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let mut rng = StdRng::seed_from_u64(12345);
+        let mut x = LsVec(vec![0.0, 0.0]);
+        let mut ll = floglik(&x);
+        let mut x0 = Vec::new();
+        let mut x1 = Vec::new();
+        for k in 0..41000 {
+            elliptical_slice(
+                &floglik,
+                &mut x,
+                &mut ll,
+                &prior_sample,
+                Some(&mean),
+                &mut rng,
+            );
+            if k >= 1000 {
+                x0.push(x[0]);
+                x1.push(x[1]);
+            }
+        }
+        for x in &[x0, x1] {
+            let (m, v) = moments(x);
+            assert!((m - 2.6).abs() < 0.05, "{}", m);
+            assert!((v - 0.8).abs() < 0.05, "{}", v);
+        }
+    }
+}
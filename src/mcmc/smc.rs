@@ -0,0 +1,481 @@
+#![allow(clippy::too_many_arguments)]
+#![allow(clippy::type_complexity)]
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use num::traits::{float::Float, NumCast};
+
+use rand::{
+    distributions::{uniform::SampleUniform, Distribution, Standard, Uniform},
+    Rng,
+};
+use rand_distr::StandardNormal;
+
+use std::ops::{Add, Mul, Sub};
+
+use super::ensemble_sample::{sample as stretch_sample, UpdateFlagSpec};
+use crate::linear_space::utils::{cholesky, cov};
+use crate::linear_space::IndexableLinearSpace;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Resampling {
+    Systematic,
+    Residual,
+}
+
+///The MCMC kernel used to rejuvenate the particles after each tempering step
+#[derive(Clone, Copy, Debug)]
+pub enum SmcKernel<T> {
+    ///Affine invariant stretch move of `ensemble_sample` with parameter `a`, which pairs the
+    ///particles and so requires an even number of them
+    Stretch { a: T },
+    ///Random-walk Metropolis with the covariance of the particles,
+    ///scaled by `2.38^2/d` and adapted according to the acceptance rate
+    RandomWalk,
+}
+
+pub struct SmcParams<T> {
+    ///The next temperature is chosen so that the conditional ESS equals this fraction of the
+    ///number of particles
+    pub cess_target: T,
+    ///Resample when the ESS drops below this fraction of the number of particles
+    pub ess_threshold: T,
+    pub resampling: Resampling,
+    pub kernel: SmcKernel<T>,
+    ///Number of MCMC sweeps after each tempering step
+    pub nmove: usize,
+    pub max_stages: usize,
+}
+
+impl<T> Default for SmcParams<T>
+where
+    T: Float,
+{
+    fn default() -> Self {
+        SmcParams {
+            cess_target: T::from(0.9).unwrap(),
+            ess_threshold: T::from(0.5).unwrap(),
+            resampling: Resampling::Systematic,
+            kernel: SmcKernel::RandomWalk,
+            nmove: 10,
+            max_stages: 1000,
+        }
+    }
+}
+
+pub struct SmcResult<T, V> {
+    pub particles: Vec<V>,
+    ///Normalized weights of `particles`
+    pub weights: Vec<T>,
+    pub log_evidence: T,
+    ///The sequence of inverse temperatures, starting with 0 and ending with 1
+    pub betas: Vec<T>,
+    ///Acceptance ratio of the MCMC moves at every stage
+    pub accept_ratios: Vec<T>,
+}
+
+fn log_sum_exp<T>(x: &[T]) -> T
+where
+    T: Float,
+{
+    let m = x.iter().fold(T::neg_infinity(), |a, &b| a.max(b));
+    if m == T::neg_infinity() {
+        m
+    } else {
+        m + x.iter().fold(T::zero(), |s, &x| s + (x - m).exp()).ln()
+    }
+}
+
+fn systematic_indices<T, U>(weights: &[T], m: usize, rng: &mut U) -> Vec<usize>
+where
+    T: Float + SampleUniform,
+    U: Rng,
+{
+    let n = weights.len();
+    let mm = T::from(m).unwrap();
+    let u0 = rng.sample(Uniform::new(T::zero(), T::one()));
+    let mut result = Vec::with_capacity(m);
+    let mut cum = T::zero();
+    for (i, &w) in weights.iter().enumerate() {
+        cum = cum + w * mm;
+        while result.len() < m && T::from(result.len()).unwrap() + u0 < cum {
+            result.push(i);
+        }
+    }
+    while result.len() < m {
+        result.push(n - 1);
+    }
+    result
+}
+
+///Systematic resampling, returning the indices of the selected particles
+pub fn systematic_resample<T, U>(weights: &[T], rng: &mut U) -> Vec<usize>
+where
+    T: Float + SampleUniform,
+    U: Rng,
+{
+    systematic_indices(weights, weights.len(), rng)
+}
+
+///Residual resampling, the residual part is resampled systematically
+pub fn residual_resample<T, U>(weights: &[T], rng: &mut U) -> Vec<usize>
+where
+    T: Float + SampleUniform,
+    U: Rng,
+{
+    let n = weights.len();
+    let nn = T::from(n).unwrap();
+    let mut result = Vec::with_capacity(n);
+    let mut residual = Vec::with_capacity(n);
+    for (i, &w) in weights.iter().enumerate() {
+        let nw = w * nn;
+        let k = nw.floor();
+        for _ in 0..k.to_usize().unwrap() {
+            result.push(i);
+        }
+        residual.push(nw - k);
+    }
+    let nres = n - result.len();
+    if nres > 0 {
+        let total = residual.iter().fold(T::zero(), |a, &b| a + b);
+        let residual: Vec<T> = residual.into_iter().map(|r| r / total).collect();
+        result.extend(systematic_indices(&residual, nres, rng));
+        result.sort_unstable();
+    }
+    result
+}
+
+///Conditional ESS of the increment `delta` of the inverse temperature, as a fraction of the
+///number of particles
+fn cess<T>(weights: &[T], logl: &[T], delta: T) -> T
+where
+    T: Float,
+{
+    let lw: Vec<T> = logl.iter().map(|&l| delta * l).collect();
+    let m = lw.iter().fold(T::neg_infinity(), |a, &b| a.max(b));
+    let (s1, s2) =
+        weights
+            .iter()
+            .zip(lw.iter())
+            .fold((T::zero(), T::zero()), |(s1, s2), (&w, &l)| {
+                let x = (l - m).exp();
+                (s1 + w * x, s2 + w * x * x)
+            });
+    s1 * s1 / s2
+}
+
+fn next_delta<T>(weights: &[T], logl: &[T], max_delta: T, target: T) -> T
+where
+    T: Float,
+{
+    if cess(weights, logl, max_delta) >= target {
+        return max_delta;
+    }
+    let two = T::one() + T::one();
+    let (mut lo, mut hi) = (T::zero(), max_delta);
+    for _ in 0..60 {
+        let mid = (lo + hi) / two;
+        if cess(weights, logl, mid) >= target {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    lo.max(T::epsilon() * max_delta)
+}
+
+fn random_walk_sweep<T, U, V, F>(
+    flogprob: &F,
+    particles: &mut [V],
+    logl: &mut [T],
+    logprior: &mut [T],
+    beta: T,
+    chol: &[Vec<T>],
+    scale: T,
+    rng: &mut U,
+) -> usize
+where
+    T: Float + NumCast + std::cmp::PartialOrd + SampleUniform + Sync + Send + std::fmt::Debug,
+    Standard: Distribution<T>,
+    StandardNormal: Distribution<T>,
+    U: Rng,
+    V: Clone + IndexableLinearSpace<T> + Sync + Send,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    F: Fn(&V) -> (T, T) + Send + Sync + ?Sized,
+{
+    let ndim = chol.len();
+    let proposed: Vec<V> = particles
+        .iter()
+        .map(|x| {
+            let u: Vec<T> = (0..ndim).map(|_| rng.sample(StandardNormal)).collect();
+            let mut y = x.clone();
+            for i in 0..ndim {
+                y[i] = x[i] + scale * (0..=i).fold(T::zero(), |s, k| s + chol[i][k] * u[k]);
+            }
+            y
+        })
+        .collect();
+    let new_lp: Vec<_> = proposed.par_iter().map(flogprob).collect();
+    let mut naccepted = 0;
+    for (((x, y), (lpr, ll)), (lpr1, ll1)) in particles
+        .iter_mut()
+        .zip(proposed)
+        .zip(logprior.iter_mut().zip(logl.iter_mut()))
+        .zip(new_lp)
+    {
+        let delta = lpr1 + beta * ll1 - (*lpr + beta * *ll);
+        if !delta.is_nan() && rng.sample(Uniform::new(T::zero(), T::one())).ln() < delta {
+            *x = y;
+            *lpr = lpr1;
+            *ll = ll1;
+            naccepted += 1;
+        }
+    }
+    naccepted
+}
+
+///Sequential Monte Carlo with adaptive tempering from the prior (`beta=0`) to the posterior (`beta=1`).
+///`particles` should be drawn from the prior, whose log density is `flogprior`;
+///`flogl` is the log likelihood.
+#[allow(clippy::manual_is_multiple_of)]
+pub fn sample<T, U, V, F, G>(
+    flogprior: &F,
+    flogl: &G,
+    particles: Vec<V>,
+    params: &SmcParams<T>,
+    rng: &mut U,
+) -> SmcResult<T, V>
+where
+    T: Float
+        + NumCast
+        + std::cmp::PartialOrd
+        + SampleUniform
+        + Sync
+        + Send
+        + std::fmt::Display
+        + std::fmt::Debug,
+    Standard: Distribution<T>,
+    StandardNormal: Distribution<T>,
+    U: Rng,
+    V: Clone + IndexableLinearSpace<T> + Sync + Send,
+    for<'b> &'b V: Add<Output = V>,
+    for<'b> &'b V: Sub<Output = V>,
+    for<'b> &'b V: Mul<T, Output = V>,
+    F: Fn(&V) -> T + Send + Sync + ?Sized,
+    G: Fn(&V) -> T + Send + Sync + ?Sized,
+{
+    let n = particles.len();
+    assert!(n > 1);
+    if let SmcKernel::Stretch { .. } = params.kernel {
+        assert!(
+            n % 2 == 0,
+            "the stretch kernel needs an even number of particles"
+        );
+    }
+    let nn = T::from(n).unwrap();
+    let ndim = particles[0].dimension();
+    let eval = |x: &V| {
+        let lpr = flogprior(x);
+        let ll = if lpr == T::neg_infinity() {
+            T::neg_infinity()
+        } else {
+            flogl(x)
+        };
+        (lpr, if ll.is_nan() { T::neg_infinity() } else { ll })
+    };
+
+    let mut particles = particles;
+    let (mut logprior, mut logl): (Vec<T>, Vec<T>) = particles.par_iter().map(eval).unzip();
+    let mut weights = vec![T::one() / nn; n];
+    let mut beta = T::zero();
+    let mut log_evidence = T::zero();
+    let mut betas = vec![beta];
+    let mut accept_ratios = Vec::new();
+    let mut rw_scale = T::from(2.38).unwrap() / T::from(ndim).unwrap().sqrt();
+
+    while beta < T::one() && betas.len() <= params.max_stages {
+        let delta = next_delta(&weights, &logl, T::one() - beta, params.cess_target);
+        let lw: Vec<T> = weights
+            .iter()
+            .zip(logl.iter())
+            .map(|(&w, &l)| w.ln() + delta * l)
+            .collect();
+        let lz = log_sum_exp(&lw);
+        log_evidence = log_evidence + lz;
+        weights = lw.into_iter().map(|l| (l - lz).exp()).collect();
+        beta = if delta >= T::one() - beta {
+            T::one()
+        } else {
+            beta + delta
+        };
+        betas.push(beta);
+
+        let ess = T::one() / weights.iter().fold(T::zero(), |s, &w| s + w * w);
+        if ess < params.ess_threshold * nn {
+            let idx = match params.resampling {
+                Resampling::Systematic => systematic_resample(&weights, rng),
+                Resampling::Residual => residual_resample(&weights, rng),
+            };
+            particles = idx.iter().map(|&i| particles[i].clone()).collect();
+            logprior = idx.iter().map(|&i| logprior[i]).collect();
+            logl = idx.iter().map(|&i| logl[i]).collect();
+            weights = vec![T::one() / nn; n];
+        }
+
+        let mut naccepted = 0;
+        match params.kernel {
+            SmcKernel::Stretch { a } => {
+                let ftempered = |x: &V| {
+                    let (lpr, ll) = eval(x);
+                    lpr + beta * ll
+                };
+                let mut lp: Vec<T> = logprior
+                    .iter()
+                    .zip(logl.iter())
+                    .map(|(&lpr, &ll)| lpr + beta * ll)
+                    .collect();
+                for _ in 0..params.nmove {
+                    let old_lp = lp.clone();
+                    stretch_sample(
+                        &ftempered,
+                        &mut particles,
+                        &mut lp,
+                        rng,
+                        a,
+                        &mut UpdateFlagSpec::All,
+                    );
+                    naccepted += old_lp.iter().zip(lp.iter()).filter(|(a, b)| a != b).count();
+                }
+                let (lpr, ll): (Vec<T>, Vec<T>) = particles.par_iter().map(eval).unzip();
+                logprior = lpr;
+                logl = ll;
+            }
+            SmcKernel::RandomWalk => {
+                let mut c = vec![vec![T::zero(); ndim]; ndim];
+                cov(&particles, &mut |i, j, x| c[i][j] = x);
+                for (i, row) in c.iter_mut().enumerate() {
+                    row[i] = row[i] + T::epsilon().sqrt() * (T::one() + row[i].abs());
+                }
+                let chol =
+                    cholesky(&c).expect("covariance of the particles is not positive definite");
+                for _ in 0..params.nmove {
+                    let nacc = random_walk_sweep(
+                        &eval,
+                        &mut particles,
+                        &mut logl,
+                        &mut logprior,
+                        beta,
+                        &chol,
+                        rw_scale,
+                        rng,
+                    );
+                    let acc = T::from(nacc).unwrap() / nn;
+                    rw_scale = rw_scale * (acc - T::from(0.234).unwrap()).exp();
+                    naccepted += nacc;
+                }
+            }
+        }
+        accept_ratios.push(T::from(naccepted).unwrap() / T::from(n * params.nmove.max(1)).unwrap());
+    }
+
+    SmcResult {
+        particles,
+        weights,
+        log_evidence,
+        betas,
+        accept_ratios,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_space::type_wrapper::LsVec;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type Vf = LsVec<f64, Vec<f64>>;
+
+    #[test]
+    fn resample_test() {
+        let mut rng = StdRng::seed_from_u64(12345);
+        let w = vec![0.1, 0.25, 0.05, 0.6];
+        for idx in &[
+            systematic_resample(&w, &mut rng),
+            residual_resample(&w, &mut rng),
+        ] {
+            assert_eq!(idx.len(), 4);
+            assert!(idx.windows(2).all(|x| x[0] <= x[1]));
+            assert!(idx.iter().filter(|&&i| i == 3).count() >= 2);
+        }
+    }
+
+    fn bimodal(kernel: SmcKernel<f64>) {
+        //prior N(0, sp^2 I), likelihood an equal mixture of N(+-mu, sl^2 I)
+        let (sp, sl, mu) = (5.0, 0.5, 3.0);
+        let two_pi = 2.0 * std::f64::consts::PI;
+        let flogprior =
+            |x: &Vf| -(x[0] * x[0] + x[1] * x[1]) / (2.0 * sp * sp) - (two_pi * sp * sp).ln();
+        let flogl = |x: &Vf| {
+            let l1 = -((x[0] - mu).powi(2) + (x[1] - mu).powi(2)) / (2.0 * sl * sl);
+            let l2 = -((x[0] + mu).powi(2) + (x[1] + mu).powi(2)) / (2.0 * sl * sl);
+            let m = l1.max(l2);
+            m + ((l1 - m).exp() + (l2 - m).exp()).ln() - (2.0 * two_pi * sl * sl).ln()
+        };
+        let s2 = sp * sp + sl * sl;
+        let logz_expected = -(2.0 * mu * mu) / (2.0 * s2) - (two_pi * s2).ln();
+
+        let mut rng = StdRng::seed_from_u64(12345);
+        let particles: Vec<Vf> = (0..2000)
+            .map(|_| {
+                LsVec(
+                    (0..2)
+                        .map(|_| sp * rng.sample::<f64, _>(StandardNormal))
+                        .collect(),
+                )
+            })
+            .collect();
+        let params = SmcParams {
+            kernel,
+            ..SmcParams::default()
+        };
+        let result = sample(&flogprior, &flogl, particles, &params, &mut rng);
+        assert_eq!(*result.betas.last().unwrap(), 1.0);
+        assert!(
+            (result.log_evidence - logz_expected).abs() < 0.1,
+            "{} {}",
+            result.log_evidence,
+            logz_expected
+        );
+        let w_pos = result
+            .particles
+            .iter()
+            .zip(result.weights.iter())
+            .filter(|(x, _)| x[0] > 0.0)
+            .map(|(_, w)| w)
+            .sum::<f64>();
+        assert!((w_pos - 0.5).abs() < 0.1, "{}", w_pos);
+    }
+
+    #[test]
+    fn random_walk_test() {
+        bimodal(SmcKernel::RandomWalk);
+    }
+
+    #[test]
+    fn stretch_test() {
+        bimodal(SmcKernel::Stretch { a: 2.0 });
+    }
+
+    #[test]
+    #[should_panic(expected = "even number of particles")]
+    fn stretch_odd_test() {
+        let particles: Vec<Vf> = (0..5).map(|i| LsVec(vec![i as f64])).collect();
+        let params = SmcParams {
+            kernel: SmcKernel::Stretch { a: 2.0 },
+            ..SmcParams::default()
+        };
+        let f = |x: &Vf| -x[0] * x[0];
+        sample(&f, &f, particles, &params, &mut StdRng::seed_from_u64(1));
+    }
+}
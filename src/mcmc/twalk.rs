@@ -72,7 +72,7 @@ impl TWalkKernal {
     }
 }
 
-pub struct TWalkParams<T>
+pub struct TWalkParams<T, V>
 where
     T: Float + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
 {
@@ -80,9 +80,12 @@ where
     pub at: T,
     pub pphi: T,
     pub fw: [T; 2],
+    ///Optional support predicate, proposals outside of the support are rejected
+    ///without evaluating the log-probability
+    pub supp: Option<Box<dyn Fn(&V) -> bool + Send + Sync>>,
 }
 
-impl<T> TWalkParams<T>
+impl<T, V> TWalkParams<T, V>
 where
     T: Float + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
 {
@@ -106,6 +109,7 @@ where
             at: T::from(6.0).unwrap(),
             pphi,
             fw,
+            supp: None,
         }
     }
 
@@ -126,6 +130,77 @@ where
     pub fn with_pphi(self, pphi: T) -> Self {
         TWalkParams { pphi, ..self }
     }
+
+    pub fn with_supp<S>(self, supp: S) -> Self
+    where
+        S: Fn(&V) -> bool + Send + Sync + 'static,
+    {
+        TWalkParams {
+            supp: Some(Box::new(supp)),
+            ..self
+        }
+    }
+
+    pub fn in_supp(&self, x: &V) -> bool {
+        match self.supp {
+            Some(ref supp) => supp(x),
+            None => true,
+        }
+    }
+}
+
+///Number of proposals and acceptances of each kernel, indexed by `TWalkKernal::to_usize`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TWalkStat {
+    pub proposed: [usize; 4],
+    pub accepted: [usize; 4],
+}
+
+impl TWalkStat {
+    pub fn new() -> TWalkStat {
+        TWalkStat::default()
+    }
+
+    pub fn record(&mut self, kernel: TWalkKernal, accepted: bool) {
+        self.proposed[kernel.to_usize()] += 1;
+        if accepted {
+            self.accepted[kernel.to_usize()] += 1;
+        }
+    }
+
+    pub fn num_proposed(&self, kernel: TWalkKernal) -> usize {
+        self.proposed[kernel.to_usize()]
+    }
+
+    pub fn num_accepted(&self, kernel: TWalkKernal) -> usize {
+        self.accepted[kernel.to_usize()]
+    }
+
+    pub fn accept_ratio(&self, kernel: TWalkKernal) -> Option<f64> {
+        match self.num_proposed(kernel) {
+            0 => None,
+            n => Some(self.num_accepted(kernel) as f64 / n as f64),
+        }
+    }
+
+    pub fn total_accept_ratio(&self) -> Option<f64> {
+        let n: usize = self.proposed.iter().sum();
+        let m: usize = self.accepted.iter().sum();
+        if n == 0 {
+            None
+        } else {
+            Some(m as f64 / n as f64)
+        }
+    }
+}
+
+impl std::ops::AddAssign for TWalkStat {
+    fn add_assign(&mut self, rhs: TWalkStat) {
+        for i in 0..4 {
+            self.proposed[i] += rhs.proposed[i];
+            self.accepted[i] += rhs.accepted[i];
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -271,7 +346,7 @@ where
     }
 }
 
-pub fn sim_walk<T, U, V>(x: &V, xp: &V, rng: &mut U, param: &TWalkParams<T>) -> (V, Vec<bool>)
+pub fn sim_walk<T, U, V>(x: &V, xp: &V, rng: &mut U, param: &TWalkParams<T, V>) -> (V, Vec<bool>)
 where
     T: Float + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
     Standard: Distribution<T>,
@@ -303,7 +378,7 @@ where
     (result, update_flags)
 }
 
-pub fn sim_b<T, U>(rng: &mut U, param: &TWalkParams<T>) -> T
+pub fn sim_b<T, U, V>(rng: &mut U, param: &TWalkParams<T, V>) -> T
 where
     T: Float + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
     Standard: Distribution<T>,
@@ -326,7 +401,7 @@ pub fn sim_traverse<T, U, V>(
     xp: &V,
     b: T,
     rng: &mut U,
-    param: &TWalkParams<T>,
+    param: &TWalkParams<T, V>,
 ) -> (V, Vec<bool>)
 where
     T: Float + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
@@ -352,7 +427,7 @@ where
     (result, update_flags)
 }
 
-pub fn sim_blow<T, U, V>(x: &V, xp: &V, rng: &mut U, param: &TWalkParams<T>) -> (V, Vec<bool>)
+pub fn sim_blow<T, U, V>(x: &V, xp: &V, rng: &mut U, param: &TWalkParams<T, V>) -> (V, Vec<bool>)
 where
     T: Float + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
     Standard: Distribution<T>,
@@ -413,7 +488,7 @@ where
     }
 }
 
-pub fn sim_hop<T, U, V>(x: &V, xp: &V, rng: &mut U, param: &TWalkParams<T>) -> (V, Vec<bool>)
+pub fn sim_hop<T, U, V>(x: &V, xp: &V, rng: &mut U, param: &TWalkParams<T, V>) -> (V, Vec<bool>)
 where
     T: Float + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
     Standard: Distribution<T>,
@@ -506,7 +581,7 @@ pub fn propose_move<T, V, U>(
     x: &V,
     xp: &V,
     rng: &mut U,
-    param: &TWalkParams<T>,
+    param: &TWalkParams<T, V>,
 ) -> (V, Vec<bool>, TWalkKernal, Option<T>)
 where
     T: Float + FloatConst + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
@@ -534,9 +609,10 @@ where
 pub fn sample_st<T, U, V, F>(
     flogprob: &F,
     state: &mut TWalkState<T, V>,
-    param: &TWalkParams<T>,
+    param: &TWalkParams<T, V>,
     rng: &mut U,
-) where
+) -> TWalkStat
+where
     T: Float + FloatConst + NumCast + std::cmp::PartialOrd + SampleUniform + std::fmt::Debug,
     Standard: Distribution<T>,
     StandardNormal: Distribution<T>,
@@ -551,46 +627,64 @@ pub fn sample_st<T, U, V, F>(
     let (yp1, phi1, kernel1, b1) = propose_move(&state.xp, &state.x, rng, param);
     let (yp2, phi2, kernel2, b2) = propose_move(&state.x, &state.xp, rng, param);
 
-    let up_prop1 = flogprob(&yp1);
-    let up_prop2 = flogprob(&yp2);
-    let a1 = calc_a(
-        &state.x,
-        (&state.xp, state.up),
-        (&yp1, up_prop1),
-        &phi1,
-        kernel1,
-        b1,
-        T::one(),
-    );
-    let a2 = calc_a(
-        &state.xp,
-        (&state.x, state.u),
-        (&yp2, up_prop2),
-        &phi2,
-        kernel2,
-        b2,
-        T::one(),
-    );
+    let (up_prop1, a1) = if param.in_supp(&yp1) {
+        let up_prop1 = flogprob(&yp1);
+        let a1 = calc_a(
+            &state.x,
+            (&state.xp, state.up),
+            (&yp1, up_prop1),
+            &phi1,
+            kernel1,
+            b1,
+            T::one(),
+        );
+        (up_prop1, a1)
+    } else {
+        (T::neg_infinity(), T::zero())
+    };
+
+    let (up_prop2, a2) = if param.in_supp(&yp2) {
+        let up_prop2 = flogprob(&yp2);
+        let a2 = calc_a(
+            &state.xp,
+            (&state.x, state.u),
+            (&yp2, up_prop2),
+            &phi2,
+            kernel2,
+            b2,
+            T::one(),
+        );
+        (up_prop2, a2)
+    } else {
+        (T::neg_infinity(), T::zero())
+    };
 
-    if rng.sample(uniform) < a1 {
+    let mut stat = TWalkStat::new();
+    let accepted1 = rng.sample(&uniform) < a1;
+    if accepted1 {
         state.xp = yp1;
         state.up = up_prop1;
     }
+    stat.record(kernel1, accepted1);
 
-    if rng.sample(Uniform::new(T::zero(), T::one())) < a2 {
+    let accepted2 = rng.sample(&uniform) < a2;
+    if accepted2 {
         state.x = yp2;
         state.u = up_prop2;
     }
+    stat.record(kernel2, accepted2);
+    stat
 }
 
 pub fn sample<T, U, V, W, X, F>(
     flogprob: &F,
     ensemble_logprob: &mut (W, X),
-    param: &TWalkParams<T>,
+    param: &TWalkParams<T, V>,
     rng: &mut U,
     beta_list: &[T],
     nthreads: usize,
-) where
+) -> TWalkStat
+where
     T: Float
         + FloatConst
         + NumCast
@@ -626,7 +720,7 @@ pub fn sample<T, U, V, W, X, F>(
         .map(|pid1| pid1.iter().map(|(i1, i2)| (*i2, *i1)).collect())
         .collect();
 
-    sample1(
+    let mut stat = sample1(
         flogprob,
         ensemble_logprob,
         param,
@@ -636,7 +730,7 @@ pub fn sample<T, U, V, W, X, F>(
         nthreads,
     );
 
-    sample1(
+    stat += sample1(
         flogprob,
         ensemble_logprob,
         param,
@@ -645,17 +739,19 @@ pub fn sample<T, U, V, W, X, F>(
         pair_id2,
         nthreads,
     );
+    stat
 }
 
 pub fn sample1<T, U, V, W, X, F>(
     flogprob: &F,
     ensemble_logprob: &mut (W, X),
-    param: &TWalkParams<T>,
+    param: &TWalkParams<T, V>,
     rng: &mut U,
     beta_list: &[T],
     pair_id: Vec<Vec<(usize, usize)>>,
     nthreads: usize,
-) where
+) -> TWalkStat
+where
     T: Float
         + FloatConst
         + NumCast
@@ -691,6 +787,11 @@ pub fn sample1<T, U, V, W, X, F>(
         })
         .collect();
 
+    let in_supp: Vec<Vec<_>> = proposed_points
+        .iter()
+        .map(|pp1| pp1.iter().map(|pp| param.in_supp(&pp.0)).collect())
+        .collect();
+
     let logprobs = Mutex::new(vec![vec![T::zero(); nwalkers_per_beta / 2]; nbetas]);
     let atomic_k = Mutex::new(0);
 
@@ -698,6 +799,7 @@ pub fn sample1<T, U, V, W, X, F>(
         let atomic_k = &atomic_k;
         let logprobs = &logprobs;
         let proposed_points = &proposed_points;
+        let in_supp = &in_supp;
         move || loop {
             let k: usize;
             {
@@ -711,7 +813,11 @@ pub fn sample1<T, U, V, W, X, F>(
             let ibeta = 2 * k / nwalkers_per_beta;
             let jbeta = k - ibeta * nwalkers_per_beta / 2;
             //println!("{} {} {} {}",k, ibeta, jbeta, nwalkers_per_beta);
-            let lp = flogprob(&proposed_points[ibeta][jbeta].0);
+            let lp = if in_supp[ibeta][jbeta] {
+                flogprob(&proposed_points[ibeta][jbeta].0)
+            } else {
+                T::neg_infinity()
+            };
             {
                 let mut lps = logprobs.lock().unwrap();
                 //println!("{} {} {} {}",k, ibeta, jbeta, nwalkers_per_beta);
@@ -733,30 +839,142 @@ pub fn sample1<T, U, V, W, X, F>(
 
     let logprobs = logprobs.into_inner().unwrap();
 
-    for (ibeta, (pair_id1, (logprobs1, proposed_points1))) in pair_id
+    let mut stat = TWalkStat::new();
+    for (ibeta, (pair_id1, (logprobs1, (proposed_points1, in_supp1)))) in pair_id
         .into_iter()
-        .zip(logprobs.into_iter().zip(proposed_points.into_iter()))
+        .zip(
+            logprobs
+                .into_iter()
+                .zip(proposed_points.into_iter().zip(in_supp)),
+        )
         .enumerate()
     {
-        for ((&(i1, i2), &up_prop1), (yp1, phi, k, b)) in pair_id1
+        for (((&(i1, i2), &up_prop1), (yp1, phi, k, b)), s) in pair_id1
             .iter()
             .zip(logprobs1.iter())
-            .zip(proposed_points1.into_iter())
+            .zip(proposed_points1)
+            .zip(in_supp1)
         {
-            let a1 = calc_a(
-                &ensemble_logprob.0[i2],
-                (&ensemble_logprob.0[i1], ensemble_logprob.1[i1]),
-                (&yp1, up_prop1),
-                &phi,
-                k,
-                b,
-                beta_list[ibeta],
-            );
+            let a1 = if s {
+                calc_a(
+                    &ensemble_logprob.0[i2],
+                    (&ensemble_logprob.0[i1], ensemble_logprob.1[i1]),
+                    (&yp1, up_prop1),
+                    &phi,
+                    k,
+                    b,
+                    beta_list[ibeta],
+                )
+            } else {
+                T::zero()
+            };
 
-            if rng.sample(Uniform::new(T::zero(), T::one())) < a1 {
+            let accepted = rng.sample(Uniform::new(T::zero(), T::one())) < a1;
+            if accepted {
                 ensemble_logprob.0[ibeta * nwalkers_per_beta + i1] = yp1;
                 ensemble_logprob.1[ibeta * nwalkers_per_beta + i1] = up_prop1;
             }
+            stat.record(k, accepted);
+        }
+    }
+    stat
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_space::type_wrapper::LsVec;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    type Vf = LsVec<f64, Vec<f64>>;
+
+    fn positive(x: &Vf) -> bool {
+        x.0.iter().all(|&x| x > 0.0)
+    }
+
+    #[test]
+    fn supp_test() {
+        let mut rng = StdRng::seed_from_u64(12345);
+        let nevals = Cell::new(0);
+        //standard normal truncated to the positive quadrant
+        let flogprob = |x: &Vf| {
+            assert!(positive(x), "{:?}", x.0);
+            nevals.set(nevals.get() + 1);
+            -x.0.iter().map(|x| x * x).sum::<f64>() / 2.0
+        };
+        let nrejected = Arc::new(AtomicUsize::new(0));
+        let nrejected1 = nrejected.clone();
+        let param = TWalkParams::new(2).with_supp(move |x: &Vf| {
+            let s = positive(x);
+            if !s {
+                nrejected1.fetch_add(1, Ordering::Relaxed);
+            }
+            s
+        });
+        let mut state = TWalkState::new(&LsVec(vec![1.0, 1.0]), &LsVec(vec![0.5, 2.0]), &flogprob);
+        assert_eq!(nevals.get(), 2);
+
+        let niter = 20000;
+        let mut stat = TWalkStat::new();
+        for _ in 0..niter {
+            stat += sample_st(&flogprob, &mut state, &param, &mut rng);
+            assert!(positive(&state.x) && positive(&state.xp));
+        }
+
+        let nrejected = nrejected.load(Ordering::Relaxed);
+        assert!(nrejected > 0);
+        assert_eq!(nevals.get() - 2 + nrejected, 2 * niter);
+        let nproposed: usize = stat.proposed.iter().sum();
+        assert_eq!(nproposed, 2 * niter);
+        assert!(stat.total_accept_ratio().unwrap() > 0.0);
+    }
+
+    #[test]
+    fn stat_test() {
+        let mut rng = StdRng::seed_from_u64(12345);
+        let nevals = AtomicUsize::new(0);
+        let flogprob = |x: &Vf| {
+            nevals.fetch_add(1, Ordering::Relaxed);
+            -x.0.iter().map(|x| x * x).sum::<f64>() / 2.0
+        };
+        let param = TWalkParams::new(2).with_fw([0.3, 0.7]);
+        let nwalkers = 16;
+        let ensemble: Vec<Vf> = (0..nwalkers)
+            .map(|i| LsVec(vec![i as f64 / 8.0 - 1.0, 1.0 - i as f64 / 8.0]))
+            .collect();
+        let logprob: Vec<f64> = ensemble.iter().map(&flogprob).collect();
+        let mut ensemble_logprob = (ensemble, logprob);
+        nevals.store(0, Ordering::Relaxed);
+
+        let niter = 1000;
+        let mut stat = TWalkStat::new();
+        for _ in 0..niter {
+            stat += sample(
+                &flogprob,
+                &mut ensemble_logprob,
+                &param,
+                &mut rng,
+                &[1.0],
+                2,
+            );
+        }
+        let nproposed: usize = stat.proposed.iter().sum();
+        assert_eq!(nproposed, niter * nwalkers);
+        assert_eq!(nevals.load(Ordering::Relaxed), niter * nwalkers);
+        assert_eq!(stat.num_proposed(TWalkKernal::Blow), 0);
+        assert_eq!(stat.num_proposed(TWalkKernal::Hop), 0);
+        for k in [TWalkKernal::Walk, TWalkKernal::Traverse] {
+            assert!(stat.num_accepted(k) <= stat.num_proposed(k));
+            assert!(stat.accept_ratio(k).unwrap() > 0.0);
+        }
+        //the kernels are chosen with probabilities 0.3 and 0.7
+        let fw = stat.num_proposed(TWalkKernal::Walk) as f64 / nproposed as f64;
+        assert!((fw - 0.3).abs() < 0.02, "{}", fw);
+        for (x, &lp) in ensemble_logprob.0.iter().zip(ensemble_logprob.1.iter()) {
+            assert_eq!(flogprob(x), lp);
         }
     }
 }
@@ -19,6 +19,7 @@ where
     (x + one()).ln_gamma().0
 }
 
+#[allow(clippy::manual_is_multiple_of)]
 pub fn coefficient<T>(n: usize, k: usize) -> T
 where
     T: Float + Copy + Gamma,
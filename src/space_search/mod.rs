@@ -35,11 +35,11 @@ where
 
 impl<T> Eq for IdxDistPair<T> where T: PartialOrd {}
 
+#[allow(clippy::non_canonical_partial_ord_impl)]
 impl<T> PartialOrd for IdxDistPair<T>
 where
     T: PartialOrd,
 {
-    #[allow(clippy::non_canonical_partial_ord_impl)]
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         self.dist.partial_cmp(&(other.dist))
     }
@@ -103,6 +103,7 @@ where
         };
         let p0 = self.items[lower];
         if upper - lower > 1 {
+            #[allow(clippy::manual_div_ceil)]
             let median = (upper + lower + 1) / 2;
 
             //select_by(&mut self.items[lower+1], median)